@@ -0,0 +1,371 @@
+use std::collections::{HashMap, HashSet};
+use std::sync::atomic::{AtomicI32, AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex as StdMutex, RwLock};
+use std::time::Duration;
+
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::tcp::{OwnedReadHalf, OwnedWriteHalf};
+use tokio::net::TcpStream;
+use tokio::sync::{oneshot, Mutex};
+use tokio::time::timeout;
+
+use crate::error::{KafkaError, Result};
+use crate::protocol::api_versions::{negotiate_version, ApiVersion, ApiVersionsRequest, ApiVersionsResponse, API_VERSIONS_VERSION};
+use crate::protocol::header::RequestHeader;
+use crate::protocol::metadata::{MetadataRequest, MetadataResponse};
+use crate::protocol::request::{KafkaRequest, Request};
+use crate::protocol::response::{frame_correlation_id, Response};
+
+const REQUEST_TIMEOUT: Duration = Duration::from_secs(30);
+
+type PendingResponses = Arc<StdMutex<Pending>>;
+
+
+/// The callers waiting for a response on a connection, by correlation id.
+#[derive(Debug, Default)]
+struct Pending {
+    senders: HashMap<i32, oneshot::Sender<Result<Vec<u8>>>>,
+    /// Requests whose caller stopped waiting, whose late response is dropped when it arrives.
+    timed_out: HashSet<i32>,
+    /// Set by the reader task once the socket failed, after which no caller may wait.
+    closed: bool,
+}
+
+
+/// A socket to a single broker shared by every task sending to it.
+///
+/// Requests are written under a lock while a background task reads the responses
+/// and hands each one to the caller waiting on its correlation id.
+#[derive(Debug)]
+struct AsyncBrokerConnection {
+    writer: Mutex<OwnedWriteHalf>,
+    pending: PendingResponses,
+}
+
+impl AsyncBrokerConnection {
+    async fn connect(host: &str) -> Result<Arc<Self>> {
+        let stream = TcpStream::connect(host).await
+            .map_err(|e| KafkaError::Connection(format!("Could not connect to {}: {}", host, e)))?;
+        stream.set_nodelay(true)?;
+        let (reader, writer) = stream.into_split();
+        let connection = Arc::new(Self {
+            writer: Mutex::new(writer),
+            pending: Arc::new(StdMutex::new(Pending::default())),
+        });
+        tokio::spawn(Self::read_responses(reader, connection.pending.clone(), host.to_string()));
+        Ok(connection)
+    }
+
+    fn is_closed(&self) -> bool {
+        self.pending.lock().unwrap().closed
+    }
+
+    async fn read_responses(mut reader: OwnedReadHalf, pending: PendingResponses, host: String) {
+        let reason = loop {
+            let frame = match Self::read_frame(&mut reader).await {
+                Ok(frame) => frame,
+                Err(e) => break e.to_string(),
+            };
+            let correlation_id = match frame_correlation_id(&frame) {
+                Ok(correlation_id) => correlation_id,
+                Err(e) => break e.to_string(),
+            };
+            let mut pending = pending.lock().unwrap();
+            match pending.senders.remove(&correlation_id) {
+                Some(sender) => {
+                    let _ = sender.send(Ok(frame));
+                }
+                None if pending.timed_out.remove(&correlation_id) => {}
+                None => break format!("Received a response with unknown correlation id {}", correlation_id),
+            }
+        };
+        let mut pending = pending.lock().unwrap();
+        pending.closed = true;
+        for (_, sender) in pending.senders.drain() {
+            let _ = sender.send(Err(KafkaError::Connection(format!("Connection to {} closed: {}", host, reason))));
+        }
+    }
+
+    async fn read_frame(reader: &mut OwnedReadHalf) -> Result<Vec<u8>> {
+        let message_size = reader.read_i32().await?;
+        if message_size < 4 {
+            return Err(KafkaError::Codec(format!("invalid message size {}", message_size)));
+        }
+        let mut buffer = vec![0_u8; message_size as usize];
+        reader.read_exact(&mut buffer).await?;
+        Ok(buffer)
+    }
+
+    async fn send(&self, correlation_id: i32, buffer: &[u8], request_timeout: Duration) -> Result<Vec<u8>> {
+        let (sender, receiver) = oneshot::channel();
+        {
+            // Checked under the same lock the reader task closes the connection with, so that
+            // a sender is either registered before the final drain or never registered at all.
+            let mut pending = self.pending.lock().unwrap();
+            if pending.closed {
+                return Err(KafkaError::Connection("Connection is closed".to_string()));
+            }
+            pending.senders.insert(correlation_id, sender);
+        }
+        let written = self.writer.lock().await.write_all(buffer).await;
+        if let Err(e) = written {
+            self.pending.lock().unwrap().senders.remove(&correlation_id);
+            return Err(e.into());
+        }
+        match timeout(request_timeout, receiver).await {
+            Ok(Ok(frame)) => frame,
+            Ok(Err(_)) => Err(KafkaError::Connection("Connection closed before the response arrived".to_string())),
+            Err(_) => {
+                // The broker may still answer, which must not be mistaken for a broken connection.
+                let mut pending = self.pending.lock().unwrap();
+                if pending.senders.remove(&correlation_id).is_some() {
+                    pending.timed_out.insert(correlation_id);
+                }
+                Err(KafkaError::Timeout)
+            }
+        }
+    }
+
+    /// Writes a request the broker does not answer, without waiting on a correlation id.
+    async fn send_without_response(&self, buffer: &[u8]) -> Result<()> {
+        if self.is_closed() {
+            return Err(KafkaError::Connection("Connection is closed".to_string()));
+        }
+        self.writer.lock().await.write_all(buffer).await?;
+        Ok(())
+    }
+}
+
+/// Non-blocking counterpart of `KafkaClient`, available with the `tokio` feature.
+///
+/// It encodes and decodes messages with the same `ToBytes`/`FromBytes` codecs and keeps
+/// one connection per broker, on which any number of tasks can have requests in flight.
+#[derive(Debug)]
+pub struct AsyncKafkaClient {
+    client_id: String,
+    pub api_versions: HashMap<i16, ApiVersion>,
+    brokers: RwLock<HashMap<i32, String>>,
+    connections: Mutex<HashMap<i32, Arc<AsyncBrokerConnection>>>,
+    next_broker: AtomicUsize,
+    correlation_id: AtomicI32,
+    pub request_timeout: Duration,
+}
+
+impl AsyncKafkaClient {
+    pub async fn new(hosts: &[&str], client_id: String) -> Result<Self> {
+        let mut kafka_client = Self {
+            client_id,
+            api_versions: HashMap::new(),
+            brokers: RwLock::new(HashMap::new()),
+            connections: Mutex::new(HashMap::new()),
+            next_broker: AtomicUsize::new(0),
+            correlation_id: AtomicI32::new(1),
+            request_timeout: REQUEST_TIMEOUT,
+        };
+        kafka_client.connect(hosts).await?;
+        let node_id = kafka_client.next_broker()?;
+        let response: Response<ApiVersionsResponse> = kafka_client.send_with_version(
+            node_id, ApiVersionsRequest::new(), API_VERSIONS_VERSION).await?;
+        kafka_client.api_versions = response.body.into_map()?;
+        Ok(kafka_client)
+    }
+
+    pub fn correlation_id(&self) -> i32 {
+        self.correlation_id.fetch_add(1, Ordering::Relaxed) + 1
+    }
+
+    pub fn brokers(&self) -> HashMap<i32, String> {
+        self.brokers.read().unwrap().clone()
+    }
+
+    pub fn next_broker(&self) -> Result<i32> {
+        let mut node_ids: Vec<i32> = self.brokers.read().unwrap().keys().cloned().collect();
+        if node_ids.is_empty() {
+            return Err(KafkaError::Connection("No brokers are known to the client".to_string()));
+        }
+        node_ids.sort_unstable();
+        Ok(node_ids[self.next_broker.fetch_add(1, Ordering::Relaxed) % node_ids.len()])
+    }
+
+    /// Sends a request in the highest version supported by both this client and the brokers.
+    pub async fn send_request<T: KafkaRequest>(&self, node_id: Option<i32>, request_body: T) -> Result<Response<T::Response>> {
+        let api_version = negotiate_version::<T>(&self.api_versions)?;
+        let node_id = match node_id {
+            Some(node_id) => node_id,
+            None => self.next_broker()?,
+        };
+        self.send_with_version(node_id, request_body, api_version).await
+    }
+
+    /// Writes a request the broker does not answer, such as a produce request with no acks.
+    pub async fn send_without_response<T: KafkaRequest>(&self, node_id: Option<i32>, request_body: T) -> Result<()> {
+        let api_version = negotiate_version::<T>(&self.api_versions)?;
+        let node_id = match node_id {
+            Some(node_id) => node_id,
+            None => self.next_broker()?,
+        };
+        let header = RequestHeader::new(T::API_KEY as i16, api_version, self.correlation_id(), self.client_id.clone());
+        let request = Request::new(header, request_body);
+        self.connection(node_id).await?.send_without_response(&request.buffer).await
+    }
+
+    async fn send_with_version<T: KafkaRequest>(&self,
+                                                node_id: i32,
+                                                request_body: T,
+                                                api_version: i16) -> Result<Response<T::Response>> {
+        let correlation_id = self.correlation_id();
+        let header = RequestHeader::new(T::API_KEY as i16, api_version, correlation_id, self.client_id.clone());
+        let request = Request::new(header, request_body);
+        let connection = self.connection(node_id).await?;
+        let frame = connection.send(correlation_id, &request.buffer, self.request_timeout).await?;
+        let response = Response::<T::Response>::from_frame(
+            frame, T::response_header_version(api_version), api_version)?;
+        if response.header.correlation_id != correlation_id {
+            return Err(KafkaError::Protocol(format!(
+                "Expected a response with correlation id {} but received {}",
+                correlation_id, response.header.correlation_id)));
+        }
+        Ok(response)
+    }
+
+    async fn connection(&self, node_id: i32) -> Result<Arc<AsyncBrokerConnection>> {
+        let mut connections = self.connections.lock().await;
+        if let Some(connection) = connections.get(&node_id) {
+            if !connection.is_closed() {
+                return Ok(connection.clone());
+            }
+        }
+        let host = self.brokers.read().unwrap().get(&node_id).cloned()
+            .ok_or_else(|| KafkaError::Connection(format!("Broker {} is not known to the client", node_id)))?;
+        let connection = AsyncBrokerConnection::connect(&host).await?;
+        connections.insert(node_id, connection.clone());
+        Ok(connection)
+    }
+
+    async fn connect(&self, hosts: &[&str]) -> Result<()> {
+        let mut errors = Vec::new();
+        for (i, host) in hosts.iter().enumerate() {
+            let bootstrap_id = -(i as i32) - 1;
+            self.brokers.write().unwrap().insert(bootstrap_id, host.to_string());
+            let body = MetadataRequest::new(&[]);
+            let result: Result<Response<MetadataResponse>> = self.send_with_version(
+                bootstrap_id, body, MetadataRequest::MIN_VERSION).await;
+            self.brokers.write().unwrap().remove(&bootstrap_id);
+            self.connections.lock().await.remove(&bootstrap_id);
+            match result {
+                Ok(response) => {
+                    self.update_brokers(&response.body);
+                    return Ok(());
+                }
+                Err(e) => errors.push(format!("{}: {}", host, e)),
+            }
+        }
+        Err(KafkaError::Connection(format!("Could not connect to any of the hosts provided ({})", errors.join(", "))))
+    }
+
+    fn update_brokers(&self, metadata: &MetadataResponse) {
+        let mut brokers = self.brokers.write().unwrap();
+        for broker in &metadata.brokers {
+            brokers.insert(broker.node_id, format!("{}:{}", broker.host, broker.port));
+        }
+    }
+
+    pub async fn fetch_metadata(&self, topics: Vec<String>) -> Result<Response<MetadataResponse>> {
+        let body = MetadataRequest::new(&topics);
+        let response = self.send_request(None, body).await?;
+        self.update_brokers(&response.body);
+        Ok(response)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::{Read, Write};
+    use std::net::TcpListener;
+    use std::thread;
+
+    use super::*;
+
+    fn frame(correlation_id: i32) -> Vec<u8> {
+        let mut frame = 4_i32.to_be_bytes().to_vec();
+        frame.extend_from_slice(&correlation_id.to_be_bytes());
+        frame
+    }
+
+    fn read_request(stream: &mut std::net::TcpStream) {
+        let mut size = [0_u8; 4];
+        stream.read_exact(&mut size).unwrap();
+        let mut request = vec![0_u8; i32::from_be_bytes(size) as usize];
+        stream.read_exact(&mut request).unwrap();
+    }
+
+    #[test]
+    fn late_response_to_timed_out_request_keeps_connection_open() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let host = listener.local_addr().unwrap().to_string();
+        let broker = thread::spawn(move || {
+            let (mut stream, _) = listener.accept().unwrap();
+            read_request(&mut stream);
+            thread::sleep(Duration::from_millis(200));
+            stream.write_all(&frame(1)).unwrap();
+            read_request(&mut stream);
+            stream.write_all(&frame(2)).unwrap();
+        });
+
+        let runtime = tokio::runtime::Builder::new_current_thread().enable_all().build().unwrap();
+        runtime.block_on(async {
+            let connection = AsyncBrokerConnection::connect(&host).await.unwrap();
+            let first = connection.send(1, &frame(1), Duration::from_millis(50)).await;
+            assert!(matches!(first, Err(KafkaError::Timeout)));
+            tokio::time::sleep(Duration::from_millis(300)).await;
+            let second = connection.send(2, &frame(2), Duration::from_secs(5)).await.unwrap();
+            assert_eq!(second, 2_i32.to_be_bytes().to_vec());
+        });
+        broker.join().unwrap();
+    }
+
+    #[test]
+    fn request_without_response_is_not_awaited() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let host = listener.local_addr().unwrap().to_string();
+        let broker = thread::spawn(move || {
+            let (mut stream, _) = listener.accept().unwrap();
+            read_request(&mut stream);
+            read_request(&mut stream);
+            stream.write_all(&frame(2)).unwrap();
+        });
+
+        let runtime = tokio::runtime::Builder::new_current_thread().enable_all().build().unwrap();
+        runtime.block_on(async {
+            let connection = AsyncBrokerConnection::connect(&host).await.unwrap();
+            connection.send_without_response(&frame(1)).await.unwrap();
+            assert!(connection.pending.lock().unwrap().senders.is_empty());
+            let second = connection.send(2, &frame(2), Duration::from_secs(5)).await.unwrap();
+            assert_eq!(second, 2_i32.to_be_bytes().to_vec());
+            assert!(connection.pending.lock().unwrap().timed_out.is_empty());
+        });
+        broker.join().unwrap();
+    }
+
+    #[test]
+    fn send_fails_once_broker_closed_connection() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let host = listener.local_addr().unwrap().to_string();
+        let broker = thread::spawn(move || {
+            let (stream, _) = listener.accept().unwrap();
+            drop(stream);
+        });
+
+        let runtime = tokio::runtime::Builder::new_current_thread().enable_all().build().unwrap();
+        runtime.block_on(async {
+            let connection = AsyncBrokerConnection::connect(&host).await.unwrap();
+            broker.join().unwrap();
+            while !connection.is_closed() {
+                tokio::time::sleep(Duration::from_millis(10)).await;
+            }
+            let result = connection.send(1, &frame(1), Duration::from_secs(5)).await;
+            assert!(matches!(result, Err(KafkaError::Connection(_))));
+            assert!(connection.pending.lock().unwrap().senders.is_empty());
+        });
+    }
+}
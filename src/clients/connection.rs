@@ -0,0 +1,270 @@
+use std::collections::{HashMap, VecDeque};
+use std::io::Write;
+use std::net::TcpStream;
+use std::time::{Duration, Instant};
+
+use crate::error::{KafkaError, Result};
+use crate::protocol::response::{frame_correlation_id, read_frame};
+
+const RECONNECT_BACKOFF: Duration = Duration::from_millis(50);
+const RECONNECT_BACKOFF_MAX: Duration = Duration::from_millis(1000);
+const CONNECTIONS_MAX_IDLE: Duration = Duration::from_secs(540);
+const REQUEST_TIMEOUT: Duration = Duration::from_secs(30);
+const MAX_IN_FLIGHT_REQUESTS_PER_CONNECTION: usize = 5;
+
+
+/// A socket to a single broker, together with the state needed to reconnect to it.
+///
+/// Several requests can be written before their responses are read. The broker answers
+/// them in the order they were sent, so `in_flight` holds their correlation ids in that
+/// order, and responses read on behalf of another caller wait in `completed`.
+#[derive(Debug)]
+pub struct BrokerConnection {
+    pub host: String,
+    stream: Option<TcpStream>,
+    last_used: Instant,
+    failed_attempts: u32,
+    next_attempt: Instant,
+    in_flight: VecDeque<i32>,
+    completed: HashMap<i32, Vec<u8>>,
+}
+
+impl BrokerConnection {
+    fn new(host: String) -> Self {
+        Self {
+            host,
+            stream: None,
+            last_used: Instant::now(),
+            failed_attempts: 0,
+            next_attempt: Instant::now(),
+            in_flight: VecDeque::new(),
+            completed: HashMap::new(),
+        }
+    }
+
+    pub fn is_connected(&self) -> bool {
+        self.stream.is_some()
+    }
+
+    pub fn in_flight_count(&self) -> usize {
+        self.in_flight.len()
+    }
+
+    fn disconnect(&mut self) {
+        self.stream = None;
+        self.in_flight.clear();
+        self.completed.clear();
+    }
+
+    /// Reads the response to the oldest request in flight and keeps it aside.
+    fn read_next(&mut self) -> Result<i32> {
+        let stream = match self.stream.as_mut() {
+            Some(stream) => stream,
+            None => return Err(KafkaError::Connection(format!("Connection to {} is closed", self.host))),
+        };
+        let frame = read_frame(stream)?;
+        let correlation_id = frame_correlation_id(&frame)?;
+        match self.in_flight.pop_front() {
+            Some(expected) if expected == correlation_id => {
+                self.completed.insert(correlation_id, frame);
+                Ok(correlation_id)
+            }
+            expected => Err(KafkaError::Protocol(format!(
+                "Expected a response with correlation id {:?} from {} but received {}",
+                expected, self.host, correlation_id))),
+        }
+    }
+
+    fn connect(&mut self, request_timeout: Duration, backoff: Duration, backoff_max: Duration) -> Result<()> {
+        let now = Instant::now();
+        if self.next_attempt > now {
+            // Callers retry connection errors, so the backoff never blocks the calling thread.
+            return Err(KafkaError::Connection(format!(
+                "Not reconnecting to {} for another {:?}", self.host, self.next_attempt - now)));
+        }
+        match TcpStream::connect(&self.host) {
+            Ok(stream) => {
+                stream.set_nodelay(true)?;
+                stream.set_read_timeout(Some(request_timeout))?;
+                stream.set_write_timeout(Some(request_timeout))?;
+                self.stream = Some(stream);
+                self.failed_attempts = 0;
+                Ok(())
+            }
+            Err(e) => {
+                let exponent = self.failed_attempts.min(16);
+                let delay = backoff.checked_mul(1 << exponent).unwrap_or(backoff_max).min(backoff_max);
+                self.failed_attempts += 1;
+                self.next_attempt = Instant::now() + delay;
+                Err(KafkaError::Connection(format!("Could not connect to {}: {}", self.host, e)))
+            }
+        }
+    }
+}
+
+/// Keeps one long-lived socket per broker, keyed by node id.
+///
+/// Sockets are opened lazily on first use, reopened with an exponential backoff
+/// after a failure and closed once they stayed unused for longer than the idle timeout.
+/// Bootstrap hosts, whose node ids are unknown, are registered under negative ids.
+#[derive(Debug)]
+pub struct ConnectionManager {
+    connections: HashMap<i32, BrokerConnection>,
+    pub reconnect_backoff: Duration,
+    pub reconnect_backoff_max: Duration,
+    pub connections_max_idle: Duration,
+    pub request_timeout: Duration,
+    pub max_in_flight_requests_per_connection: usize,
+}
+
+impl ConnectionManager {
+    pub fn new() -> Self {
+        Self {
+            connections: HashMap::new(),
+            reconnect_backoff: RECONNECT_BACKOFF,
+            reconnect_backoff_max: RECONNECT_BACKOFF_MAX,
+            connections_max_idle: CONNECTIONS_MAX_IDLE,
+            request_timeout: REQUEST_TIMEOUT,
+            max_in_flight_requests_per_connection: MAX_IN_FLIGHT_REQUESTS_PER_CONNECTION,
+        }
+    }
+
+    /// Registers the address of a broker, dropping the current socket if the address changed.
+    pub fn add_broker(&mut self, node_id: i32, host: String) {
+        match self.connections.get_mut(&node_id) {
+            Some(connection) if connection.host == host => {}
+            Some(connection) => *connection = BrokerConnection::new(host),
+            None => {
+                self.connections.insert(node_id, BrokerConnection::new(host));
+            }
+        }
+    }
+
+    pub fn remove_broker(&mut self, node_id: i32) {
+        self.connections.remove(&node_id);
+    }
+
+    /// The address registered for a broker.
+    pub fn host(&self, node_id: i32) -> Option<&str> {
+        self.connections.get(&node_id).map(|connection| connection.host.as_str())
+    }
+
+    pub fn node_ids(&self) -> Vec<i32> {
+        self.connections.keys().cloned().collect()
+    }
+
+    fn connection(&mut self, node_id: i32) -> Result<&mut BrokerConnection> {
+        self.close_idle();
+        let (request_timeout, backoff, backoff_max) =
+            (self.request_timeout, self.reconnect_backoff, self.reconnect_backoff_max);
+        let connection = self.connections.get_mut(&node_id)
+            .ok_or_else(|| KafkaError::Connection(format!("Broker {} is not known to the client", node_id)))?;
+        if !connection.is_connected() {
+            connection.connect(request_timeout, backoff, backoff_max)?;
+        }
+        connection.last_used = Instant::now();
+        Ok(connection)
+    }
+
+    /// Returns the socket to the given broker, connecting to it first if needed.
+    pub fn stream(&mut self, node_id: i32) -> Result<&mut TcpStream> {
+        let connection = self.connection(node_id)?;
+        if !connection.in_flight.is_empty() {
+            return Err(KafkaError::Protocol(format!(
+                "Connection to {} has requests in flight", connection.host)));
+        }
+        Ok(connection.stream.as_mut().unwrap())
+    }
+
+    /// Writes a request to the broker without waiting for its response.
+    ///
+    /// When the connection already holds the maximum number of requests in flight,
+    /// the oldest response is read first to make room for this one.
+    pub fn send(&mut self, node_id: i32, correlation_id: i32, buffer: &[u8], expect_response: bool) -> Result<()> {
+        let max_in_flight = self.max_in_flight_requests_per_connection.max(1);
+        let connection = self.connection(node_id)?;
+        let result = Self::write(connection, buffer, max_in_flight);
+        if result.is_err() {
+            connection.disconnect();
+        } else if expect_response {
+            connection.in_flight.push_back(correlation_id);
+        }
+        result
+    }
+
+    fn write(connection: &mut BrokerConnection, buffer: &[u8], max_in_flight: usize) -> Result<()> {
+        while connection.in_flight.len() >= max_in_flight {
+            connection.read_next()?;
+        }
+        connection.stream.as_mut().unwrap().write_all(buffer)?;
+        Ok(())
+    }
+
+    /// Returns the response frame to the request sent with the given correlation id,
+    /// reading the responses to older requests on the way.
+    pub fn receive(&mut self, node_id: i32, correlation_id: i32) -> Result<Vec<u8>> {
+        let connection = self.connections.get_mut(&node_id)
+            .ok_or_else(|| KafkaError::Connection(format!("Broker {} is not known to the client", node_id)))?;
+        connection.last_used = Instant::now();
+        loop {
+            if let Some(frame) = connection.completed.remove(&correlation_id) {
+                return Ok(frame);
+            }
+            if !connection.in_flight.contains(&correlation_id) {
+                return Err(KafkaError::Protocol(format!(
+                    "No request with correlation id {} is in flight to {}",
+                    correlation_id, connection.host)));
+            }
+            if let Err(e) = connection.read_next() {
+                connection.disconnect();
+                return Err(e);
+            }
+        }
+    }
+
+    /// Drops the socket to a broker, the next request to it opens a new one.
+    pub fn close(&mut self, node_id: i32) {
+        if let Some(connection) = self.connections.get_mut(&node_id) {
+            connection.disconnect();
+        }
+    }
+
+    /// Drops the sockets unused for `connections_max_idle`, keeping the ones with requests
+    /// in flight or responses read but not collected yet.
+    pub fn close_idle(&mut self) {
+        let max_idle = self.connections_max_idle;
+        for connection in self.connections.values_mut() {
+            if connection.is_connected() && connection.in_flight.is_empty() && connection.completed.is_empty()
+                && connection.last_used.elapsed() > max_idle {
+                connection.disconnect();
+            }
+        }
+    }
+}
+
+impl Default for ConnectionManager {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::net::TcpListener;
+
+    use super::*;
+
+    #[test]
+    fn reconnect_backoff_does_not_block() {
+        let host = TcpListener::bind("127.0.0.1:0").unwrap().local_addr().unwrap().to_string();
+        let mut connections = ConnectionManager::new();
+        connections.reconnect_backoff = Duration::from_secs(60);
+        connections.reconnect_backoff_max = Duration::from_secs(60);
+        connections.add_broker(1, host);
+
+        assert!(matches!(connections.stream(1), Err(KafkaError::Connection(_))));
+        let started = Instant::now();
+        assert!(matches!(connections.stream(1), Err(KafkaError::Connection(_))));
+        assert!(started.elapsed() < Duration::from_secs(1));
+    }
+}
@@ -1,113 +1,498 @@
-use std::collections::HashMap;
-use std::io::{Cursor, Write};
-use std::iter::Map;
-use std::net::TcpStream;
+use std::collections::{HashMap, HashSet, VecDeque};
 use std::thread;
-use std::thread::JoinHandle;
+use std::time::{Duration, Instant};
+use std::vec::IntoIter;
 
+use crate::clients::consumer_coordinator::ConsumerCoordinator;
+use crate::clients::consumer_record::ConsumerRecord;
 use crate::clients::kafka_client::KafkaClient;
-use crate::protocol::api_keys::ApiKeys;
-use crate::protocol::api_versions::{api_versions, ApiVersion, ApiVersionsRequest, ApiVersionsResponse};
-use crate::protocol::create_topic::{CreateTopicRequest, CreateTopicResponse};
-use crate::protocol::fetch::{FetchRequest, FetchResponse};
-use crate::protocol::find_coordinator::{FindCoordinatorRequest, FindCoordinatorResponse};
-use crate::protocol::header::RequestHeader;
-use crate::protocol::join_group::{JoinGroupRequest, JoinGroupResponse};
-use crate::protocol::list_offsets::{ListOffsetsRequest, ListOffsetsResponse};
-use crate::protocol::metadata::{MetadataRequest, MetadataResponse};
+use crate::clients::partition_assignor::{assignor_for, PartitionAssignor};
+use crate::config::consumer_config::{AutoOffsetReset, ConsumerConfig};
+use crate::error::{KafkaError, Result};
+use crate::protocol::fetch::{AbortedTransactions, FetchRequest, FetchResponse};
+use crate::protocol::find_coordinator::CoordinatorType;
+use crate::protocol::kafka_error_codes::{check_errors, ErrorCode};
+use crate::protocol::list_offsets::{ListOffsetsRequest, EARLIEST_TIMESTAMP, LATEST_TIMESTAMP};
 use crate::protocol::offset_commit::{CommitOffsetRequest, CommitOffsetResponse};
-use crate::protocol::primitives::{KafkaString, KafkaPrimitive};
-use crate::protocol::request::Request;
-use crate::protocol::produce::{ProduceResponse, ProduceRequest};
+use crate::protocol::offset_fetch::OffsetFetchRequest;
+use crate::protocol::record::RecordBatch;
 use crate::protocol::response::Response;
 
-const CLIENT_ID: &str = "consumer-client";
-
-
 
+/// Fetches records from the partitions of the subscribed topics, keeping the position of
+/// the next record to return from each partition.
+///
+/// With a `group_id`, the consumer joins the group and fetches only the partitions assigned
+/// to it, otherwise every partition of the subscribed topics. A partition without a position
+/// starts from the offset committed by the group, or else from where `auto_offset_reset` points to. The records fetched beyond `max_poll_records`
+/// stay buffered for the next `poll`, their partitions not being fetched again meanwhile.
 #[derive(Debug)]
 pub struct ConsumerClient {
     pub kafka_client: KafkaClient,
+    config: ConsumerConfig,
+    subscription: Vec<String>,
+    /// The offset of the next record to return from each topic-partition.
+    positions: HashMap<(String, i32), i64>,
+    /// The records fetched but not returned yet, one entry per partition fetched.
+    completed_fetches: VecDeque<CompletedFetch>,
+    /// Set when a fetch failed because the leader of a partition may have moved.
+    metadata_stale: bool,
+    /// The membership in the group, when the consumer has a `group_id`.
+    coordinator: Option<ConsumerCoordinator>,
 }
 
 impl ConsumerClient {
-    pub fn new(hosts: Vec<&str>) -> Self {
-        Self {
-            kafka_client: KafkaClient::new(&hosts, CLIENT_ID.to_string()),
-        }
+    pub fn new(config: ConsumerConfig) -> Result<Self> {
+        let assignors = config.partition_assignment_strategy.iter().map(|&strategy| assignor_for(strategy)).collect();
+        Self::with_assignors(config, assignors)
+    }
+
+    /// Creates a consumer offering the given assignors to its group rather than the ones of
+    /// `partition_assignment_strategy`.
+    pub fn with_assignors(config: ConsumerConfig, assignors: Vec<Box<dyn PartitionAssignor>>) -> Result<Self> {
+        let hosts: Vec<&str> = config.bootstrap_servers.iter().map(|host| host.as_str()).collect();
+        let mut kafka_client = KafkaClient::new(&hosts, config.client_id.clone())?;
+        kafka_client.connections.request_timeout = Duration::from_millis(config.request_timeout_ms.max(0) as u64);
+        let coordinator = config.group_id.clone().map(|group_id| ConsumerCoordinator::new(&config, group_id, assignors));
+        Ok(Self {
+            kafka_client,
+            config,
+            coordinator,
+            subscription: vec![],
+            positions: HashMap::new(),
+            completed_fetches: VecDeque::new(),
+            metadata_stale: false,
+        })
     }
 
-    pub fn topics_metadata_in_cache(&self, topics: &Vec<&str>) -> bool {
+    pub fn config(&self) -> &ConsumerConfig {
+        &self.config
+    }
+
+    pub fn topics_metadata_in_cache(&self, topics: &[&str]) -> bool {
         self.kafka_client.topics_metadata.keys().any(|x| topics.contains(&x.as_str()))
     }
     pub fn topic_metadata_in_cache(&self, topic: &str) -> bool {
         self.kafka_client.topics_metadata.contains_key(topic)
     }
 
-    pub fn fetch(&mut self, topics: Vec<&str>) -> Vec<Response<FetchResponse>> {
-        if !self.topics_metadata_in_cache(&topics) {
-            self.kafka_client.update_topics_metadata();
+    /// The offset of the next record to return from a partition, if it has one yet.
+    pub fn position(&self, topic: &str, partition: i32) -> Option<i64> {
+        self.positions.get(&(topic.to_string(), partition)).copied()
+    }
+
+    /// Makes the next `poll` return the records of a partition from the given offset on.
+    pub fn seek(&mut self, topic: &str, partition: i32, offset: i64) {
+        self.completed_fetches.retain(|fetch| fetch.topic != topic || fetch.partition != partition);
+        self.positions.insert((topic.to_string(), partition), offset);
+    }
+
+    /// Consumes the given topics from the next `poll` on, every partition of them or the
+    /// ones the group assigns to the consumer.
+    pub fn subscribe(&mut self, topics: &[&str]) {
+        self.subscription = topics.iter().map(|topic| topic.to_string()).collect();
+        let subscription = &self.subscription;
+        self.positions.retain(|(topic, _), _| subscription.contains(topic));
+        self.completed_fetches.retain(|fetch| subscription.contains(&fetch.topic));
+        if let Some(coordinator) = self.coordinator.as_mut() {
+            coordinator.request_rejoin();
+        }
+    }
+
+    pub fn subscription(&self) -> &[String] {
+        &self.subscription
+    }
+
+    /// The partitions assigned by the group, empty until the consumer joined it.
+    pub fn assignment(&self) -> Vec<(String, i32)> {
+        self.coordinator.as_ref().map(|coordinator| coordinator.assignment().to_vec()).unwrap_or_default()
+    }
+
+    /// Returns up to `max_poll_records` records following the positions of the subscribed
+    /// partitions, fetching them when none are buffered and waiting up to `timeout` for
+    /// some to arrive. The position of a partition moves past the records returned only.
+    pub fn poll(&mut self, timeout: Duration) -> Result<IntoIter<ConsumerRecord>> {
+        if self.subscription.is_empty() {
+            return Err(KafkaError::IllegalState("The consumer is not subscribed to any topic".to_string()));
+        }
+        let deadline = Instant::now() + timeout;
+        loop {
+            self.poll_coordinator()?;
+            let records = self.drain_buffered_records();
+            let remaining = deadline.saturating_duration_since(Instant::now());
+            if !records.is_empty() || (remaining.as_millis() == 0 && !timeout.is_zero()) {
+                return Ok(records.into_iter());
+            }
+            let max_wait_ms = remaining.as_millis().min(self.config.fetch_max_wait_ms.max(0) as u128) as i32;
+            let fetched = self.send_fetches(max_wait_ms)?;
+            if timeout.is_zero() {
+                return Ok(self.drain_buffered_records().into_iter());
+            }
+            // Nothing to fetch from yet, e.g. no partition assigned or no leader known: wait
+            // instead of asking for the metadata again right away.
+            if !fetched {
+                let remaining = deadline.saturating_duration_since(Instant::now());
+                thread::sleep(remaining.min(Duration::from_millis(self.config.retry_backoff_ms)));
+            }
+        }
+    }
+
+    /// Joins the group again once it rebalanced, dropping the positions and buffered records
+    /// of the partitions no longer assigned to the consumer.
+    fn poll_coordinator(&mut self) -> Result<()> {
+        let coordinator = match self.coordinator.as_mut() {
+            Some(coordinator) => coordinator,
+            None => return Ok(()),
+        };
+        if coordinator.ensure_active_group(&mut self.kafka_client, &self.subscription)? {
+            let assignment = coordinator.assignment();
+            self.positions.retain(|topic_partition, _| assignment.contains(topic_partition));
+            self.completed_fetches.retain(|fetch| {
+                assignment.iter().any(|(topic, partition)| topic == &fetch.topic && *partition == fetch.partition)
+            });
+        }
+        Ok(())
+    }
+
+    /// Leaves the group, so that its partitions are reassigned to the other members right away.
+    pub fn close(mut self) -> Result<()> {
+        match self.coordinator.as_mut() {
+            Some(coordinator) => coordinator.leave_group(&mut self.kafka_client),
+            None => Ok(()),
+        }
+    }
+
+    /// Takes up to `max_poll_records` records from the buffered fetches, moving the position
+    /// of their partitions past them.
+    fn drain_buffered_records(&mut self) -> Vec<ConsumerRecord> {
+        let mut records = Vec::new();
+        while records.len() < self.config.max_poll_records {
+            let fetch = match self.completed_fetches.front_mut() {
+                Some(fetch) => fetch,
+                None => break,
+            };
+            let count = fetch.records.len().min(self.config.max_poll_records - records.len());
+            records.extend(fetch.records.drain(..count));
+            let position = match records.last() {
+                _ if fetch.records.is_empty() => fetch.next_fetch_offset,
+                Some(record) => record.offset + 1,
+                None => break,
+            };
+            self.positions.insert((fetch.topic.clone(), fetch.partition), position);
+            if fetch.records.is_empty() {
+                self.completed_fetches.pop_front();
+            }
+        }
+        records
+    }
+
+    /// Fetches the assigned partitions that have no buffered records from their leaders.
+    /// The request to every leader is written before any response is read, so that the
+    /// leaders answer concurrently. Returns whether any request was sent.
+    fn send_fetches(&mut self, max_wait_ms: i32) -> Result<bool> {
+        let topics: Vec<&str> = self.subscription.iter().map(|topic| topic.as_str()).collect();
+        let metadata_missing = topics.iter().any(|topic| !self.topic_metadata_in_cache(topic));
+        if self.metadata_stale || metadata_missing {
+            self.kafka_client.update_topics_metadata()?;
+            self.metadata_stale = false;
         }
 
-        let mut responses = Vec::new();
-        for topic in topics {
-            let partitions = &self.kafka_client.topics_metadata.get(topic).unwrap().clone();
-            for (&node_id, partitions) in partitions {
-                if partitions.is_empty() {
-                    continue
+        let mut partitions = self.assigned_partitions()?;
+        partitions.retain(|(topic, partition)| {
+            !self.completed_fetches.iter().any(|fetch| &fetch.topic == topic && fetch.partition == *partition)
+        });
+        self.update_positions(&partitions)?;
+
+        let mut requests: HashMap<i32, FetchRequest> = HashMap::new();
+        for (topic, partition) in partitions.iter() {
+            let leader_id = match self.kafka_client.leader_for(topic, *partition) {
+                Some(leader_id) if leader_id >= 0 => leader_id,
+                _ => {
+                    self.metadata_stale = true;
+                    continue;
                 }
-                let mut partitions_by_topic = HashMap::new();
-                partitions_by_topic.insert(topic.to_string(), partitions);
-                let body = FetchRequest::new(partitions_by_topic);
-                let response: Response<FetchResponse> = self.kafka_client.send_request(
-                    Some(node_id),
-                    ApiKeys::Fetch,
-                    body,
-                    8
-                );
-                responses.push(response);
+            };
+            let request = requests.entry(leader_id).or_insert_with(|| {
+                FetchRequest::new(max_wait_ms, self.config.fetch_min_bytes, self.config.fetch_max_bytes,
+                                  self.config.isolation_level.as_i8())
+            });
+            let position = self.positions[&(topic.clone(), *partition)];
+            request.add_partition(topic, *partition, position, self.config.max_partition_fetch_bytes);
+        }
+
+        let mut in_flight = Vec::new();
+        for (leader_id, request) in requests {
+            match self.kafka_client.send(Some(leader_id), request) {
+                Ok(request) => in_flight.push(request),
+                Err(_) => self.metadata_stale = true,
             }
         }
-        responses
+        let fetched = !in_flight.is_empty();
+        let mut error = None;
+        for request in in_flight {
+            let result = self.kafka_client.receive(request)
+                .and_then(|response| self.buffer_fetch(response.body));
+            match result {
+                Ok(()) => {}
+                Err(e) if e.is_retriable() || matches!(e, KafkaError::Connection(_)) => self.metadata_stale = true,
+                Err(e) => error = error.or(Some(e)),
+            }
+        }
+        error.map_or(Ok(fetched), Err)
+    }
 
+    /// The partitions assigned by the group, or every partition of the subscribed topics
+    /// without a group.
+    fn assigned_partitions(&self) -> Result<Vec<(String, i32)>> {
+        if let Some(coordinator) = self.coordinator.as_ref() {
+            return Ok(coordinator.assignment().to_vec());
+        }
+        let mut partitions = Vec::new();
+        for topic in self.subscription.iter() {
+            let partition_count = self.kafka_client.partition_count(topic)
+                .ok_or_else(|| self.kafka_client.missing_topic_error(topic))?;
+            partitions.extend((0..partition_count).map(|partition| (topic.clone(), partition)));
+        }
+        Ok(partitions)
     }
 
+    /// Buffers the records returned for each partition from its position on, leaving out
+    /// control batches and, with read_committed, the batches of aborted transactions. A
+    /// partition whose position is no longer in the log is reset with `auto_offset_reset`.
+    fn buffer_fetch(&mut self, response: FetchResponse) -> Result<()> {
+        let mut out_of_range = Vec::new();
+        for topic in response.responses {
+            for partition in topic.partition_responses {
+                let topic_partition = (topic.topic.0.clone(), partition.partition);
+                match check_errors(partition.error_code) {
+                    Ok(()) => {}
+                    Err(KafkaError::Broker(ErrorCode::OffsetOutOfRange)) => {
+                        out_of_range.push(topic_partition);
+                        continue;
+                    }
+                    Err(e) if e.is_retriable() => {
+                        self.metadata_stale |= e.needs_metadata_refresh();
+                        continue;
+                    }
+                    Err(e) => return Err(e),
+                }
+                let position = match self.positions.get(&topic_partition) {
+                    Some(&position) => position,
+                    None => continue,
+                };
+                let mut fetch = CompletedFetch {
+                    topic: topic_partition.0,
+                    partition: topic_partition.1,
+                    records: VecDeque::new(),
+                    next_fetch_offset: position,
+                };
+                let mut aborted = AbortedTransactionFilter::new(partition.aborted_transactions);
+                for mut batch in partition.records {
+                    fetch.next_fetch_offset = fetch.next_fetch_offset.max(batch.last_offset() + 1);
+                    if aborted.is_aborted(&batch) || batch.is_control_batch() {
+                        continue;
+                    }
+                    for record in std::mem::take(&mut batch.records) {
+                        if record.offset >= position {
+                            fetch.records.push_back(ConsumerRecord::new(fetch.topic.clone(), fetch.partition, &batch, record));
+                        }
+                    }
+                }
+                if fetch.next_fetch_offset > position {
+                    self.completed_fetches.push_back(fetch);
+                }
+            }
+        }
+        self.reset_positions(out_of_range)
+    }
 
-    pub fn commit_offset(&mut self, topics: Vec<&str>) -> Response<CommitOffsetResponse> {
-        let body = CommitOffsetRequest::new(topics.get(0).unwrap().to_string());
-        let response: Response<CommitOffsetResponse> = self.kafka_client.send_request(
-            None, ApiKeys::OffsetCommit, body, 4);
-        response
+    /// Gives a position to the partitions without one, from the offsets committed by
+    /// the group or else from `auto_offset_reset`.
+    fn update_positions(&mut self, partitions: &[(String, i32)]) -> Result<()> {
+        let missing: Vec<(String, i32)> = partitions.iter()
+            .filter(|topic_partition| !self.positions.contains_key(*topic_partition))
+            .cloned()
+            .collect();
+        if missing.is_empty() {
+            return Ok(());
+        }
+        if self.config.group_id.is_some() {
+            let committed = self.committed(&missing)?;
+            self.positions.extend(committed);
+        }
+        let missing = missing.into_iter()
+            .filter(|topic_partition| !self.positions.contains_key(topic_partition))
+            .collect();
+        self.reset_positions(missing)
+    }
+
+    fn reset_positions(&mut self, partitions: Vec<(String, i32)>) -> Result<()> {
+        let timestamp = match (self.config.auto_offset_reset, partitions.first()) {
+            (_, None) => return Ok(()),
+            (AutoOffsetReset::Earliest, _) => EARLIEST_TIMESTAMP,
+            (AutoOffsetReset::Latest, _) => LATEST_TIMESTAMP,
+            (AutoOffsetReset::None, Some((topic, partition))) => {
+                return Err(KafkaError::IllegalState(format!(
+                    "No offset to consume {}-{} from and auto_offset_reset is none", topic, partition)));
+            }
+        };
+        let offsets = self.list_offsets(&partitions, timestamp)?;
+        self.positions.extend(offsets);
+        Ok(())
     }
 
-    pub fn list_offsets(&mut self, topics: &Vec<&str>) -> Response<ListOffsetsResponse> {
-        let body = ListOffsetsRequest::new(topics);
-        let response: Response<ListOffsetsResponse> = self.kafka_client.send_request(
-            None, ApiKeys::ListOffsets, body, 3);
-        response
+    /// The offsets committed by the group for the given partitions, leaving out the
+    /// partitions it committed no offset for.
+    pub fn committed(&mut self, partitions: &[(String, i32)]) -> Result<HashMap<(String, i32), i64>> {
+        let group_id = self.config.group_id.clone()
+            .ok_or_else(|| KafkaError::IllegalState("Committed offsets require a group_id".to_string()))?;
+        let coordinator = self.kafka_client.find_coordinator(&group_id, CoordinatorType::Group)?;
+        let mut request = OffsetFetchRequest::new(group_id);
+        for (topic, partition) in partitions.iter() {
+            request.add_partition(topic, *partition);
+        }
+        let response = self.kafka_client.send_request(Some(coordinator), request)?.body;
+        let mut committed = HashMap::new();
+        for topic in response.topics.iter() {
+            for partition in topic.partitions.iter() {
+                check_errors(partition.error_code)?;
+                if partition.committed_offset >= 0 {
+                    committed.insert((topic.name.0.clone(), partition.partition_index), partition.committed_offset);
+                }
+            }
+        }
+        Ok(committed)
     }
 
-    pub fn join_group(&mut self, group_id: String) -> Response<JoinGroupResponse> {
-        let body = JoinGroupRequest::new(group_id);
-        let response: Response<JoinGroupResponse> = self.kafka_client.send_request(
-            None, ApiKeys::JoinGroup, body, 3);
-        response
+    /// Looks up the offset of each partition for a timestamp, `EARLIEST_TIMESTAMP` or
+    /// `LATEST_TIMESTAMP`, asking the leader of the partition.
+    pub fn list_offsets(&mut self, partitions: &[(String, i32)], timestamp: i64) -> Result<HashMap<(String, i32), i64>> {
+        let mut requests: HashMap<i32, ListOffsetsRequest> = HashMap::new();
+        for (topic, partition) in partitions.iter() {
+            let leader_id = match self.kafka_client.leader_for(topic, *partition) {
+                Some(leader_id) if leader_id >= 0 => leader_id,
+                _ => {
+                    self.metadata_stale = true;
+                    return Err(KafkaError::Broker(ErrorCode::LeaderNotAvailable));
+                }
+            };
+            requests.entry(leader_id)
+                .or_insert_with(|| ListOffsetsRequest::new(self.config.isolation_level.as_i8()))
+                .add_partition(topic, *partition, timestamp);
+        }
+        let mut offsets = HashMap::new();
+        for (leader_id, request) in requests {
+            let response = self.kafka_client.send_request(Some(leader_id), request)?.body;
+            for topic in response.topics.iter() {
+                for partition in topic.partitions.iter() {
+                    check_errors(partition.error_code)?;
+                    offsets.insert((topic.name.0.clone(), partition.partition_index), partition.offset);
+                }
+            }
+        }
+        Ok(offsets)
     }
 
-    pub fn find_coordinator(&mut self) -> Response<FindCoordinatorResponse> {
-        let body = FindCoordinatorRequest::new();
-        let response: Response<FindCoordinatorResponse> = self.kafka_client.send_request(
-            None, ApiKeys::FindCoordinator, body, 2);
-        response
+    pub fn commit_offset(&mut self, topics: Vec<&str>) -> Result<Response<CommitOffsetResponse>> {
+        let topic = topics.first()
+            .ok_or_else(|| KafkaError::InvalidArgument("No topic given to commit offsets for".to_string()))?;
+        let body = CommitOffsetRequest::new(topic.to_string());
+        self.kafka_client.send_request(None, body)
     }
+}
 
-    pub fn produce(&mut self, topic: &str, key: Vec<u8>, value: Vec<u8>) -> Response<ProduceResponse> {
-        if !self.topic_metadata_in_cache(topic) {
-            self.kafka_client.update_topics_metadata();
+/// The records fetched from a partition and not returned yet.
+#[derive(Debug)]
+struct CompletedFetch {
+    topic: String,
+    partition: i32,
+    records: VecDeque<ConsumerRecord>,
+    /// The offset following the last batch fetched, where the partition is fetched from next.
+    next_fetch_offset: i64,
+}
+
+/// Finds the batches of aborted transactions among the batches fetched from a partition,
+/// from the aborted transactions the broker lists for them with read_committed.
+///
+/// A transaction is aborted from its first offset until the control batch ending it, which
+/// comes after every batch of the transaction in the log.
+#[derive(Debug)]
+struct AbortedTransactionFilter {
+    /// The aborted transactions not reached yet, the first one last.
+    upcoming: Vec<AbortedTransactions>,
+    /// The producers whose current transaction is aborted.
+    aborted_producers: HashSet<i64>,
+}
+
+impl AbortedTransactionFilter {
+    fn new(mut aborted_transactions: Vec<AbortedTransactions>) -> Self {
+        aborted_transactions.sort_by_key(|transaction| std::cmp::Reverse(transaction.first_offset));
+        Self {
+            upcoming: aborted_transactions,
+            aborted_producers: HashSet::new(),
+        }
+    }
+
+    /// Whether the batch belongs to an aborted transaction, to be called on every batch in
+    /// offset order. A control batch ends the transaction of its producer and is never aborted.
+    fn is_aborted(&mut self, batch: &RecordBatch) -> bool {
+        while let Some(transaction) = self.upcoming.last() {
+            if transaction.first_offset > batch.last_offset() {
+                break;
+            }
+            self.aborted_producers.insert(transaction.producer_id);
+            self.upcoming.pop();
+        }
+        if batch.is_control_batch() {
+            self.aborted_producers.remove(&batch.producer_id);
+            return false;
         }
-        let body = ProduceRequest::new(topic.to_string(), key, value);
-        let response: Response<ProduceResponse> = self.kafka_client.send_request(
-            None, ApiKeys::Produce, body, 8);
-        response
+        batch.is_transactional() && self.aborted_producers.contains(&batch.producer_id)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::protocol::record::Record;
+
+    fn batch(base_offset: i64, producer_id: i64, transactional: bool, control: bool) -> RecordBatch {
+        let mut batch = RecordBatch::new(vec![Record::new(None, Some(vec![0]))]);
+        batch.base_offset = base_offset;
+        batch.producer_id = producer_id;
+        batch.set_transactional(transactional);
+        if control {
+            batch.attributes |= 0x20;
+        }
+        batch
+    }
+
+    #[test]
+    fn filters_batches_of_aborted_transactions() {
+        let mut filter = AbortedTransactionFilter::new(vec![
+            AbortedTransactions { producer_id: 2, first_offset: 5 },
+            AbortedTransactions { producer_id: 1, first_offset: 1 },
+        ]);
+        let batches = [
+            (batch(0, 1, true, false), false),
+            (batch(1, 1, true, false), true),
+            (batch(2, 3, false, false), false),
+            (batch(3, 1, true, false), true),
+            (batch(4, 1, true, true), false),
+            (batch(5, 2, true, false), true),
+            (batch(6, 1, true, false), false),
+            (batch(7, 2, true, true), false),
+            (batch(8, 2, true, false), false),
+        ];
+        for (batch, aborted) in batches.iter() {
+            assert_eq!(filter.is_aborted(batch), *aborted, "batch at offset {}", batch.base_offset);
+        }
+    }
+
+    #[test]
+    fn keeps_everything_without_aborted_transactions() {
+        let mut filter = AbortedTransactionFilter::new(vec![]);
+        assert!(!filter.is_aborted(&batch(0, 1, true, false)));
+        assert!(!filter.is_aborted(&batch(1, 1, true, true)));
     }
 }
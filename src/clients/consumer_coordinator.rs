@@ -0,0 +1,411 @@
+use std::collections::HashMap;
+use std::sync::mpsc::{self, RecvTimeoutError, Sender};
+use std::sync::{Arc, Mutex, MutexGuard, PoisonError};
+use std::thread::{self, JoinHandle};
+use std::time::{Duration, Instant};
+
+use crate::clients::kafka_client::KafkaClient;
+use crate::clients::partition_assignor::{Assignment, PartitionAssignor, RebalanceProtocol, Subscription};
+use crate::config::consumer_config::ConsumerConfig;
+use crate::error::{KafkaError, Result};
+use crate::protocol::find_coordinator::CoordinatorType;
+use crate::protocol::consumer_protocol::{ConsumerProtocolAssignment, ConsumerProtocolSubscription};
+use crate::protocol::heartbeat::HeartbeatRequest;
+use crate::protocol::join_group::{JoinGroupRequest, JoinGroupResponse};
+use crate::protocol::kafka_error_codes::{check_errors, ErrorCode};
+use crate::protocol::leave_group::LeaveGroupRequest;
+use crate::protocol::sync_group::SyncGroupRequest;
+
+/// Added to the rebalance timeout to wait for the JoinGroup response, which the coordinator
+/// only sends once every member joined or the rebalance timed out.
+const JOIN_GROUP_TIMEOUT_MARGIN: Duration = Duration::from_secs(5);
+
+
+/// The generation of the group the consumer is a member of, shared with the heartbeat thread.
+#[derive(Debug)]
+struct GroupMembership {
+    member_id: String,
+    /// -1 until the consumer joined the group.
+    generation_id: i32,
+    /// Set when the group rebalances or the coordinator no longer knows the member, so that
+    /// the consumer joins again.
+    rejoin_needed: bool,
+    /// Set by the heartbeat thread when the coordinator moved or cannot be reached, so that
+    /// the consumer looks it up again.
+    coordinator_unknown: bool,
+}
+
+/// Keeps the consumer a member of its group: joins it through the group coordinator, has the
+/// leader assign the partitions of the subscribed topics, and heartbeats from a background
+/// thread so the coordinator knows the member is alive.
+///
+/// The heartbeat thread owns its own connection to the coordinator, so heartbeats keep being
+/// sent while the consumer waits for fetches. It flags a rebalance, which the consumer acts
+/// on in its next `poll`.
+///
+/// With the eager protocol the consumer gives up all its partitions before joining again. With
+/// the cooperative one it keeps them, and joins once more when the new assignment took some
+/// of them away, so that the group assigns them to their new owners.
+#[derive(Debug)]
+pub struct ConsumerCoordinator {
+    group_id: String,
+    group_instance_id: Option<String>,
+    config: ConsumerConfig,
+    coordinator: Option<i32>,
+    membership: Arc<Mutex<GroupMembership>>,
+    /// The coordinator the heartbeat thread sends to. Dropping the sender stops the thread.
+    heartbeat: Option<(i32, Sender<()>, JoinHandle<()>)>,
+    assignors: Vec<Box<dyn PartitionAssignor>>,
+    /// The topic-partitions assigned to the consumer.
+    assignment: Vec<(String, i32)>,
+    /// The generation `assignment` was received in, -1 when the consumer owns no partition.
+    assignment_generation_id: i32,
+}
+
+impl ConsumerCoordinator {
+    pub fn new(config: &ConsumerConfig, group_id: String, assignors: Vec<Box<dyn PartitionAssignor>>) -> Self {
+        Self {
+            group_id,
+            group_instance_id: config.group_instance_id.clone(),
+            config: config.clone(),
+            coordinator: None,
+            membership: Arc::new(Mutex::new(GroupMembership {
+                member_id: String::new(),
+                generation_id: -1,
+                rejoin_needed: true,
+                coordinator_unknown: false,
+            })),
+            heartbeat: None,
+            assignors,
+            assignment: vec![],
+            assignment_generation_id: -1,
+        }
+    }
+
+    pub fn group_id(&self) -> &str {
+        &self.group_id
+    }
+
+    pub fn assignment(&self) -> &[(String, i32)] {
+        &self.assignment
+    }
+
+    pub fn generation_id(&self) -> i32 {
+        self.membership().generation_id
+    }
+
+    pub fn member_id(&self) -> String {
+        self.membership().member_id.clone()
+    }
+
+    /// Makes the consumer join the group again on its next `poll`, e.g. after its subscription changed.
+    pub fn request_rejoin(&mut self) {
+        self.membership().rejoin_needed = true;
+    }
+
+    pub fn rejoin_needed(&self) -> bool {
+        self.membership().rejoin_needed
+    }
+
+    /// Cooperative when every assignor supports it, eager otherwise.
+    pub fn rebalance_protocol(&self) -> RebalanceProtocol {
+        let cooperative = self.assignors.iter()
+            .all(|assignor| assignor.supported_protocols().contains(&RebalanceProtocol::Cooperative));
+        if cooperative && !self.assignors.is_empty() {
+            RebalanceProtocol::Cooperative
+        } else {
+            RebalanceProtocol::Eager
+        }
+    }
+
+    /// Joins the group when the consumer is not a member of its current generation, returning
+    /// whether the assignment may have changed.
+    pub fn ensure_active_group(&mut self, client: &mut KafkaClient, subscription: &[String]) -> Result<bool> {
+        if std::mem::take(&mut self.membership().coordinator_unknown) {
+            self.coordinator = None;
+        }
+        if !self.rejoin_needed() {
+            // A coordinator that cannot be found yet is looked up again on the next `poll`.
+            match self.ensure_heartbeat_thread(client) {
+                Err(e) if e.is_retriable() || matches!(e, KafkaError::Connection(_)) => {
+                    self.membership().coordinator_unknown = true;
+                }
+                result => result?,
+            }
+            return Ok(false);
+        }
+        if self.assignors.is_empty() {
+            return Err(KafkaError::IllegalState("No partition assignor to join the group with".to_string()));
+        }
+        if self.rebalance_protocol() == RebalanceProtocol::Eager || self.generation_id() < 0 {
+            self.revoke_assignment();
+        }
+        self.join_group(client, subscription)?;
+        self.ensure_heartbeat_thread(client)?;
+        Ok(true)
+    }
+
+    fn revoke_assignment(&mut self) {
+        self.assignment.clear();
+        self.assignment_generation_id = -1;
+    }
+
+    fn membership(&self) -> MutexGuard<'_, GroupMembership> {
+        self.membership.lock().unwrap_or_else(PoisonError::into_inner)
+    }
+
+    fn ensure_coordinator(&mut self, client: &mut KafkaClient) -> Result<i32> {
+        match self.coordinator {
+            Some(coordinator) => Ok(coordinator),
+            None => {
+                let coordinator = client.find_coordinator(&self.group_id, CoordinatorType::Group)?;
+                self.coordinator = Some(coordinator);
+                Ok(coordinator)
+            }
+        }
+    }
+
+    /// Joins the group and syncs it, until the consumer is a member of a stable generation
+    /// or `max_poll_interval_ms` passed.
+    ///
+    /// A new member joins first with an empty member id, which the coordinator answers with
+    /// MEMBER_ID_REQUIRED and the member id to join again with.
+    fn join_group(&mut self, client: &mut KafkaClient, subscription: &[String]) -> Result<()> {
+        let rebalance_timeout = Duration::from_millis(self.config.max_poll_interval_ms.max(0) as u64);
+        let deadline = Instant::now() + rebalance_timeout;
+        loop {
+            let result = self.ensure_coordinator(client)
+                .and_then(|coordinator| self.send_join_group(client, coordinator, subscription))
+                .and_then(|response| self.sync_group(client, response));
+            match result {
+                Ok(()) => return Ok(()),
+                Err(KafkaError::Broker(ErrorCode::MemberIdRequired)) | Err(KafkaError::Broker(ErrorCode::RebalanceInProgress)) => {}
+                Err(KafkaError::Broker(ErrorCode::UnknownMemberId)) | Err(KafkaError::Broker(ErrorCode::IllegalGeneration)) => {
+                    {
+                        let mut membership = self.membership();
+                        membership.member_id.clear();
+                        membership.generation_id = -1;
+                    }
+                    self.revoke_assignment();
+                }
+                Err(e) if e.is_retriable() || matches!(e, KafkaError::Connection(_)) => {
+                    if matches!(e, KafkaError::Broker(ErrorCode::NotCoordinator)
+                        | KafkaError::Broker(ErrorCode::CoordinatorNotAvailable)
+                        | KafkaError::Io(_) | KafkaError::Connection(_)) {
+                        self.coordinator = None;
+                    }
+                    thread::sleep(Duration::from_millis(self.config.retry_backoff_ms));
+                }
+                Err(e) => return Err(e),
+            }
+            if Instant::now() >= deadline {
+                return Err(KafkaError::Timeout);
+            }
+        }
+    }
+
+    /// Sends JoinGroup offering every assignor, keeping the member id the coordinator gives
+    /// even when it asks the member to join again with it.
+    fn send_join_group(&mut self, client: &mut KafkaClient, coordinator: i32,
+                       subscription: &[String]) -> Result<JoinGroupResponse> {
+        let mut request = JoinGroupRequest::new(
+            self.group_id.clone(),
+            self.config.session_timeout_ms,
+            self.config.max_poll_interval_ms,
+            self.member_id(),
+            self.group_instance_id.clone(),
+        );
+        for assignor in self.assignors.iter() {
+            let metadata = ConsumerProtocolSubscription::new(
+                subscription,
+                assignor.subscription_user_data(subscription),
+                &self.assignment,
+                self.assignment_generation_id,
+            );
+            request.add_protocol(assignor.name(), metadata.get_in_bytes());
+        }
+
+        let request_timeout = client.connections.request_timeout;
+        let rebalance_timeout = Duration::from_millis(self.config.max_poll_interval_ms.max(0) as u64);
+        client.connections.request_timeout = request_timeout.max(rebalance_timeout + JOIN_GROUP_TIMEOUT_MARGIN);
+        let response = client.send_request(Some(coordinator), request);
+        client.connections.request_timeout = request_timeout;
+        let response = response?.body;
+
+        let mut membership = self.membership();
+        if !response.member_id.0.is_empty() {
+            membership.member_id = response.member_id.0.clone();
+        }
+        check_errors(response.error_code)?;
+        membership.generation_id = response.generation_id;
+        Ok(response)
+    }
+
+    /// Sends SyncGroup, with the assignment of every member when the consumer leads the
+    /// group, and keeps the partitions assigned to the consumer.
+    fn sync_group(&mut self, client: &mut KafkaClient, response: JoinGroupResponse) -> Result<()> {
+        let coordinator = self.ensure_coordinator(client)?;
+        let protocol_name = response.protocol_name.0.clone();
+        let assignor_index = self.assignors.iter().position(|assignor| assignor.name() == protocol_name)
+            .ok_or_else(|| KafkaError::IllegalState(format!("The group chose the unknown assignor {}", protocol_name)))?;
+        let member_id = response.member_id.0.clone();
+        let mut request = SyncGroupRequest::new(
+            self.group_id.clone(),
+            response.generation_id,
+            member_id.clone(),
+            self.group_instance_id.clone(),
+            protocol_name,
+        );
+        if response.leader.0 == member_id {
+            let assignor = &self.assignors[assignor_index];
+            for (member_id, assignment) in Self::assign(assignor.as_ref(), client, &response)? {
+                let assignment = ConsumerProtocolAssignment::new(&assignment.partitions, assignment.user_data);
+                request.add_assignment(&member_id, assignment.get_in_bytes());
+            }
+        }
+        let sync_response = client.send_request(Some(coordinator), request)?.body;
+        let assignment = ConsumerProtocolAssignment::from_bytes(sync_response.assignment)?;
+        let assignment = Assignment {
+            partitions: assignment.partitions(),
+            user_data: assignment.user_data.0,
+        };
+        self.assignors[assignor_index].on_assignment(&assignment, response.generation_id);
+
+        let revoked = self.assignment.iter().any(|topic_partition| !assignment.partitions.contains(topic_partition));
+        self.assignment = assignment.partitions;
+        self.assignment_generation_id = response.generation_id;
+        self.membership().rejoin_needed = revoked && self.rebalance_protocol() == RebalanceProtocol::Cooperative;
+        Ok(())
+    }
+
+    /// Decodes the subscriptions of the members and has the assignor chosen by the group
+    /// assign the partitions of the topics they subscribe to.
+    fn assign(assignor: &dyn PartitionAssignor, client: &mut KafkaClient,
+              response: &JoinGroupResponse) -> Result<HashMap<String, Assignment>> {
+        let mut subscriptions = HashMap::new();
+        for member in response.members.iter() {
+            let metadata = ConsumerProtocolSubscription::from_bytes(member.metadata.clone())?;
+            subscriptions.insert(member.member_id.0.clone(), Subscription {
+                topics: metadata.topics(),
+                user_data: metadata.user_data.0.clone(),
+                owned_partitions: metadata.owned_partitions(),
+                generation_id: metadata.generation_id,
+                group_instance_id: member.group_instance_id.0.clone(),
+            });
+        }
+        let topics: Vec<&String> = subscriptions.values().flat_map(|subscription| subscription.topics.iter()).collect();
+        if topics.iter().any(|topic| client.partition_count(topic).is_none()) {
+            client.update_topics_metadata()?;
+        }
+        let partitions_per_topic = topics.into_iter()
+            .filter_map(|topic| Some((topic.clone(), client.partition_count(topic)?)))
+            .collect();
+        Ok(assignor.assign(&partitions_per_topic, &subscriptions))
+    }
+
+    /// Leaves the group so that its partitions are reassigned right away rather than after
+    /// `session_timeout_ms`, and stops the heartbeat thread.
+    ///
+    /// A static member stays in the group, which keeps its partitions until its session expires.
+    pub fn leave_group(&mut self, client: &mut KafkaClient) -> Result<()> {
+        self.stop_heartbeat_thread();
+        let member_id = {
+            let mut membership = self.membership();
+            membership.generation_id = -1;
+            membership.rejoin_needed = true;
+            std::mem::take(&mut membership.member_id)
+        };
+        self.revoke_assignment();
+        if member_id.is_empty() || self.group_instance_id.is_some() {
+            return Ok(());
+        }
+        let coordinator = self.ensure_coordinator(client)?;
+        let request = LeaveGroupRequest::new(self.group_id.clone(), member_id, None);
+        client.send_request(Some(coordinator), request)?;
+        Ok(())
+    }
+
+    /// Starts sending heartbeats every `heartbeat_interval_ms` over a connection of its own
+    /// to the coordinator, again with a new connection when the coordinator moved.
+    fn ensure_heartbeat_thread(&mut self, client: &mut KafkaClient) -> Result<()> {
+        let coordinator = self.ensure_coordinator(client)?;
+        if matches!(self.heartbeat, Some((heartbeat_coordinator, _, _)) if heartbeat_coordinator == coordinator) {
+            return Ok(());
+        }
+        self.stop_heartbeat_thread();
+        let mut client = client.for_node(coordinator)?;
+        client.connections.request_timeout = Duration::from_millis(self.config.request_timeout_ms.max(0) as u64);
+
+        let (sender, receiver) = mpsc::channel();
+        let heartbeat = Heartbeat {
+            group_id: self.group_id.clone(),
+            group_instance_id: self.group_instance_id.clone(),
+            interval: Duration::from_millis(self.config.heartbeat_interval_ms),
+            membership: self.membership.clone(),
+            coordinator,
+        };
+        let handle = thread::Builder::new()
+            .name(format!("kafka-coordinator-heartbeat-thread | {}", self.group_id))
+            .spawn(move || {
+                while let Err(RecvTimeoutError::Timeout) = receiver.recv_timeout(heartbeat.interval) {
+                    heartbeat.send(&mut client);
+                }
+            })?;
+        self.heartbeat = Some((coordinator, sender, handle));
+        Ok(())
+    }
+
+    fn stop_heartbeat_thread(&mut self) {
+        if let Some((_, sender, handle)) = self.heartbeat.take() {
+            drop(sender);
+            let _ = handle.join();
+        }
+    }
+}
+
+/// What the heartbeat thread needs to heartbeat on behalf of the consumer.
+struct Heartbeat {
+    group_id: String,
+    group_instance_id: Option<String>,
+    interval: Duration,
+    membership: Arc<Mutex<GroupMembership>>,
+    coordinator: i32,
+}
+
+impl Heartbeat {
+    /// Sends a heartbeat for the current generation, flagging a rebalance when the coordinator
+    /// answers that the group is rebalancing or no longer knows the member, and the coordinator
+    /// unknown when it moved or cannot be reached.
+    fn send(&self, client: &mut KafkaClient) {
+        let (member_id, generation_id) = {
+            let membership = self.membership.lock().unwrap_or_else(PoisonError::into_inner);
+            if membership.rejoin_needed || membership.generation_id < 0 {
+                return;
+            }
+            (membership.member_id.clone(), membership.generation_id)
+        };
+        let request = HeartbeatRequest::new(self.group_id.clone(), generation_id, member_id, self.group_instance_id.clone());
+        let result = client.send_request(Some(self.coordinator), request);
+
+        let mut membership = self.membership.lock().unwrap_or_else(PoisonError::into_inner);
+        if membership.generation_id != generation_id {
+            return;
+        }
+        match result {
+            Ok(_) => {}
+            Err(KafkaError::Broker(ErrorCode::RebalanceInProgress)) | Err(KafkaError::Broker(ErrorCode::FencedInstanceId)) => {
+                membership.rejoin_needed = true;
+            }
+            Err(KafkaError::Broker(ErrorCode::UnknownMemberId)) | Err(KafkaError::Broker(ErrorCode::IllegalGeneration)) => {
+                membership.member_id.clear();
+                membership.generation_id = -1;
+                membership.rejoin_needed = true;
+            }
+            Err(KafkaError::Broker(ErrorCode::NotCoordinator)) | Err(KafkaError::Broker(ErrorCode::CoordinatorNotAvailable))
+            | Err(KafkaError::Io(_)) | Err(KafkaError::Connection(_)) => {
+                membership.coordinator_unknown = true;
+            }
+            Err(_) => {}
+        }
+    }
+}
@@ -0,0 +1,39 @@
+use crate::protocol::record::{Record, RecordBatch, RecordHeader, TimestampType};
+
+
+/// A record fetched from a topic-partition.
+#[derive(Debug, Clone)]
+pub struct ConsumerRecord {
+    pub topic: String,
+    pub partition: i32,
+    pub offset: i64,
+    pub timestamp: i64,
+    pub timestamp_type: TimestampType,
+    pub key: Option<Vec<u8>>,
+    pub value: Option<Vec<u8>>,
+    pub headers: Vec<RecordHeader>,
+    /// The epoch of the leader that appended the record, unknown for the formats before v2.
+    pub leader_epoch: Option<i32>,
+}
+
+impl ConsumerRecord {
+    /// Builds a record from one decoded from a batch, whose timestamp is the log append
+    /// time of the batch when the topic uses it.
+    pub fn new(topic: String, partition: i32, batch: &RecordBatch, record: Record) -> Self {
+        let timestamp_type = batch.timestamp_type();
+        Self {
+            topic,
+            partition,
+            offset: record.offset,
+            timestamp: match timestamp_type {
+                TimestampType::LogAppendTime => batch.max_timestamp,
+                _ => record.timestamp,
+            },
+            timestamp_type,
+            key: record.key,
+            value: record.value,
+            headers: record.headers,
+            leader_epoch: if batch.partition_leader_epoch >= 0 { Some(batch.partition_leader_epoch) } else { None },
+        }
+    }
+}
@@ -0,0 +1,71 @@
+use std::fmt;
+use std::sync::mpsc::{channel, Receiver, TryRecvError};
+
+use crate::error::{KafkaError, Result};
+
+
+/// Where a record was written, as acknowledged by the partition leader.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RecordMetadata {
+    pub topic: String,
+    pub partition: i32,
+    /// The offset of the record in the partition, -1 when sent without acks.
+    pub offset: i64,
+    /// The log append time when the topic uses it, otherwise the timestamp of the record.
+    pub timestamp: i64,
+}
+
+/// Called once with the outcome of sending a record.
+pub struct DeliveryCallback(Box<dyn FnOnce(Result<RecordMetadata>) + Send>);
+
+impl DeliveryCallback {
+    pub fn new(callback: impl FnOnce(Result<RecordMetadata>) + Send + 'static) -> Self {
+        Self(Box::new(callback))
+    }
+
+    pub fn complete(self, result: Result<RecordMetadata>) {
+        (self.0)(result)
+    }
+}
+
+impl fmt::Debug for DeliveryCallback {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("DeliveryCallback")
+    }
+}
+
+/// The outcome of sending a record, available once the producer sent its batch
+/// and read the answer of the broker during a `send`, `poll` or `flush` call.
+#[derive(Debug)]
+pub struct DeliveryFuture {
+    receiver: Receiver<Result<RecordMetadata>>,
+}
+
+impl DeliveryFuture {
+    pub(crate) fn new() -> (Self, DeliveryCallback) {
+        let (sender, receiver) = channel();
+        let callback = DeliveryCallback::new(move |result| {
+            let _ = sender.send(result);
+        });
+        (Self { receiver }, callback)
+    }
+
+    /// Returns the outcome if it is already known, without blocking.
+    pub fn try_wait(&self) -> Option<Result<RecordMetadata>> {
+        match self.receiver.try_recv() {
+            Ok(result) => Some(result),
+            Err(TryRecvError::Empty) => None,
+            Err(TryRecvError::Disconnected) => Some(Err(Self::dropped())),
+        }
+    }
+
+    /// Blocks until the outcome is known. The producer has to be driven by another thread
+    /// in the meantime, otherwise call `flush` on it first.
+    pub fn wait(self) -> Result<RecordMetadata> {
+        self.receiver.recv().unwrap_or_else(|_| Err(Self::dropped()))
+    }
+
+    fn dropped() -> KafkaError {
+        KafkaError::Connection("The producer was closed before the record was delivered".to_string())
+    }
+}
@@ -1,17 +1,18 @@
 use std::collections::HashMap;
 use std::io::Write;
 use std::net::TcpStream;
-use std::process::exit;
 
-use crate::protocol::api_keys::ApiKeys;
-use crate::protocol::api_versions::{api_versions, ApiVersion};
+use crate::clients::connection::ConnectionManager;
+use crate::error::{KafkaError, Result};
+use crate::protocol::api_versions::{api_versions, negotiate_version, ApiVersion};
+use crate::protocol::find_coordinator::{CoordinatorType, FindCoordinatorRequest};
 use crate::protocol::header::RequestHeader;
+use crate::protocol::kafka_error_codes::ErrorCode;
 use crate::protocol::metadata::{MetadataRequest, MetadataResponse};
-use crate::protocol::request::{Request, ToBytes};
+use crate::protocol::request::{KafkaRequest, Request};
 use crate::protocol::response::{FromBytes, Response};
-use std::borrow::{BorrowMut, Borrow};
-use std::slice::Iter;
 use std::iter::Cycle;
+use std::marker::PhantomData;
 use std::vec::IntoIter;
 
 
@@ -21,35 +22,77 @@ pub struct PartitionMetadata {
     pub partitions: Vec<i32>,
 }
 
+/// A request written to a broker whose response has not been read yet.
+#[derive(Debug)]
+pub struct InFlightRequest<U: FromBytes> {
+    pub node_id: i32,
+    pub correlation_id: i32,
+    pub api_version: i16,
+    pub response_header_version: i16,
+    response: PhantomData<U>,
+}
+
 #[derive(Debug)]
 pub struct KafkaClient {
     client_id: String,
     pub api_versions: HashMap<i16, ApiVersion>,
     hosts: Vec<String>,
     pub brokers: HashMap<i32, String>,
+    pub connections: ConnectionManager,
     next_broker: Cycle<IntoIter<i32>>,
     pub topics_metadata: HashMap<String, HashMap<i32, Vec<i32>>>,
+    /// The error the last metadata refresh answered for each topic it could not describe.
+    pub topic_errors: HashMap<String, ErrorCode>,
     correlation_id: i32,
 }
 
 impl KafkaClient {
-    pub fn new(hosts: &Vec<&str>, client_id: String) -> Self {
+    pub fn new(hosts: &[&str], client_id: String) -> Result<Self> {
         let mut kafka_client = Self {
             client_id,
             api_versions: HashMap::new(),
             brokers: HashMap::new(),
+            connections: ConnectionManager::new(),
             hosts: hosts.iter().map(|x| x.to_string()).collect(),
             topics_metadata: HashMap::new(),
+            topic_errors: HashMap::new(),
             correlation_id: 1,
             next_broker: Vec::new().into_iter().cycle(),
         };
-        kafka_client.connect();
-        let brokers: Vec<i32> = kafka_client.brokers.keys().cloned().collect();
-        kafka_client.next_broker = brokers.into_iter().cycle();
-        let mut broker = kafka_client.tcp_stream(None);
-        kafka_client.api_versions = api_versions(&mut broker, kafka_client.correlation_id());
-        kafka_client.update_topics_metadata();
-        kafka_client
+        kafka_client.connect()?;
+        let node_id = kafka_client.next_broker()?;
+        let correlation_id = kafka_client.correlation_id();
+        let result = api_versions(kafka_client.tcp_stream(Some(node_id))?, correlation_id);
+        kafka_client.api_versions = kafka_client.check_connection(node_id, result)?;
+        kafka_client.update_topics_metadata()?;
+        Ok(kafka_client)
+    }
+
+    /// Creates a client of its own to a single broker known to this one, e.g. for a background
+    /// thread. It reuses the api versions negotiated by this client and only ever connects to
+    /// that broker, without bootstrapping or fetching metadata.
+    pub fn for_node(&self, node_id: i32) -> Result<Self> {
+        let host = self.connections.host(node_id)
+            .ok_or_else(|| KafkaError::Connection(format!("Broker {} is not known to the client", node_id)))?
+            .to_string();
+        let mut connections = ConnectionManager::new();
+        connections.reconnect_backoff = self.connections.reconnect_backoff;
+        connections.reconnect_backoff_max = self.connections.reconnect_backoff_max;
+        connections.connections_max_idle = self.connections.connections_max_idle;
+        connections.request_timeout = self.connections.request_timeout;
+        connections.max_in_flight_requests_per_connection = self.connections.max_in_flight_requests_per_connection;
+        connections.add_broker(node_id, host.clone());
+        Ok(Self {
+            client_id: self.client_id.clone(),
+            api_versions: self.api_versions.clone(),
+            hosts: vec![],
+            brokers: vec![(node_id, host)].into_iter().collect(),
+            connections,
+            next_broker: vec![node_id].into_iter().cycle(),
+            topics_metadata: HashMap::new(),
+            topic_errors: HashMap::new(),
+            correlation_id: 1,
+        })
     }
 
     pub fn correlation_id(&mut self) -> i32 {
@@ -57,45 +100,99 @@ impl KafkaClient {
         self.correlation_id
     }
 
-    pub fn next_broker(&mut self) -> &String {
-        self.brokers.get(self.next_broker.next().unwrap().borrow()).unwrap()
+    pub fn next_broker(&mut self) -> Result<i32> {
+        self.next_broker.next()
+            .ok_or_else(|| KafkaError::Connection("No brokers are known to the client".to_string()))
     }
 
-    pub fn send_request<T: ToBytes, U: FromBytes>(&mut self,
-                                                  node_id: Option<i32>,
-                                                  api_key: ApiKeys,
-                                                  request_body: T,
-                                                  api_version_tmp: i16) -> Response<U> {
-        let api_version = self.api_versions.get(&(api_key as i16)).unwrap();
-        let header = RequestHeader::new(api_version.api_key, api_version_tmp,
-                                        self.correlation_id(), self.client_id.clone());
-        let request = Request::new(header, request_body);
-        let mut stream = self.tcp_stream(node_id);
-        stream.write(request.buffer.as_slice()).unwrap();
-        let response = Response::<U>::build(&mut stream);
-        response
-    }
-
-    pub fn send_request2<T: ToBytes, U: FromBytes>(client_id: String,
-                                                   api_version: ApiVersion,
-                                                   mut stream: TcpStream,
-                                                   request_body: T,
-                                                   correlation_id: i32,
-                                                   api_version_tmp: i16) -> Response<U> {
-        let header = RequestHeader::new(api_version.api_key, api_version_tmp,
-                                        correlation_id, client_id);
+    pub fn send_request<T: KafkaRequest>(&mut self, node_id: Option<i32>, request_body: T) -> Result<Response<T::Response>> {
+        let in_flight = self.send(node_id, request_body)?;
+        self.receive(in_flight)
+    }
+
+    /// Writes a request without waiting for its response, which is read later with `receive`.
+    /// Up to `max_in_flight_requests_per_connection` requests can be pending on each broker.
+    ///
+    /// The request is sent in the highest version supported by both this client and the
+    /// brokers, as reported by their ApiVersions response.
+    pub fn send<T: KafkaRequest>(&mut self, node_id: Option<i32>, request_body: T) -> Result<InFlightRequest<T::Response>> {
+        self.write(node_id, request_body, true)
+    }
+
+    /// Writes a request the broker does not answer, such as a produce request with no acks.
+    pub fn send_without_response<T: KafkaRequest>(&mut self, node_id: Option<i32>, request_body: T) -> Result<()> {
+        self.write(node_id, request_body, false)?;
+        Ok(())
+    }
+
+    fn write<T: KafkaRequest>(&mut self,
+                              node_id: Option<i32>,
+                              request_body: T,
+                              expect_response: bool) -> Result<InFlightRequest<T::Response>> {
+        let api_version = negotiate_version::<T>(&self.api_versions)?;
+        let correlation_id = self.correlation_id();
+        let header = RequestHeader::new(T::API_KEY as i16, api_version,
+                                        correlation_id, self.client_id.clone());
         let request = Request::new(header, request_body);
-        stream.write(request.buffer.as_slice()).unwrap();
-        let response = Response::<U>::build(&mut stream);
-        response
+        let node_id = match node_id {
+            Some(node_id) => node_id,
+            None => self.next_broker()?,
+        };
+        self.connections.send(node_id, correlation_id, &request.buffer, expect_response)?;
+        Ok(InFlightRequest {
+            node_id,
+            correlation_id,
+            api_version,
+            response_header_version: T::response_header_version(api_version),
+            response: PhantomData,
+        })
+    }
+
+    pub fn receive<U: FromBytes>(&mut self, request: InFlightRequest<U>) -> Result<Response<U>> {
+        let frame = self.connections.receive(request.node_id, request.correlation_id)?;
+        let response = Response::<U>::from_frame(frame, request.response_header_version, request.api_version)?;
+        if response.header.correlation_id != request.correlation_id {
+            self.connections.close(request.node_id);
+            return Err(KafkaError::Protocol(format!(
+                "Expected a response with correlation id {} but received {}",
+                request.correlation_id, response.header.correlation_id)));
+        }
+        Ok(response)
     }
 
-    fn connect(&mut self) {
-        let mut connected_brokers = Vec::new();
-        for host in &self.hosts {
-            match TcpStream::connect(host) {
-                Ok(_) => {
-                    connected_brokers.push(host);
+    fn write_and_read<T: KafkaRequest>(stream: &mut TcpStream, request: Request<T>) -> Result<Response<T::Response>> {
+        stream.write_all(&request.buffer)?;
+        let api_version = request.header.api_version;
+        Response::build(stream, T::response_header_version(api_version), api_version)
+    }
+
+    /// Drops the socket to a broker when the exchange failed on the wire,
+    /// so that the next request to it starts on a fresh connection.
+    fn check_connection<U>(&mut self, node_id: i32, result: Result<U>) -> Result<U> {
+        if let Err(KafkaError::Io(_)) | Err(KafkaError::Truncated) | Err(KafkaError::Timeout) = result {
+            self.connections.close(node_id);
+        }
+        result
+    }
+
+    fn connect(&mut self) -> Result<()> {
+        for (i, host) in self.hosts.iter().enumerate() {
+            self.connections.add_broker(-(i as i32) - 1, host.clone());
+        }
+
+        let mut metadata = None;
+        for i in 0..self.hosts.len() {
+            let bootstrap_id = -(i as i32) - 1;
+            let correlation_id = self.correlation_id();
+            let result = match self.connections.stream(bootstrap_id) {
+                Ok(stream) => Self::fetch_initial_metadata(stream, correlation_id),
+                Err(e) => Err(e),
+            };
+            self.connections.remove_broker(bootstrap_id);
+            match result {
+                Ok(response) => {
+                    metadata = Some(response.body);
+                    break;
                 }
                 Err(e) => {
                     println!("{}", e);
@@ -103,66 +200,107 @@ impl KafkaClient {
             }
         }
 
-        if connected_brokers.is_empty() {
-            eprintln!("Could not connect to any of the hosts provided");
-            exit(1);
-        }
-
-        let metadata = Self::fetch_initial_metadata(
-            Self::tcp_stream2(connected_brokers.get(0).unwrap().to_string()).borrow_mut(), self.correlation_id()
-        ).body;
-
+        let metadata = metadata
+            .ok_or_else(|| KafkaError::Connection("Could not connect to any of the hosts provided".to_string()))?;
         println!("Client ({}) successfully connected to cluster on hosts:", self.client_id);
+        self.update_brokers(&metadata);
+        Ok(())
+    }
+
+    fn update_brokers(&mut self, metadata: &MetadataResponse) {
         for broker in &metadata.brokers {
             let host = format!("{}:{}", broker.host, broker.port);
+            self.connections.add_broker(broker.node_id, host.clone());
             self.brokers.insert(broker.node_id, host);
         }
-
+        let brokers: Vec<i32> = self.brokers.keys().cloned().collect();
+        self.next_broker = brokers.into_iter().cycle();
     }
 
-    pub fn update_topics_metadata(&mut self) {
-        let metadata = self.fetch_metadata(Vec::new()).body;
-        if metadata.topics.is_empty() {
-            panic!("None of the topics could be found in the cluster.")
-        }
+    /// Refreshes the brokers and the partitions of every topic. A topic the brokers answer
+    /// an error for is left out and its error kept in `topic_errors`, the others being
+    /// usable still.
+    pub fn update_topics_metadata(&mut self) -> Result<()> {
+        let metadata = self.fetch_metadata(Vec::new())?.body;
+        self.update_brokers(&metadata);
+        self.topics_metadata.clear();
+        self.topic_errors.clear();
         for topic in metadata.topics {
             let topic_name = topic.name;
+            if topic.error_code != 0 {
+                self.topic_errors.insert(topic_name, ErrorCode::from_code(topic.error_code));
+                continue;
+            }
             for partition in topic.partitions {
                 self.topics_metadata
                     .entry(topic_name.clone())
-                    .or_insert(HashMap::new())
+                    .or_default()
                     .entry(partition.leader_id)
-                    .or_insert(Vec::new())
+                    .or_default()
                     .push(partition.partition_index);
             }
         }
+        Ok(())
     }
 
-    pub fn fetch_metadata(&mut self, topics: Vec<String>) -> Response<MetadataResponse> {
+    /// Looks up the broker coordinating a group or a transactional id and registers its address.
+    pub fn find_coordinator(&mut self, key: &str, key_type: CoordinatorType) -> Result<i32> {
+        let request = FindCoordinatorRequest::new(key.to_string(), key_type);
+        let coordinator = self.send_request(None, request)?.body;
+        self.connections.add_broker(coordinator.node_id, format!("{}:{}", coordinator.host.0, coordinator.port));
+        Ok(coordinator.node_id)
+    }
+
+    /// Returns the broker leading a partition according to the cached metadata.
+    pub fn leader_for(&self, topic: &str, partition: i32) -> Option<i32> {
+        self.topics_metadata.get(topic)?
+            .iter()
+            .find(|(_, partitions)| partitions.contains(&partition))
+            .map(|(&leader_id, _)| leader_id)
+    }
+
+    /// The error to report for a topic missing from the cached metadata: the one the brokers
+    /// answered for it, or else UNKNOWN_TOPIC_OR_PARTITION.
+    pub fn missing_topic_error(&self, topic: &str) -> KafkaError {
+        KafkaError::Broker(self.topic_errors.get(topic).copied().unwrap_or(ErrorCode::UnknownTopicOrPartition))
+    }
+
+    pub fn partition_count(&self, topic: &str) -> Option<i32> {
+        self.topics_metadata.get(topic)
+            .map(|partitions| partitions.values().map(|partitions| partitions.len() as i32).sum())
+    }
+
+    /// Returns the partitions of a topic that currently have a leader, in ascending order.
+    pub fn available_partitions(&self, topic: &str) -> Vec<i32> {
+        let mut available: Vec<i32> = self.topics_metadata.get(topic)
+            .map(|partitions| partitions.iter()
+                .filter(|(&leader_id, _)| leader_id >= 0)
+                .flat_map(|(_, partitions)| partitions.iter().cloned())
+                .collect())
+            .unwrap_or_default();
+        available.sort_unstable();
+        available
+    }
+
+    pub fn fetch_metadata(&mut self, topics: Vec<String>) -> Result<Response<MetadataResponse>> {
         let body = MetadataRequest::new(&topics);
-        let response: Response<MetadataResponse> = self.send_request(None, ApiKeys::Metadata, body, 6);
-        response
+        self.send_request(None, body)
     }
 
-    pub fn fetch_initial_metadata(stream: &mut TcpStream, correlation_id: i32) -> Response<MetadataResponse> {
-        let body = MetadataRequest::new(Vec::new().as_ref());
-        let header = RequestHeader::new(ApiKeys::Metadata as i16, 6,
+    pub fn fetch_initial_metadata(stream: &mut TcpStream, correlation_id: i32) -> Result<Response<MetadataResponse>> {
+        let body = MetadataRequest::new(&[]);
+        let header = RequestHeader::new(MetadataRequest::API_KEY as i16, MetadataRequest::MIN_VERSION,
                                         correlation_id, "initial-metadata".to_string());
         let request = Request::new(header, body);
-        stream.write(request.buffer.as_slice()).unwrap();
-        let response = Response::<MetadataResponse>::build(stream);
-        response
+        Self::write_and_read(stream, request)
     }
 
-    pub fn tcp_stream(&mut self, host: Option<i32>) -> TcpStream {
-        if host.is_none() {
-            return TcpStream::connect(self.next_broker()).unwrap();
-        }
-        let broker = self.brokers.get(&host.unwrap()).unwrap().to_owned();
-        TcpStream::connect(broker).unwrap()
-    }
-
-    pub fn tcp_stream2(host: String) -> TcpStream {
-        TcpStream::connect(host).unwrap()
+    /// Returns the pooled socket to a broker, or to the next broker in turn when none is given.
+    pub fn tcp_stream(&mut self, host: Option<i32>) -> Result<&mut TcpStream> {
+        let node_id = match host {
+            Some(node_id) => node_id,
+            None => self.next_broker()?,
+        };
+        self.connections.stream(node_id)
     }
 }
@@ -1,3 +1,16 @@
 pub mod admin_client;
+#[cfg(feature = "tokio")]
+pub mod async_kafka_client;
+pub mod connection;
 pub mod consumer_client;
-pub mod kafka_client;
\ No newline at end of file
+pub mod consumer_coordinator;
+pub mod consumer_record;
+pub mod delivery;
+pub mod kafka_client;
+pub mod partition_assignor;
+pub mod partitioner;
+pub mod producer_client;
+pub mod producer_record;
+pub mod record_accumulator;
+pub mod sticky_assignor;
+pub mod transaction_manager;
@@ -0,0 +1,229 @@
+use std::collections::{BTreeSet, HashMap};
+use std::fmt;
+
+use crate::clients::sticky_assignor::{CooperativeStickyAssignor, StickyAssignor};
+use crate::config::consumer_config::AssignmentStrategy;
+
+
+/// How the members of a group hand their partitions over when it rebalances.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RebalanceProtocol {
+    /// Every member gives up all its partitions before joining the group again.
+    Eager,
+    /// Members keep consuming their partitions while the group rebalances and give up only
+    /// the ones moving to another member, which are assigned in a second rebalance.
+    Cooperative,
+}
+
+/// The subscription of a member of the group, as decoded by the leader.
+#[derive(Debug, Clone)]
+pub struct Subscription {
+    pub topics: Vec<String>,
+    pub user_data: Option<Vec<u8>>,
+    /// The partitions the member consumes while it rejoins, empty with the eager protocol.
+    pub owned_partitions: Vec<(String, i32)>,
+    /// The generation the owned partitions were assigned in, -1 when unknown.
+    pub generation_id: i32,
+    pub group_instance_id: Option<String>,
+}
+
+/// The partitions assigned to a member of the group.
+#[derive(Debug, Clone, Default)]
+pub struct Assignment {
+    pub partitions: Vec<(String, i32)>,
+    pub user_data: Option<Vec<u8>>,
+}
+
+/// Splits the partitions of the topics subscribed by a group among its members, on the
+/// member the coordinator elected leader.
+///
+/// Every member offers the assignors it is configured with, in order of preference, and the
+/// coordinator picks the first one all members offer. The names and the user data must match
+/// the Java client for Rust and JVM consumers to share a group.
+pub trait PartitionAssignor: fmt::Debug + Send {
+    /// The name of the assignment protocol, e.g. "range".
+    fn name(&self) -> &'static str;
+
+    fn supported_protocols(&self) -> Vec<RebalanceProtocol> {
+        vec![RebalanceProtocol::Eager]
+    }
+
+    /// The user data joined with, which the leader receives in the subscription of this member.
+    fn subscription_user_data(&self, _topics: &[String]) -> Option<Vec<u8>> {
+        None
+    }
+
+    /// Assigns the partitions of `partitions_per_topic`, the partition count of every topic
+    /// subscribed in the group, to the members keyed by member id. Every member gets an
+    /// assignment, possibly empty.
+    fn assign(&self, partitions_per_topic: &HashMap<String, i32>,
+              subscriptions: &HashMap<String, Subscription>) -> HashMap<String, Assignment>;
+
+    /// Called on every member once it received its assignment for a generation.
+    fn on_assignment(&mut self, _assignment: &Assignment, _generation_id: i32) {}
+}
+
+/// Builds the assignor of one of the standard strategies.
+pub fn assignor_for(strategy: AssignmentStrategy) -> Box<dyn PartitionAssignor> {
+    match strategy {
+        AssignmentStrategy::Range => Box::new(RangeAssignor),
+        AssignmentStrategy::RoundRobin => Box::new(RoundRobinAssignor),
+        AssignmentStrategy::Sticky => Box::new(StickyAssignor::default()),
+        AssignmentStrategy::CooperativeSticky => Box::new(CooperativeStickyAssignor::default()),
+    }
+}
+
+/// The member ids in the order the Java client sorts members: static members first by
+/// their group instance id, then the others by member id.
+pub fn sorted_members(subscriptions: &HashMap<String, Subscription>) -> Vec<&String> {
+    let mut members: Vec<&String> = subscriptions.keys().collect();
+    members.sort_by_key(|&member_id| {
+        let group_instance_id = subscriptions[member_id].group_instance_id.as_ref();
+        (group_instance_id.is_none(), group_instance_id, member_id)
+    });
+    members
+}
+
+/// Every member with an empty assignment.
+pub fn empty_assignments(subscriptions: &HashMap<String, Subscription>) -> HashMap<String, Assignment> {
+    subscriptions.keys().map(|member_id| (member_id.clone(), Assignment::default())).collect()
+}
+
+/// The partitions of the topics subscribed by any member, sorted by topic then partition.
+pub fn all_partitions_sorted(partitions_per_topic: &HashMap<String, i32>,
+                             subscriptions: &HashMap<String, Subscription>) -> Vec<(String, i32)> {
+    let topics: BTreeSet<&String> = subscriptions.values()
+        .flat_map(|subscription| subscription.topics.iter())
+        .filter(|topic| partitions_per_topic.contains_key(*topic))
+        .collect();
+    topics.into_iter()
+        .flat_map(|topic| (0..partitions_per_topic[topic]).map(move |partition| (topic.clone(), partition)))
+        .collect()
+}
+
+/// Splits each topic in ranges of consecutive partitions, one per member subscribed to it,
+/// the first members taking one more partition when the partitions do not divide evenly.
+#[derive(Debug, Default)]
+pub struct RangeAssignor;
+
+impl PartitionAssignor for RangeAssignor {
+    fn name(&self) -> &'static str {
+        "range"
+    }
+
+    fn assign(&self, partitions_per_topic: &HashMap<String, i32>,
+              subscriptions: &HashMap<String, Subscription>) -> HashMap<String, Assignment> {
+        let mut assignments = empty_assignments(subscriptions);
+        let mut topics: Vec<&String> = partitions_per_topic.keys().collect();
+        topics.sort();
+        for topic in topics {
+            let members: Vec<&String> = sorted_members(subscriptions).into_iter()
+                .filter(|&member_id| subscriptions[member_id].topics.contains(topic))
+                .collect();
+            if members.is_empty() {
+                continue;
+            }
+            let partition_count = partitions_per_topic[topic];
+            let members_count = members.len() as i32;
+            let partitions_per_member = partition_count / members_count;
+            let members_with_extra_partition = partition_count % members_count;
+            for (i, member_id) in members.into_iter().enumerate() {
+                let i = i as i32;
+                let start = partitions_per_member * i + i.min(members_with_extra_partition);
+                let length = partitions_per_member + if i < members_with_extra_partition { 1 } else { 0 };
+                let assignment = assignments.get_mut(member_id).unwrap();
+                assignment.partitions.extend((start..start + length).map(|partition| (topic.clone(), partition)));
+            }
+        }
+        assignments
+    }
+}
+
+/// Deals the partitions of all topics one by one to the members in turn, skipping the
+/// members not subscribed to the topic of the partition.
+#[derive(Debug, Default)]
+pub struct RoundRobinAssignor;
+
+impl PartitionAssignor for RoundRobinAssignor {
+    fn name(&self) -> &'static str {
+        "roundrobin"
+    }
+
+    fn assign(&self, partitions_per_topic: &HashMap<String, i32>,
+              subscriptions: &HashMap<String, Subscription>) -> HashMap<String, Assignment> {
+        let mut assignments = empty_assignments(subscriptions);
+        let members = sorted_members(subscriptions);
+        let mut next_member = 0;
+        for (topic, partition) in all_partitions_sorted(partitions_per_topic, subscriptions) {
+            while !subscriptions[members[next_member]].topics.contains(&topic) {
+                next_member = (next_member + 1) % members.len();
+            }
+            assignments.get_mut(members[next_member]).unwrap().partitions.push((topic, partition));
+            next_member = (next_member + 1) % members.len();
+        }
+        assignments
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn subscription(topics: &[&str]) -> Subscription {
+        Subscription {
+            topics: topics.iter().map(|topic| topic.to_string()).collect(),
+            user_data: None,
+            owned_partitions: vec![],
+            generation_id: -1,
+            group_instance_id: None,
+        }
+    }
+
+    fn partitions(partitions: &[(&str, i32)]) -> Vec<(String, i32)> {
+        partitions.iter().map(|&(topic, partition)| (topic.to_string(), partition)).collect()
+    }
+
+    fn two_topics_of_three_partitions() -> HashMap<String, i32> {
+        vec![("t0".to_string(), 3), ("t1".to_string(), 3)].into_iter().collect()
+    }
+
+    fn two_members() -> HashMap<String, Subscription> {
+        vec![
+            ("c0".to_string(), subscription(&["t0", "t1"])),
+            ("c1".to_string(), subscription(&["t0", "t1"])),
+        ].into_iter().collect()
+    }
+
+    #[test]
+    fn range_gives_first_members_the_extra_partitions() {
+        let assignments = RangeAssignor.assign(&two_topics_of_three_partitions(), &two_members());
+        assert_eq!(assignments["c0"].partitions, partitions(&[("t0", 0), ("t0", 1), ("t1", 0), ("t1", 1)]));
+        assert_eq!(assignments["c1"].partitions, partitions(&[("t0", 2), ("t1", 2)]));
+    }
+
+    #[test]
+    fn roundrobin_deals_partitions_in_turn() {
+        let assignments = RoundRobinAssignor.assign(&two_topics_of_three_partitions(), &two_members());
+        assert_eq!(assignments["c0"].partitions, partitions(&[("t0", 0), ("t0", 2), ("t1", 1)]));
+        assert_eq!(assignments["c1"].partitions, partitions(&[("t0", 1), ("t1", 0), ("t1", 2)]));
+    }
+
+    #[test]
+    fn roundrobin_skips_members_not_subscribed() {
+        let mut subscriptions = two_members();
+        subscriptions.insert("c2".to_string(), subscription(&["t1"]));
+        let assignments = RoundRobinAssignor.assign(&two_topics_of_three_partitions(), &subscriptions);
+        assert_eq!(assignments["c0"].partitions, partitions(&[("t0", 0), ("t0", 2), ("t1", 2)]));
+        assert_eq!(assignments["c1"].partitions, partitions(&[("t0", 1), ("t1", 0)]));
+        assert_eq!(assignments["c2"].partitions, partitions(&[("t1", 1)]));
+    }
+
+    #[test]
+    fn static_members_sort_first() {
+        let mut subscriptions = two_members();
+        subscriptions.get_mut("c1").unwrap().group_instance_id = Some("instance".to_string());
+        let assignments = RangeAssignor.assign(&two_topics_of_three_partitions(), &subscriptions);
+        assert_eq!(assignments["c1"].partitions, partitions(&[("t0", 0), ("t0", 1), ("t1", 0), ("t1", 1)]));
+        assert_eq!(assignments["c0"].partitions, partitions(&[("t0", 2), ("t1", 2)]));
+    }
+}
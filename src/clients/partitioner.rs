@@ -0,0 +1,194 @@
+use std::collections::hash_map::RandomState;
+use std::collections::HashMap;
+use std::fmt::Debug;
+use std::hash::{BuildHasher, Hasher};
+
+
+/// The partitions of a topic known from the cluster metadata.
+#[derive(Debug, Clone, Copy)]
+pub struct TopicPartitions<'a> {
+    pub partition_count: i32,
+    /// The partitions that currently have a leader.
+    pub available_partitions: &'a [i32],
+}
+
+/// Chooses the partition of the records sent without an explicit one.
+pub trait Partitioner: Debug + Send {
+    fn partition(&mut self, topic: &str, key: Option<&[u8]>, value: Option<&[u8]>, partitions: TopicPartitions) -> i32;
+
+    /// Called before a record would start a new batch on the partition chosen for it,
+    /// letting sticky partitioners move to another partition first.
+    fn on_new_batch(&mut self, _topic: &str, _partitions: TopicPartitions, _previous_partition: i32) {}
+}
+
+/// The partitioner of the Java client before 3.3: records with a key go to the murmur2
+/// hash of the key modulo the number of partitions, records without a key stick to one
+/// available partition until its batch is sent.
+#[derive(Debug, Default)]
+pub struct DefaultPartitioner {
+    sticky_partitions: StickyPartitionCache,
+}
+
+impl Partitioner for DefaultPartitioner {
+    fn partition(&mut self, topic: &str, key: Option<&[u8]>, _value: Option<&[u8]>, partitions: TopicPartitions) -> i32 {
+        match key {
+            Some(key) => to_positive(murmur2(key)) % partitions.partition_count.max(1),
+            None => self.sticky_partitions.partition(topic, partitions),
+        }
+    }
+
+    fn on_new_batch(&mut self, topic: &str, partitions: TopicPartitions, previous_partition: i32) {
+        self.sticky_partitions.next_partition(topic, partitions, previous_partition);
+    }
+}
+
+/// Sticks to one available partition until its batch is sent, whether records have a key or not.
+#[derive(Debug, Default)]
+pub struct UniformStickyPartitioner {
+    sticky_partitions: StickyPartitionCache,
+}
+
+impl Partitioner for UniformStickyPartitioner {
+    fn partition(&mut self, topic: &str, _key: Option<&[u8]>, _value: Option<&[u8]>, partitions: TopicPartitions) -> i32 {
+        self.sticky_partitions.partition(topic, partitions)
+    }
+
+    fn on_new_batch(&mut self, topic: &str, partitions: TopicPartitions, previous_partition: i32) {
+        self.sticky_partitions.next_partition(topic, partitions, previous_partition);
+    }
+}
+
+/// Spreads the records of each topic over its available partitions in turn, ignoring keys.
+#[derive(Debug, Default)]
+pub struct RoundRobinPartitioner {
+    counters: HashMap<String, i32>,
+}
+
+impl Partitioner for RoundRobinPartitioner {
+    fn partition(&mut self, topic: &str, _key: Option<&[u8]>, _value: Option<&[u8]>, partitions: TopicPartitions) -> i32 {
+        let counter = self.counters.entry(topic.to_string()).or_insert(0);
+        let next = *counter;
+        *counter = counter.wrapping_add(1);
+        if partitions.available_partitions.is_empty() {
+            to_positive(next) % partitions.partition_count.max(1)
+        } else {
+            partitions.available_partitions[to_positive(next) as usize % partitions.available_partitions.len()]
+        }
+    }
+}
+
+/// The partition each topic currently sticks to.
+#[derive(Debug, Default)]
+struct StickyPartitionCache {
+    partitions: HashMap<String, i32>,
+}
+
+impl StickyPartitionCache {
+    fn partition(&mut self, topic: &str, partitions: TopicPartitions) -> i32 {
+        match self.partitions.get(topic) {
+            Some(&partition) => partition,
+            None => self.next_partition(topic, partitions, -1),
+        }
+    }
+
+    /// Moves to a random available partition other than the previous one, unless another
+    /// record already moved the topic away from it.
+    fn next_partition(&mut self, topic: &str, partitions: TopicPartitions, previous_partition: i32) -> i32 {
+        if let Some(&partition) = self.partitions.get(topic) {
+            if partition != previous_partition {
+                return partition;
+            }
+        }
+        let available = partitions.available_partitions;
+        let partition = match available.len() {
+            0 => to_positive(random_i32()) % partitions.partition_count.max(1),
+            1 => available[0],
+            _ => loop {
+                let partition = available[to_positive(random_i32()) as usize % available.len()];
+                if partition != previous_partition {
+                    break partition;
+                }
+            },
+        };
+        self.partitions.insert(topic.to_string(), partition);
+        partition
+    }
+}
+
+/// The murmur2 hash of the Java client, so that a key lands on the same partition
+/// whichever client produced it.
+pub fn murmur2(data: &[u8]) -> i32 {
+    const SEED: u32 = 0x9747_b28c;
+    const M: u32 = 0x5bd1_e995;
+    const R: u32 = 24;
+
+    let length = data.len();
+    let mut h = SEED ^ length as u32;
+    let mut chunks = data.chunks_exact(4);
+    for chunk in &mut chunks {
+        let mut k = u32::from_le_bytes([chunk[0], chunk[1], chunk[2], chunk[3]]);
+        k = k.wrapping_mul(M);
+        k ^= k >> R;
+        k = k.wrapping_mul(M);
+        h = h.wrapping_mul(M);
+        h ^= k;
+    }
+    let tail = chunks.remainder();
+    if tail.len() >= 3 {
+        h ^= (tail[2] as u32) << 16;
+    }
+    if tail.len() >= 2 {
+        h ^= (tail[1] as u32) << 8;
+    }
+    if !tail.is_empty() {
+        h ^= tail[0] as u32;
+        h = h.wrapping_mul(M);
+    }
+
+    h ^= h >> 13;
+    h = h.wrapping_mul(M);
+    h ^= h >> 15;
+    h as i32
+}
+
+/// Clears the sign bit like the Java client does, which differs from `abs` for negative numbers.
+pub fn to_positive(number: i32) -> i32 {
+    number & 0x7fff_ffff
+}
+
+fn random_i32() -> i32 {
+    RandomState::new().build_hasher().finish() as i32
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// The hashes of the Java `Utils.murmur2`, which places keyed records on the same
+    /// partitions as the Java producer only if they match.
+    #[test]
+    fn murmur2_matches_java() {
+        let cases: &[(&[u8], i32)] = &[
+            (b"", 275_646_681),
+            (b"a", -1_563_381_124),
+            (b"21", -973_932_308),
+            (b"abc", 479_470_107),
+            (b"foobar", -790_332_482),
+            (b"a-little-bit-long-string", -985_981_536),
+            (b"a-little-bit-longer-string", -1_486_304_829),
+            (b"lkjh234lh9fiuh90y23oiuhsafujhadof229phr9h19h89h8", -58_897_971),
+        ];
+        for &(key, hash) in cases {
+            assert_eq!(murmur2(key), hash, "{:?}", String::from_utf8_lossy(key));
+        }
+    }
+
+    #[test]
+    fn to_positive_clears_the_sign_bit() {
+        assert_eq!(to_positive(0), 0);
+        assert_eq!(to_positive(42), 42);
+        assert_eq!(to_positive(-1), i32::MAX);
+        assert_eq!(to_positive(i32::MIN), 0);
+        assert_eq!(to_positive(murmur2(b"a")), 584_102_524);
+    }
+}
@@ -0,0 +1,579 @@
+use std::collections::HashMap;
+use std::thread;
+use std::time::{Duration, Instant};
+
+use crate::clients::delivery::{DeliveryCallback, DeliveryFuture};
+use crate::clients::kafka_client::{InFlightRequest, KafkaClient};
+use crate::clients::partitioner::{DefaultPartitioner, Partitioner, TopicPartitions};
+use crate::clients::producer_record::ProducerRecord;
+use crate::clients::record_accumulator::{ProducerBatch, RecordAccumulator};
+use crate::clients::transaction_manager::{TransactionManager, TransactionState};
+use crate::config::producer_config::{Acks, ProducerConfig};
+use crate::error::{KafkaError, Result};
+use crate::protocol::add_offsets_to_txn::AddOffsetsToTxnRequest;
+use crate::protocol::compression::Compression;
+use crate::protocol::add_partitions_to_txn::AddPartitionsToTxnRequest;
+use crate::protocol::end_txn::EndTxnRequest;
+use crate::protocol::find_coordinator::CoordinatorType;
+use crate::protocol::init_producer_id::InitProducerIdRequest;
+use crate::protocol::kafka_error_codes::{check_errors, ErrorCode};
+use crate::protocol::produce::{ProduceRequest, ProduceResponse};
+use crate::protocol::record::RecordBatch;
+use crate::protocol::txn_offset_commit::TxnOffsetCommitRequest;
+
+
+/// Sends records in batches, one request per partition leader.
+///
+/// Records given to `send` wait in the accumulator until their batch is full or
+/// lingered long enough. Ready batches are sent during `send`, `poll` and `flush` calls,
+/// so an application sending records in bursts should call `poll` or `flush` in between.
+///
+/// With `enable_idempotence` the producer obtains a producer id before sending its first batch
+/// and numbers the batches of each partition, so that retries never write a batch twice.
+///
+/// With a `transactional_id`, records are sent between `begin_transaction` and
+/// `commit_transaction` or `abort_transaction` after a single `init_transactions` call,
+/// and become visible to consumers reading committed records only once committed.
+#[derive(Debug)]
+pub struct ProducerClient {
+    pub kafka_client: KafkaClient,
+    config: ProducerConfig,
+    accumulator: RecordAccumulator,
+    partitioner: Box<dyn Partitioner>,
+    /// The producer id, sequence numbers and transaction state, when idempotence is enabled.
+    transaction_manager: Option<TransactionManager>,
+    /// The coordinators found for the transactional id and the groups offsets were sent for.
+    coordinators: HashMap<(CoordinatorType, String), i32>,
+    /// Set when a batch failed because the leader of its partition may have moved.
+    metadata_stale: bool,
+}
+
+impl ProducerClient {
+    pub fn new(config: ProducerConfig) -> Result<Self> {
+        Self::with_partitioner(config, Box::new(DefaultPartitioner::default()))
+    }
+
+    /// Creates a producer choosing the partition of records without one with the given partitioner.
+    pub fn with_partitioner(config: ProducerConfig, partitioner: Box<dyn Partitioner>) -> Result<Self> {
+        let idempotent = config.enable_idempotence || config.transactional_id.is_some();
+        if idempotent && config.acks != Acks::All {
+            return Err(KafkaError::InvalidArgument("Idempotence and transactions require acks to be all".to_string()));
+        }
+        // Rejects a codec whose feature is disabled rather than failing every batch later.
+        Compression::from_name(config.compression.name())?;
+        let hosts: Vec<&str> = config.bootstrap_servers.iter().map(|host| host.as_str()).collect();
+        let mut kafka_client = KafkaClient::new(&hosts, config.client_id.clone())?;
+        kafka_client.connections.request_timeout = Duration::from_millis(config.request_timeout_ms.max(0) as u64);
+        kafka_client.connections.max_in_flight_requests_per_connection = config.max_in_flight_requests_per_connection;
+        let accumulator = RecordAccumulator::new(
+            config.batch_size, Duration::from_millis(config.linger_ms), config.buffer_memory);
+        let transaction_manager = if idempotent {
+            Some(TransactionManager::new(config.transactional_id.clone()))
+        } else {
+            None
+        };
+        Ok(Self {
+            kafka_client,
+            config,
+            accumulator,
+            partitioner,
+            transaction_manager,
+            coordinators: HashMap::new(),
+            metadata_stale: false,
+        })
+    }
+
+    pub fn config(&self) -> &ProducerConfig {
+        &self.config
+    }
+
+    /// Adds a record to its partition batch, then sends the batches that are ready.
+    /// The returned future resolves once the batch of the record was acknowledged or failed.
+    ///
+    /// When the records held would exceed `buffer_memory`, every batch is sent first.
+    pub fn send(&mut self, record: ProducerRecord) -> Result<DeliveryFuture> {
+        let (future, callback) = DeliveryFuture::new();
+        self.send_with_callback(record, callback)?;
+        Ok(future)
+    }
+
+    /// Like `send`, calling the callback with the outcome instead. An error returned here
+    /// means the record was not accepted and the callback is not called.
+    pub fn send_with_callback(&mut self, record: ProducerRecord, callback: DeliveryCallback) -> Result<()> {
+        if let Some(transaction_manager) = self.transaction_manager.as_ref() {
+            transaction_manager.check_can_send()?;
+        }
+        let kafka_record = record.to_record();
+        let size_in_bytes = kafka_record.size_in_bytes();
+        if !self.accumulator.can_hold(size_in_bytes) {
+            return Err(KafkaError::Broker(ErrorCode::MessageTooLarge));
+        }
+        let partition = match record.partition {
+            Some(partition) => partition,
+            None => {
+                let partition = self.partition(&record)?;
+                if self.accumulator.starts_new_batch(&record.topic, partition, size_in_bytes) {
+                    let available_partitions = self.kafka_client.available_partitions(&record.topic);
+                    let partitions = TopicPartitions {
+                        partition_count: self.kafka_client.partition_count(&record.topic).unwrap_or(0),
+                        available_partitions: &available_partitions,
+                    };
+                    self.partitioner.on_new_batch(&record.topic, partitions, partition);
+                    self.partition(&record)?
+                } else {
+                    partition
+                }
+            }
+        };
+        if !self.accumulator.has_room_for(size_in_bytes) {
+            self.send_batches(true)?;
+        }
+        self.accumulator.append(record.topic, partition, kafka_record, callback);
+        self.send_batches(false)
+    }
+
+    /// Sends the batches that are full or lingered for `linger_ms`.
+    pub fn poll(&mut self) -> Result<()> {
+        self.send_batches(false)
+    }
+
+    /// Sends every batch held and waits for the outcome of each of their records.
+    pub fn flush(&mut self) -> Result<()> {
+        self.send_batches(true)
+    }
+
+    /// Registers the transactional id with its coordinator and obtains a producer id and a new
+    /// epoch for it, fencing any previous producer with the same transactional id and aborting
+    /// the transaction it left open. Called once, before the first transaction.
+    pub fn init_transactions(&mut self) -> Result<()> {
+        self.transaction_manager()?.require_state(&[TransactionState::Uninitialized], "initialize transactions")?;
+        self.init_transactional_producer_id()
+    }
+
+    /// Starts a transaction, which every record sent until it is committed or aborted belongs to.
+    pub fn begin_transaction(&mut self) -> Result<()> {
+        let transaction_manager = self.transaction_manager()?;
+        transaction_manager.require_state(&[TransactionState::Ready], "begin a transaction")?;
+        transaction_manager.transition_to(TransactionState::InTransaction);
+        Ok(())
+    }
+
+    /// Commits the offsets consumed by a group as part of the current transaction, so that
+    /// the records they point past count as consumed only if the transaction commits.
+    pub fn send_offsets_to_transaction(&mut self, offsets: &HashMap<(String, i32), i64>, group_id: &str) -> Result<()> {
+        self.transaction_manager()?.require_state(&[TransactionState::InTransaction], "send offsets")?;
+        let (transactional_id, producer_id, producer_epoch) = self.producer_identity()?;
+        let result = self.with_coordinator(CoordinatorType::Transaction, &transactional_id, |client, coordinator| {
+            let request = AddOffsetsToTxnRequest::new(
+                transactional_id.clone(), producer_id, producer_epoch, group_id.to_string());
+            client.send_request(Some(coordinator), request).map(|_| ())
+        });
+        if let Err(e) = result {
+            return Err(self.transaction_failed(e));
+        }
+        self.transaction_manager()?.add_offsets();
+
+        let result = self.with_coordinator(CoordinatorType::Group, group_id, |client, coordinator| {
+            let mut request = TxnOffsetCommitRequest::new(
+                transactional_id.clone(), group_id.to_string(), producer_id, producer_epoch);
+            for ((topic, partition), offset) in offsets.iter() {
+                request.add_offset(topic, *partition, *offset);
+            }
+            let response = client.send_request(Some(coordinator), request)?.body;
+            let error_code = response.topics.iter()
+                .flat_map(|topic| topic.partitions.iter())
+                .map(|partition| partition.error_code)
+                .find(|&error_code| error_code != 0)
+                .unwrap_or(0);
+            check_errors(error_code)
+        });
+        result.map_err(|e| self.transaction_failed(e))
+    }
+
+    /// Sends every record of the current transaction, then commits it. When a record or
+    /// the offsets failed, the error is returned and the transaction can only be aborted.
+    pub fn commit_transaction(&mut self) -> Result<()> {
+        self.transaction_manager()?.require_state(&[TransactionState::InTransaction], "commit a transaction")?;
+        self.flush()?;
+        self.transaction_manager()?.require_state(&[TransactionState::InTransaction], "commit a transaction")?;
+        self.end_transaction(true)
+    }
+
+    /// Drops the records of the current transaction not sent yet and aborts it, so that
+    /// none of its records are visible to consumers reading committed records.
+    pub fn abort_transaction(&mut self) -> Result<()> {
+        self.transaction_manager()?.require_state(
+            &[TransactionState::InTransaction, TransactionState::AbortableError], "abort a transaction")?;
+        let error = KafkaError::IllegalState("The transaction was aborted before the record was sent".to_string());
+        for batch in self.accumulator.abort_batches() {
+            self.fail_batch(batch, &error);
+        }
+        self.end_transaction(false)?;
+        if self.transaction_manager()?.epoch_bump_required() {
+            self.init_transactional_producer_id()?;
+        }
+        Ok(())
+    }
+
+    /// Sends the ready batches in rounds, each carrying at most one batch per partition,
+    /// until none is left ready. With `force`, waits for the batches being retried as well
+    /// until every batch held completed.
+    ///
+    /// Fails only when an idempotent producer cannot obtain a producer id for good.
+    fn send_batches(&mut self, force: bool) -> Result<()> {
+        loop {
+            if self.metadata_stale && self.kafka_client.update_topics_metadata().is_ok() {
+                self.metadata_stale = false;
+            }
+            let now = Instant::now();
+            let delivery_timeout = Duration::from_millis(self.config.delivery_timeout_ms);
+            if let Some(deadline) = now.checked_sub(delivery_timeout) {
+                for batch in self.accumulator.expire(deadline) {
+                    self.fail_batch(batch, &KafkaError::Timeout);
+                }
+            }
+            let has_producer_id = self.ensure_producer_id()?;
+            let batches = if has_producer_id { self.accumulator.drain(now, force) } else { vec![] };
+            if !batches.is_empty() {
+                self.send_round(batches);
+                continue;
+            }
+            if !force || self.accumulator.is_empty() {
+                return Ok(());
+            }
+            if !has_producer_id {
+                thread::sleep(Duration::from_millis(self.config.retry_backoff_ms));
+            } else if let Some(next_retry) = self.accumulator.next_retry() {
+                thread::sleep(next_retry.saturating_duration_since(Instant::now()));
+            }
+        }
+    }
+
+    /// Coalesces the batches for each broker into a single produce request.
+    /// The requests to every broker are written before any response is read.
+    ///
+    /// A failure is retried or reported to the callbacks of the records it concerns only.
+    fn send_round(&mut self, batches: Vec<ProducerBatch>) {
+        if let Some(error) = self.transaction_manager.as_ref().and_then(|manager| manager.transaction_error()) {
+            batches.into_iter().for_each(|batch| self.fail_batch(batch, &error));
+            return;
+        }
+        let batches = self.add_partitions_to_transaction(batches);
+        let transactional_id = self.transaction_manager.as_ref()
+            .and_then(|manager| manager.transactional_id())
+            .map(String::from);
+
+        let mut batches_by_leader: HashMap<i32, Vec<ProducerBatch>> = HashMap::new();
+        for mut batch in batches {
+            match self.leader_for(&batch.topic, batch.partition) {
+                Ok(leader_id) => {
+                    if let Some(transaction_manager) = self.transaction_manager.as_mut() {
+                        transaction_manager.assign_sequence(&mut batch);
+                    }
+                    batches_by_leader.entry(leader_id).or_default().push(batch)
+                }
+                Err(e) => self.retry_or_fail(batch, e),
+            }
+        }
+
+        let mut in_flight: Vec<(InFlightRequest<ProduceResponse>, Vec<ProducerBatch>)> = Vec::new();
+        for (leader_id, leader_batches) in batches_by_leader {
+            let mut request = ProduceRequest::new(
+                transactional_id.clone(), self.config.acks.as_i16(), self.config.request_timeout_ms);
+            let mut batches = Vec::with_capacity(leader_batches.len());
+            for batch in leader_batches {
+                let mut record_batch = RecordBatch::new(batch.records.clone());
+                record_batch.set_compression(self.config.compression);
+                record_batch.producer_id = batch.producer_id;
+                record_batch.producer_epoch = batch.producer_epoch;
+                record_batch.base_sequence = batch.base_sequence;
+                record_batch.set_transactional(transactional_id.is_some());
+                match request.add_batch(&batch.topic, batch.partition, &record_batch) {
+                    Ok(()) => batches.push(batch),
+                    Err(e) => self.fail_batch(batch, &e),
+                }
+            }
+            if batches.is_empty() {
+                continue;
+            }
+            if self.config.acks == Acks::None {
+                match self.kafka_client.send_without_response(Some(leader_id), request) {
+                    Ok(()) => batches.into_iter().for_each(|batch| batch.complete(-1, -1)),
+                    Err(e) => self.retry_or_fail_all(batches, e),
+                }
+                continue;
+            }
+            match self.kafka_client.send(Some(leader_id), request) {
+                Ok(request) => in_flight.push((request, batches)),
+                Err(e) => self.retry_or_fail_all(batches, e),
+            }
+        }
+
+        for (request, batches) in in_flight {
+            match self.kafka_client.receive(request) {
+                Ok(response) => self.complete_batches(&response.body, batches),
+                Err(e) => self.retry_or_fail_all(batches, e),
+            }
+        }
+    }
+
+    fn complete_batches(&mut self, response: &ProduceResponse, batches: Vec<ProducerBatch>) {
+        for batch in batches {
+            let partition = response.responses.iter()
+                .filter(|topic| topic.topic.0 == batch.topic)
+                .flat_map(|topic| topic.partition_responses.iter())
+                .find(|partition| partition.partition == batch.partition);
+            let result = match partition {
+                Some(partition) => match check_errors(partition.error_code) {
+                    // The batch was written by an earlier attempt whose response got lost.
+                    Err(KafkaError::Broker(ErrorCode::DuplicateSequenceNumber)) => Ok(partition),
+                    result => result.map(|_| partition),
+                },
+                None => Err(KafkaError::Protocol(format!(
+                    "No response for partition {} of {}", batch.partition, batch.topic))),
+            };
+            match result {
+                Ok(partition) => batch.complete(partition.base_offset, partition.log_append_time),
+                Err(e) => self.retry_or_fail(batch, e),
+            }
+        }
+    }
+
+    fn retry_or_fail_all(&mut self, batches: Vec<ProducerBatch>, error: KafkaError) {
+        for batch in batches {
+            self.retry_or_fail(batch, error.clone());
+        }
+    }
+
+    /// Sends a batch again after the backoff when the error is retriable and neither
+    /// `retries` nor `delivery_timeout_ms` are exhausted, refreshing the metadata first
+    /// when the leader may have moved.
+    ///
+    /// An idempotent producer whose producer id the broker no longer knows, or whose
+    /// sequence numbers it rejects, obtains a new producer id and sends the batch again
+    /// numbered under it. A transactional producer has to abort its transaction instead.
+    fn retry_or_fail(&mut self, batch: ProducerBatch, error: KafkaError) {
+        let sequence_error = matches!(error,
+            KafkaError::Broker(ErrorCode::UnknownProducerId | ErrorCode::OutOfOrderSequenceNumber));
+        let reset_producer_id = match self.transaction_manager.as_mut() {
+            Some(transaction_manager) if sequence_error && !transaction_manager.is_transactional() => {
+                transaction_manager.reset_producer_id();
+                true
+            }
+            _ => false,
+        };
+        let retriable = reset_producer_id || error.is_retriable() || matches!(error, KafkaError::Connection(_));
+        let delivery_timeout = Duration::from_millis(self.config.delivery_timeout_ms);
+        let backoff = Duration::from_millis(self.config.retry_backoff_ms);
+        let now = Instant::now();
+        if !retriable || batch.attempts >= self.config.retries || now + backoff > batch.created + delivery_timeout {
+            self.fail_batch(batch, &error);
+            return;
+        }
+        if error.needs_metadata_refresh() || matches!(error, KafkaError::Connection(_)) {
+            self.metadata_stale = true;
+        }
+        self.accumulator.reenqueue(batch, now, backoff);
+    }
+
+    /// Reports a batch as failed. When it was numbered under the current producer id, the
+    /// broker would reject the next batch of its partition for the gap left, so a new
+    /// producer id is obtained first.
+    fn fail_batch(&mut self, batch: ProducerBatch, error: &KafkaError) {
+        if let Some(transaction_manager) = self.transaction_manager.as_mut() {
+            transaction_manager.batch_failed(&batch, error);
+        }
+        batch.fail(error);
+    }
+
+    /// Asks the coordinator to write the end of the transaction to its partitions, unless
+    /// the coordinator never heard of it because it has no records and no offsets.
+    fn end_transaction(&mut self, committed: bool) -> Result<()> {
+        if self.transaction_manager()?.transaction_started() {
+            let (transactional_id, producer_id, producer_epoch) = self.producer_identity()?;
+            let result = self.with_coordinator(CoordinatorType::Transaction, &transactional_id, |client, coordinator| {
+                let request = EndTxnRequest::new(transactional_id.clone(), producer_id, producer_epoch, committed);
+                client.send_request(Some(coordinator), request).map(|_| ())
+            });
+            if let Err(e) = result {
+                return Err(self.transaction_failed(e));
+            }
+        }
+        self.transaction_manager()?.complete_transaction();
+        Ok(())
+    }
+
+    /// Obtains the producer id and a new epoch of the transactional id from its coordinator,
+    /// which numbers the batches of every partition from 0 again.
+    fn init_transactional_producer_id(&mut self) -> Result<()> {
+        let (transactional_id, _, _) = self.producer_identity()?;
+        let transaction_timeout_ms = self.config.transaction_timeout_ms;
+        let result = self.with_coordinator(CoordinatorType::Transaction, &transactional_id, |client, coordinator| {
+            let request = InitProducerIdRequest::new(Some(transactional_id.clone()), transaction_timeout_ms);
+            client.send_request(Some(coordinator), request)
+        });
+        match result {
+            Ok(response) => {
+                let transaction_manager = self.transaction_manager()?;
+                transaction_manager.set_producer_id_and_epoch(response.body.producer_id, response.body.producer_epoch);
+                transaction_manager.complete_transaction();
+                Ok(())
+            }
+            Err(e) => Err(self.transaction_failed(e)),
+        }
+    }
+
+    /// Adds the partitions of the batches that are new to the current transaction on its
+    /// coordinator, which has to know of a partition before records of the transaction are
+    /// written to it. The batches fail along with the transaction when they cannot be added.
+    fn add_partitions_to_transaction(&mut self, batches: Vec<ProducerBatch>) -> Vec<ProducerBatch> {
+        let new_partitions: Vec<(String, i32)> = match self.transaction_manager.as_ref() {
+            Some(manager) if manager.is_transactional() => batches.iter()
+                .filter(|batch| !manager.contains_partition(&batch.topic, batch.partition))
+                .map(|batch| (batch.topic.clone(), batch.partition))
+                .collect(),
+            _ => return batches,
+        };
+        if new_partitions.is_empty() {
+            return batches;
+        }
+        let result = match self.producer_identity() {
+            Ok((transactional_id, producer_id, producer_epoch)) => {
+                self.with_coordinator(CoordinatorType::Transaction, &transactional_id, |client, coordinator| {
+                    let mut request = AddPartitionsToTxnRequest::new(transactional_id.clone(), producer_id, producer_epoch);
+                    for (topic, partition) in new_partitions.iter() {
+                        request.add_partition(topic, *partition);
+                    }
+                    let response = client.send_request(Some(coordinator), request)?.body;
+                    // The partitions the broker skipped because of another one failing are not the cause.
+                    let error_code = response.results.iter()
+                        .flat_map(|topic| topic.results.iter())
+                        .map(|partition| partition.error_code)
+                        .find(|&error_code| error_code != 0 && error_code != ErrorCode::OperationNotAttempted.code())
+                        .unwrap_or(0);
+                    check_errors(error_code)
+                })
+            }
+            Err(e) => Err(e),
+        };
+        match result {
+            Ok(()) => {
+                if let Some(transaction_manager) = self.transaction_manager.as_mut() {
+                    transaction_manager.add_partitions(new_partitions);
+                }
+                batches
+            }
+            Err(e) => {
+                batches.into_iter().for_each(|batch| self.fail_batch(batch, &e));
+                vec![]
+            }
+        }
+    }
+
+    /// Runs a request against the coordinator of a transactional id or a group, looking it up
+    /// again when it moved and retrying retriable errors for up to `max_block_ms`.
+    fn with_coordinator<R>(&mut self,
+                           key_type: CoordinatorType,
+                           key: &str,
+                           mut call: impl FnMut(&mut KafkaClient, i32) -> Result<R>) -> Result<R> {
+        let deadline = Instant::now() + Duration::from_millis(self.config.max_block_ms);
+        let backoff = Duration::from_millis(self.config.retry_backoff_ms);
+        let coordinator_key = (key_type, key.to_string());
+        loop {
+            let coordinator = match self.coordinators.get(&coordinator_key) {
+                Some(&coordinator) => Ok(coordinator),
+                None => self.kafka_client.find_coordinator(key, key_type),
+            };
+            let result = match coordinator {
+                Ok(coordinator) => {
+                    self.coordinators.insert(coordinator_key.clone(), coordinator);
+                    call(&mut self.kafka_client, coordinator)
+                }
+                Err(e) => Err(e),
+            };
+            let error = match result {
+                Ok(value) => return Ok(value),
+                Err(e) => e,
+            };
+            let coordinator_moved = matches!(error,
+                KafkaError::Broker(ErrorCode::NotCoordinator | ErrorCode::CoordinatorNotAvailable)
+                | KafkaError::Io(_) | KafkaError::Connection(_));
+            if coordinator_moved {
+                self.coordinators.remove(&coordinator_key);
+            }
+            let retriable = error.is_retriable() || matches!(error, KafkaError::Connection(_));
+            if !retriable || Instant::now() + backoff > deadline {
+                return Err(error);
+            }
+            thread::sleep(backoff);
+        }
+    }
+
+    fn transaction_manager(&mut self) -> Result<&mut TransactionManager> {
+        self.transaction_manager.as_mut()
+            .filter(|manager| manager.is_transactional())
+            .ok_or_else(|| KafkaError::IllegalState("The producer has no transactional id".to_string()))
+    }
+
+    /// The transactional id, producer id and epoch sent with every transactional request.
+    fn producer_identity(&mut self) -> Result<(String, i64, i16)> {
+        let transaction_manager = self.transaction_manager()?;
+        let transactional_id = transaction_manager.transactional_id().unwrap_or_default().to_string();
+        Ok((transactional_id, transaction_manager.producer_id(), transaction_manager.producer_epoch()))
+    }
+
+    /// Moves the transaction to an error state, returning the error to the caller.
+    fn transaction_failed(&mut self, error: KafkaError) -> KafkaError {
+        if let Some(transaction_manager) = self.transaction_manager.as_mut() {
+            transaction_manager.transaction_failed(&error);
+        }
+        error
+    }
+
+    /// Obtains a producer id when idempotence is enabled and none is held, telling whether
+    /// batches can be sent. A retriable failure is tried again on the next call.
+    ///
+    /// A transactional producer gets its producer id from `init_transactions` instead.
+    fn ensure_producer_id(&mut self) -> Result<bool> {
+        let transaction_manager = match self.transaction_manager.as_mut() {
+            Some(transaction_manager) if !transaction_manager.is_transactional()
+                && !transaction_manager.has_producer_id() => transaction_manager,
+            _ => return Ok(true),
+        };
+        let request = InitProducerIdRequest::new(None, i32::MAX);
+        match self.kafka_client.send_request(None, request) {
+            Ok(response) => {
+                transaction_manager.set_producer_id_and_epoch(response.body.producer_id, response.body.producer_epoch);
+                Ok(true)
+            }
+            Err(e) if e.is_retriable() || matches!(e, KafkaError::Connection(_)) => Ok(false),
+            Err(e) => Err(e),
+        }
+    }
+
+    fn partition(&mut self, record: &ProducerRecord) -> Result<i32> {
+        if self.kafka_client.partition_count(&record.topic).is_none() {
+            self.kafka_client.update_topics_metadata()?;
+        }
+        let partition_count = self.kafka_client.partition_count(&record.topic)
+            .ok_or_else(|| self.kafka_client.missing_topic_error(&record.topic))?;
+        let available_partitions = self.kafka_client.available_partitions(&record.topic);
+        let partitions = TopicPartitions {
+            partition_count,
+            available_partitions: &available_partitions,
+        };
+        Ok(self.partitioner.partition(&record.topic, record.key.as_deref(), record.value.as_deref(), partitions))
+    }
+
+    /// Looks up the leader of a partition, refreshing the metadata once when it is unknown.
+    fn leader_for(&mut self, topic: &str, partition: i32) -> Result<i32> {
+        if self.kafka_client.leader_for(topic, partition).is_none() {
+            self.kafka_client.update_topics_metadata()?;
+        }
+        match self.kafka_client.leader_for(topic, partition) {
+            Some(leader_id) if leader_id >= 0 => Ok(leader_id),
+            Some(_) => Err(KafkaError::Broker(ErrorCode::LeaderNotAvailable)),
+            None => Err(self.kafka_client.missing_topic_error(topic)),
+        }
+    }
+}
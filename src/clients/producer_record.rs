@@ -0,0 +1,54 @@
+use crate::protocol::record::{Record, RecordHeader};
+use crate::utils::time_utils::current_timestamp;
+
+
+/// A record to be sent to a topic.
+///
+/// A record without a value is a tombstone, which deletes its key from compacted topics.
+/// Without an explicit partition the producer's partitioner chooses one and without an explicit
+/// timestamp it is stamped with the time it is turned into a `Record`.
+#[derive(Debug, Clone)]
+pub struct ProducerRecord {
+    pub topic: String,
+    pub partition: Option<i32>,
+    pub timestamp: Option<i64>,
+    pub key: Option<Vec<u8>>,
+    pub value: Option<Vec<u8>>,
+    pub headers: Vec<RecordHeader>,
+}
+
+impl ProducerRecord {
+    pub fn new(topic: String, key: Option<Vec<u8>>, value: Option<Vec<u8>>) -> Self {
+        Self {
+            topic,
+            partition: None,
+            timestamp: None,
+            key,
+            value,
+            headers: vec![],
+        }
+    }
+
+    pub fn with_partition(mut self, partition: i32) -> Self {
+        self.partition = Some(partition);
+        self
+    }
+
+    pub fn with_timestamp(mut self, timestamp: i64) -> Self {
+        self.timestamp = Some(timestamp);
+        self
+    }
+
+    /// Appends a header; headers keep their order and the same key may appear several times.
+    pub fn with_header(mut self, key: String, value: Option<Vec<u8>>) -> Self {
+        self.headers.push(RecordHeader::new(key, value));
+        self
+    }
+
+    pub fn to_record(&self) -> Record {
+        let mut record = Record::new(self.key.clone(), self.value.clone());
+        record.timestamp = self.timestamp.unwrap_or_else(current_timestamp);
+        record.headers = self.headers.clone();
+        record
+    }
+}
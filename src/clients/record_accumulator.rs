@@ -0,0 +1,197 @@
+use std::collections::{HashMap, VecDeque};
+use std::time::{Duration, Instant};
+
+use crate::clients::delivery::{DeliveryCallback, RecordMetadata};
+use crate::error::KafkaError;
+use crate::protocol::record::{Record, RECORD_BATCH_OVERHEAD};
+
+
+/// Records waiting to be sent to one topic-partition in a single record batch.
+#[derive(Debug)]
+pub struct ProducerBatch {
+    pub topic: String,
+    pub partition: i32,
+    pub records: Vec<Record>,
+    /// The callback of each record, in the same order.
+    pub callbacks: Vec<DeliveryCallback>,
+    pub size_in_bytes: usize,
+    pub created: Instant,
+    /// How many times the batch was sent already.
+    pub attempts: u32,
+    /// When a batch that failed may be sent again.
+    pub retry_after: Option<Instant>,
+    /// The producer id, epoch and first sequence number given to the batch by an idempotent
+    /// producer when it was first sent, -1 otherwise.
+    pub producer_id: i64,
+    pub producer_epoch: i16,
+    pub base_sequence: i32,
+}
+
+impl ProducerBatch {
+    fn new(topic: String, partition: i32) -> Self {
+        Self {
+            topic,
+            partition,
+            records: vec![],
+            callbacks: vec![],
+            size_in_bytes: RECORD_BATCH_OVERHEAD,
+            created: Instant::now(),
+            attempts: 0,
+            retry_after: None,
+            producer_id: -1,
+            producer_epoch: -1,
+            base_sequence: -1,
+        }
+    }
+
+    /// Reports where each record was written, -1 as base offset standing for unknown offsets.
+    pub fn complete(self, base_offset: i64, log_append_time: i64) {
+        let records = self.records.into_iter().zip(self.callbacks).enumerate();
+        for (offset_delta, (record, callback)) in records {
+            callback.complete(Ok(RecordMetadata {
+                topic: self.topic.clone(),
+                partition: self.partition,
+                offset: if base_offset < 0 { -1 } else { base_offset + offset_delta as i64 },
+                timestamp: if log_append_time != -1 { log_append_time } else { record.timestamp },
+            }));
+        }
+    }
+
+    pub fn fail(self, error: &KafkaError) {
+        for callback in self.callbacks {
+            callback.complete(Err(error.clone()));
+        }
+    }
+}
+
+/// Groups the records sent by the producer into one queue of batches per topic-partition.
+///
+/// A batch is ready to be sent once it reached `batch_size` bytes, once a newer batch was
+/// started behind it or once it waited for `linger`. The bytes of every record held count
+/// against `buffer_memory` until their batch is drained.
+#[derive(Debug)]
+pub struct RecordAccumulator {
+    batch_size: usize,
+    linger: Duration,
+    buffer_memory: usize,
+    used_memory: usize,
+    batches: HashMap<(String, i32), VecDeque<ProducerBatch>>,
+}
+
+impl RecordAccumulator {
+    pub fn new(batch_size: usize, linger: Duration, buffer_memory: usize) -> Self {
+        Self {
+            batch_size,
+            linger,
+            buffer_memory,
+            used_memory: 0,
+            batches: HashMap::new(),
+        }
+    }
+
+    pub fn used_memory(&self) -> usize {
+        self.used_memory
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.batches.values().all(|batches| batches.is_empty())
+    }
+
+    /// Whether a record of the given size fits in the memory left.
+    pub fn has_room_for(&self, size_in_bytes: usize) -> bool {
+        self.used_memory + RECORD_BATCH_OVERHEAD + size_in_bytes <= self.buffer_memory
+    }
+
+    /// Whether a record of the given size could ever be accepted.
+    pub fn can_hold(&self, size_in_bytes: usize) -> bool {
+        RECORD_BATCH_OVERHEAD + size_in_bytes <= self.buffer_memory
+    }
+
+    /// Whether appending a record of the given size to a partition would start a new batch.
+    pub fn starts_new_batch(&self, topic: &str, partition: i32, size_in_bytes: usize) -> bool {
+        let batch = self.batches.get(&(topic.to_string(), partition)).and_then(|batches| batches.back());
+        batch.is_none_or(|batch| batch.size_in_bytes + size_in_bytes > self.batch_size)
+    }
+
+    /// Adds a record to the last batch of its partition, starting a new batch when it is full.
+    /// A record larger than `batch_size` gets a batch of its own.
+    pub fn append(&mut self, topic: String, partition: i32, record: Record, callback: DeliveryCallback) {
+        let size_in_bytes = record.size_in_bytes();
+        let starts_new_batch = self.starts_new_batch(&topic, partition, size_in_bytes);
+        let batches = self.batches.entry((topic.clone(), partition)).or_default();
+        if starts_new_batch {
+            batches.push_back(ProducerBatch::new(topic, partition));
+            self.used_memory += RECORD_BATCH_OVERHEAD;
+        }
+        let batch = batches.back_mut().unwrap();
+        batch.size_in_bytes += size_in_bytes;
+        batch.records.push(record);
+        batch.callbacks.push(callback);
+        self.used_memory += size_in_bytes;
+    }
+
+    /// Removes the first batch of each partition when it is ready to be sent, or whenever
+    /// `force` is set, unless it is waiting to be retried.
+    ///
+    /// Taking one batch per partition at a time keeps a partition from having two batches
+    /// in flight, so that a batch sent again can never be overtaken by the next one.
+    pub fn drain(&mut self, now: Instant, force: bool) -> Vec<ProducerBatch> {
+        let mut ready = Vec::new();
+        for batches in self.batches.values_mut() {
+            let batch = match batches.front() {
+                Some(batch) => batch,
+                None => continue,
+            };
+            if batch.retry_after.is_some_and(|retry_after| retry_after > now) {
+                continue;
+            }
+            let is_ready = force
+                || batch.attempts > 0
+                || batches.len() > 1
+                || batch.size_in_bytes >= self.batch_size
+                || now.duration_since(batch.created) >= self.linger;
+            if is_ready {
+                let batch = batches.pop_front().unwrap();
+                self.used_memory -= batch.size_in_bytes;
+                ready.push(batch);
+            }
+        }
+        self.batches.retain(|_, batches| !batches.is_empty());
+        ready
+    }
+
+    /// Puts a batch that failed back in front of its partition, to be sent again after the backoff.
+    pub fn reenqueue(&mut self, mut batch: ProducerBatch, now: Instant, backoff: Duration) {
+        batch.attempts += 1;
+        batch.retry_after = Some(now + backoff);
+        self.used_memory += batch.size_in_bytes;
+        self.batches.entry((batch.topic.clone(), batch.partition)).or_default().push_front(batch);
+    }
+
+    /// The earliest time a batch waiting to be retried may be sent again.
+    pub fn next_retry(&self) -> Option<Instant> {
+        self.batches.values()
+            .filter_map(|batches| batches.front().and_then(|batch| batch.retry_after))
+            .min()
+    }
+
+    /// Removes the batches created before the given deadline.
+    pub fn expire(&mut self, deadline: Instant) -> Vec<ProducerBatch> {
+        let mut expired = Vec::new();
+        for batches in self.batches.values_mut() {
+            while batches.front().is_some_and(|batch| batch.created < deadline) {
+                let batch = batches.pop_front().unwrap();
+                self.used_memory -= batch.size_in_bytes;
+                expired.push(batch);
+            }
+        }
+        self.batches.retain(|_, batches| !batches.is_empty());
+        expired
+    }
+
+    /// Removes every batch held, for a transaction being aborted.
+    pub fn abort_batches(&mut self) -> Vec<ProducerBatch> {
+        self.used_memory = 0;
+        self.batches.drain().flat_map(|(_, batches)| batches).collect()
+    }
+}
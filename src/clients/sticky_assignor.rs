@@ -0,0 +1,372 @@
+use std::collections::{BTreeMap, HashMap};
+use std::io::Cursor;
+
+use crate::clients::partition_assignor::{all_partitions_sorted, empty_assignments, sorted_members, Assignment,
+                                          PartitionAssignor, RebalanceProtocol, Subscription};
+use crate::error::Result;
+use crate::protocol::consumer_protocol::{flatten_topic_partitions, group_by_topic, read_topic_partitions,
+                                         write_topic_partitions};
+use crate::protocol::primitives::KafkaPrimitive;
+
+
+/// The partitions a member owned before the rebalance, and the generation it got them in.
+#[derive(Debug, Clone)]
+struct MemberData {
+    partitions: Vec<(String, i32)>,
+    generation_id: i32,
+}
+
+/// Balances the partitions among the members while moving as few of them as possible away
+/// from the members that owned them.
+///
+/// A partition claimed by several members stays with the one of the latest generation, and
+/// with none of them when they claim it from the same generation. The partitions left are
+/// given to the eligible member with the fewest partitions, then partitions move, the last
+/// given first, from a member to one subscribed to their topic with at least two fewer
+/// partitions until no such move is left.
+fn sticky_assign(partitions_per_topic: &HashMap<String, i32>, subscriptions: &HashMap<String, Subscription>,
+                 owned: &HashMap<&String, MemberData>) -> HashMap<String, Assignment> {
+    let members = sorted_members(subscriptions);
+    let subscribes = |member_id: &String, topic: &String| subscriptions[member_id].topics.contains(topic);
+    let exists = |topic: &String, partition: i32| {
+        partitions_per_topic.get(topic).is_some_and(|&count| partition >= 0 && partition < count)
+    };
+
+    let mut owners: HashMap<(String, i32), (i32, Option<&String>)> = HashMap::new();
+    for &member_id in members.iter() {
+        let member_data = match owned.get(member_id) {
+            Some(member_data) => member_data,
+            None => continue,
+        };
+        for (topic, partition) in member_data.partitions.iter() {
+            if !exists(topic, *partition) || !subscribes(member_id, topic) {
+                continue;
+            }
+            let owner = owners.entry((topic.clone(), *partition)).or_insert((member_data.generation_id, Some(member_id)));
+            if owner.1 == Some(member_id) {
+                continue;
+            }
+            if member_data.generation_id > owner.0 {
+                *owner = (member_data.generation_id, Some(member_id));
+            } else if member_data.generation_id == owner.0 {
+                owner.1 = None;
+            }
+        }
+    }
+
+    let mut assignment: BTreeMap<usize, Vec<(String, i32)>> = (0..members.len()).map(|i| (i, Vec::new())).collect();
+    let mut unassigned = Vec::new();
+    for topic_partition in all_partitions_sorted(partitions_per_topic, subscriptions) {
+        match owners.get(&topic_partition).and_then(|(_, owner)| *owner) {
+            Some(owner) => {
+                let i = members.iter().position(|&member_id| member_id == owner).unwrap();
+                assignment.get_mut(&i).unwrap().push(topic_partition);
+            }
+            None => unassigned.push(topic_partition),
+        }
+    }
+
+    for topic_partition in unassigned {
+        let member = (0..members.len())
+            .filter(|&i| subscribes(members[i], &topic_partition.0))
+            .min_by_key(|&i| (assignment[&i].len(), i));
+        if let Some(i) = member {
+            assignment.get_mut(&i).unwrap().push(topic_partition);
+        }
+    }
+
+    'balance: loop {
+        let mut by_count: Vec<usize> = (0..members.len()).collect();
+        by_count.sort_by_key(|&i| (std::cmp::Reverse(assignment[&i].len()), i));
+        for from in by_count {
+            let count = assignment[&from].len();
+            for index in (0..count).rev() {
+                let topic = &assignment[&from][index].0;
+                let to = (0..members.len())
+                    .filter(|&i| assignment[&i].len() + 1 < count && subscribes(members[i], topic))
+                    .min_by_key(|&i| (assignment[&i].len(), i));
+                if let Some(to) = to {
+                    let topic_partition = assignment.get_mut(&from).unwrap().remove(index);
+                    assignment.get_mut(&to).unwrap().push(topic_partition);
+                    continue 'balance;
+                }
+            }
+        }
+        break;
+    }
+
+    let mut assignments = empty_assignments(subscriptions);
+    for (i, partitions) in assignment {
+        assignments.get_mut(members[i]).unwrap().partitions = partitions;
+    }
+    assignments
+}
+
+/// Balances the partitions like the round-robin assignor while keeping as many partitions as
+/// possible with the members that owned them, with the eager protocol.
+///
+/// Since an eager member gives up its partitions before joining again, it sends the partitions
+/// it was last assigned as its user data, encoded like the Java client:
+///
+/// StickyAssignorUserData => [previous_assignment] generation
+///   previous_assignment => topic [partitions]
+///     topic => STRING
+///     partitions => INT32
+///   generation => INT32 (version 1+)
+#[derive(Debug)]
+pub struct StickyAssignor {
+    member_assignment: Option<Vec<(String, i32)>>,
+    generation_id: i32,
+}
+
+impl Default for StickyAssignor {
+    fn default() -> Self {
+        Self {
+            member_assignment: None,
+            generation_id: -1,
+        }
+    }
+}
+
+impl StickyAssignor {
+    fn member_data(subscription: &Subscription) -> Result<MemberData> {
+        let user_data = match subscription.user_data.as_ref() {
+            Some(user_data) if !user_data.is_empty() => user_data.clone(),
+            _ => return Ok(MemberData { partitions: vec![], generation_id: -1 }),
+        };
+        let length = user_data.len() as u64;
+        let mut buffer = Cursor::new(user_data);
+        let partitions = flatten_topic_partitions(&read_topic_partitions(&mut buffer)?);
+        let generation_id = if buffer.position() < length { i32::read_from_buffer(&mut buffer)? } else { -1 };
+        Ok(MemberData { partitions, generation_id })
+    }
+}
+
+impl PartitionAssignor for StickyAssignor {
+    fn name(&self) -> &'static str {
+        "sticky"
+    }
+
+    fn subscription_user_data(&self, _topics: &[String]) -> Option<Vec<u8>> {
+        let partitions = self.member_assignment.as_ref()?;
+        let mut buffer = Vec::new();
+        write_topic_partitions(&mut buffer, &group_by_topic(partitions));
+        self.generation_id.write_to_buffer(&mut buffer);
+        Some(buffer)
+    }
+
+    /// A member whose user data cannot be decoded is taken as owning no partition.
+    fn assign(&self, partitions_per_topic: &HashMap<String, i32>,
+              subscriptions: &HashMap<String, Subscription>) -> HashMap<String, Assignment> {
+        let owned = subscriptions.iter()
+            .filter_map(|(member_id, subscription)| Some((member_id, Self::member_data(subscription).ok()?)))
+            .collect();
+        sticky_assign(partitions_per_topic, subscriptions, &owned)
+    }
+
+    fn on_assignment(&mut self, assignment: &Assignment, generation_id: i32) {
+        self.member_assignment = Some(assignment.partitions.clone());
+        self.generation_id = generation_id;
+    }
+}
+
+/// The sticky assignment with the cooperative protocol: members keep their partitions while
+/// the group rebalances, and a partition moving to another member is left out of the
+/// assignment until its owner gave it up and rejoined.
+///
+/// Members send their owned partitions and generation in their subscription, and the
+/// generation again as their user data for members of older versions:
+///
+/// CooperativeStickyAssignorUserData => generation
+///   generation => INT32
+#[derive(Debug)]
+pub struct CooperativeStickyAssignor {
+    generation_id: i32,
+}
+
+impl Default for CooperativeStickyAssignor {
+    fn default() -> Self {
+        Self { generation_id: -1 }
+    }
+}
+
+impl PartitionAssignor for CooperativeStickyAssignor {
+    fn name(&self) -> &'static str {
+        "cooperative-sticky"
+    }
+
+    fn supported_protocols(&self) -> Vec<RebalanceProtocol> {
+        vec![RebalanceProtocol::Cooperative, RebalanceProtocol::Eager]
+    }
+
+    fn subscription_user_data(&self, _topics: &[String]) -> Option<Vec<u8>> {
+        let mut buffer = Vec::new();
+        self.generation_id.write_to_buffer(&mut buffer);
+        Some(buffer)
+    }
+
+    fn assign(&self, partitions_per_topic: &HashMap<String, i32>,
+              subscriptions: &HashMap<String, Subscription>) -> HashMap<String, Assignment> {
+        let owned: HashMap<&String, MemberData> = subscriptions.iter()
+            .map(|(member_id, subscription)| {
+                let generation_id = match subscription.user_data.as_ref() {
+                    _ if subscription.generation_id >= 0 => subscription.generation_id,
+                    Some(user_data) => i32::read_from_buffer(&mut Cursor::new(user_data.clone())).unwrap_or(-1),
+                    None => -1,
+                };
+                (member_id, MemberData { partitions: subscription.owned_partitions.clone(), generation_id })
+            })
+            .collect();
+        let mut assignments = sticky_assign(partitions_per_topic, subscriptions, &owned);
+
+        let mut previous_owners: HashMap<&(String, i32), &String> = HashMap::new();
+        for (member_id, subscription) in subscriptions.iter() {
+            for topic_partition in subscription.owned_partitions.iter() {
+                previous_owners.insert(topic_partition, member_id);
+            }
+        }
+        for (member_id, assignment) in assignments.iter_mut() {
+            assignment.partitions.retain(|topic_partition| {
+                previous_owners.get(topic_partition).is_none_or(|&owner| owner == member_id)
+            });
+        }
+        assignments
+    }
+
+    fn on_assignment(&mut self, _assignment: &Assignment, generation_id: i32) {
+        self.generation_id = generation_id;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn partitions(partitions: &[(&str, i32)]) -> Vec<(String, i32)> {
+        partitions.iter().map(|&(topic, partition)| (topic.to_string(), partition)).collect()
+    }
+
+    fn subscription(user_data: Option<Vec<u8>>) -> Subscription {
+        Subscription {
+            topics: vec!["t0".to_string()],
+            user_data,
+            owned_partitions: vec![],
+            generation_id: -1,
+            group_instance_id: None,
+        }
+    }
+
+    fn one_topic(partition_count: i32) -> HashMap<String, i32> {
+        vec![("t0".to_string(), partition_count)].into_iter().collect()
+    }
+
+    /// The user data of a sticky member last assigned `owned` in `generation_id`.
+    fn sticky_user_data(owned: &[(&str, i32)], generation_id: i32) -> Option<Vec<u8>> {
+        let mut assignor = StickyAssignor::default();
+        assignor.on_assignment(&Assignment { partitions: partitions(owned), user_data: None }, generation_id);
+        assignor.subscription_user_data(&[])
+    }
+
+    fn cooperative_subscription(owned: &[(&str, i32)], generation_id: i32) -> Subscription {
+        let mut subscription = subscription(Some(generation_id.to_be_bytes().to_vec()));
+        subscription.owned_partitions = partitions(owned);
+        subscription.generation_id = generation_id;
+        subscription
+    }
+
+    #[test]
+    fn sticky_user_data_matches_java() {
+        let bytes = vec![
+            0, 0, 0, 2, 0, 2, b't', b'0', 0, 0, 0, 1, 0, 0, 0, 1,
+            0, 2, b't', b'1', 0, 0, 0, 1, 0, 0, 0, 2, 0, 0, 0, 7,
+        ];
+        assert_eq!(StickyAssignor::default().subscription_user_data(&[]), None);
+        assert_eq!(sticky_user_data(&[("t0", 1), ("t1", 2)], 7), Some(bytes.clone()));
+
+        let member_data = StickyAssignor::member_data(&subscription(Some(bytes))).unwrap();
+        assert_eq!(member_data.partitions, partitions(&[("t0", 1), ("t1", 2)]));
+        assert_eq!(member_data.generation_id, 7);
+    }
+
+    #[test]
+    fn sticky_user_data_without_generation() {
+        let bytes = vec![0, 0, 0, 1, 0, 2, b't', b'0', 0, 0, 0, 1, 0, 0, 0, 3];
+        let member_data = StickyAssignor::member_data(&subscription(Some(bytes))).unwrap();
+        assert_eq!(member_data.partitions, partitions(&[("t0", 3)]));
+        assert_eq!(member_data.generation_id, -1);
+    }
+
+    #[test]
+    fn cooperative_sticky_user_data_is_the_generation() {
+        let mut assignor = CooperativeStickyAssignor::default();
+        assert_eq!(assignor.subscription_user_data(&[]), Some(vec![255, 255, 255, 255]));
+        assignor.on_assignment(&Assignment::default(), 5);
+        assert_eq!(assignor.subscription_user_data(&[]), Some(vec![0, 0, 0, 5]));
+    }
+
+    #[test]
+    fn sticky_keeps_partitions_when_a_member_leaves() {
+        let assignor = StickyAssignor::default();
+        let subscriptions: HashMap<String, Subscription> = ["c0", "c1", "c2"].iter()
+            .map(|member_id| (member_id.to_string(), subscription(None)))
+            .collect();
+        let first = assignor.assign(&one_topic(6), &subscriptions);
+        assert!(first.values().all(|assignment| assignment.partitions.len() == 2));
+
+        let subscriptions: HashMap<String, Subscription> = ["c0", "c1"].iter()
+            .map(|&member_id| {
+                let owned = &first[member_id].partitions;
+                let mut assignor = StickyAssignor::default();
+                assignor.on_assignment(&Assignment { partitions: owned.clone(), user_data: None }, 1);
+                (member_id.to_string(), subscription(assignor.subscription_user_data(&[])))
+            })
+            .collect();
+        let second = assignor.assign(&one_topic(6), &subscriptions);
+        for member_id in ["c0", "c1"].iter() {
+            assert_eq!(second[*member_id].partitions.len(), 3);
+            assert!(first[*member_id].partitions.iter().all(|partition| second[*member_id].partitions.contains(partition)));
+        }
+    }
+
+    #[test]
+    fn sticky_prefers_the_claim_of_the_latest_generation() {
+        let subscriptions: HashMap<String, Subscription> = vec![
+            ("c0".to_string(), subscription(sticky_user_data(&[("t0", 0)], 4))),
+            ("c1".to_string(), subscription(sticky_user_data(&[("t0", 0)], 5))),
+        ].into_iter().collect();
+        let assignments = StickyAssignor::default().assign(&one_topic(2), &subscriptions);
+        assert_eq!(assignments["c0"].partitions, partitions(&[("t0", 1)]));
+        assert_eq!(assignments["c1"].partitions, partitions(&[("t0", 0)]));
+    }
+
+    #[test]
+    fn sticky_ignores_conflicting_claims_of_the_same_generation() {
+        let subscriptions: HashMap<String, Subscription> = vec![
+            ("c0".to_string(), subscription(sticky_user_data(&[("t0", 0), ("t0", 1)], 5))),
+            ("c1".to_string(), subscription(sticky_user_data(&[("t0", 0)], 5))),
+        ].into_iter().collect();
+        let assignments = StickyAssignor::default().assign(&one_topic(2), &subscriptions);
+        assert_eq!(assignments["c0"].partitions, partitions(&[("t0", 1)]));
+        assert_eq!(assignments["c1"].partitions, partitions(&[("t0", 0)]));
+    }
+
+    #[test]
+    fn cooperative_sticky_withholds_partitions_until_revoked() {
+        let assignor = CooperativeStickyAssignor::default();
+        let subscriptions: HashMap<String, Subscription> = vec![
+            ("c0".to_string(), cooperative_subscription(&[("t0", 0), ("t0", 1)], 3)),
+            ("c1".to_string(), cooperative_subscription(&[], -1)),
+        ].into_iter().collect();
+        let first = assignor.assign(&one_topic(2), &subscriptions);
+        assert_eq!(first["c0"].partitions, partitions(&[("t0", 0)]));
+        assert!(first["c1"].partitions.is_empty());
+
+        let subscriptions: HashMap<String, Subscription> = vec![
+            ("c0".to_string(), cooperative_subscription(&[("t0", 0)], 4)),
+            ("c1".to_string(), cooperative_subscription(&[], 4)),
+        ].into_iter().collect();
+        let second = assignor.assign(&one_topic(2), &subscriptions);
+        assert_eq!(second["c0"].partitions, partitions(&[("t0", 0)]));
+        assert_eq!(second["c1"].partitions, partitions(&[("t0", 1)]));
+    }
+}
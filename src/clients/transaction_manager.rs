@@ -0,0 +1,218 @@
+use std::collections::{HashMap, HashSet};
+
+use crate::clients::record_accumulator::ProducerBatch;
+use crate::error::{KafkaError, Result};
+
+
+/// Where a transactional producer stands between `init_transactions` and its transactions.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TransactionState {
+    /// `init_transactions` was not called yet.
+    Uninitialized,
+    /// No transaction is ongoing, one may begin.
+    Ready,
+    InTransaction,
+    /// A record or offset of the current transaction failed, so it can only be aborted.
+    AbortableError,
+    /// The producer was fenced by a newer instance with the same transactional id or is
+    /// not authorized, so it can only be dropped.
+    FatalError,
+}
+
+/// The producer id, epoch and sequence numbers of an idempotent producer, and the
+/// transaction state of a transactional one.
+///
+/// The broker keeps the sequence number of the last batch it wrote from each producer id
+/// and partition, so it drops a batch sent again after it was written and rejects a batch
+/// arriving after a gap.
+#[derive(Debug)]
+pub struct TransactionManager {
+    transactional_id: Option<String>,
+    producer_id: i64,
+    producer_epoch: i16,
+    /// The sequence number of the next batch of each topic-partition.
+    sequences: HashMap<(String, i32), i32>,
+    state: TransactionState,
+    /// The error that moved the producer to `AbortableError` or `FatalError`.
+    last_error: Option<KafkaError>,
+    /// The partitions added to the current transaction on the coordinator.
+    partitions: HashSet<(String, i32)>,
+    /// Whether the coordinator knows of the current transaction, through its partitions or offsets.
+    transaction_started: bool,
+    /// Set when a failed batch left a gap in the sequence numbers of a transactional producer,
+    /// which needs a new epoch once its transaction is aborted.
+    epoch_bump_required: bool,
+}
+
+impl TransactionManager {
+    pub fn new(transactional_id: Option<String>) -> Self {
+        Self {
+            transactional_id,
+            producer_id: -1,
+            producer_epoch: -1,
+            sequences: HashMap::new(),
+            state: TransactionState::Uninitialized,
+            last_error: None,
+            partitions: HashSet::new(),
+            transaction_started: false,
+            epoch_bump_required: false,
+        }
+    }
+
+    pub fn transactional_id(&self) -> Option<&str> {
+        self.transactional_id.as_deref()
+    }
+
+    pub fn is_transactional(&self) -> bool {
+        self.transactional_id.is_some()
+    }
+
+    pub fn producer_id(&self) -> i64 {
+        self.producer_id
+    }
+
+    pub fn producer_epoch(&self) -> i16 {
+        self.producer_epoch
+    }
+
+    pub fn has_producer_id(&self) -> bool {
+        self.producer_id >= 0
+    }
+
+    /// Starts numbering the batches of every partition from 0 under a new producer id.
+    pub fn set_producer_id_and_epoch(&mut self, producer_id: i64, producer_epoch: i16) {
+        self.producer_id = producer_id;
+        self.producer_epoch = producer_epoch;
+        self.sequences.clear();
+        self.epoch_bump_required = false;
+    }
+
+    /// Drops the producer id once the broker lost track of it or a gap was left in
+    /// the sequence numbers of a partition, so that a new one is obtained.
+    pub fn reset_producer_id(&mut self) {
+        self.set_producer_id_and_epoch(-1, -1);
+    }
+
+    pub fn epoch_bump_required(&self) -> bool {
+        self.epoch_bump_required
+    }
+
+    /// Gives a batch the current producer id and the next sequence numbers of its partition.
+    /// A batch sent again under the same producer id and epoch keeps its sequence number,
+    /// which is how the broker recognizes it as a duplicate.
+    pub fn assign_sequence(&mut self, batch: &mut ProducerBatch) {
+        if self.holds_sequence_of(batch) {
+            return;
+        }
+        let sequence = self.sequences.entry((batch.topic.clone(), batch.partition)).or_insert(0);
+        batch.producer_id = self.producer_id;
+        batch.producer_epoch = self.producer_epoch;
+        batch.base_sequence = *sequence;
+        *sequence = increment_sequence(*sequence, batch.records.len() as i32);
+    }
+
+    /// Whether a batch was numbered under the current producer id, so that failing it
+    /// for good leaves a gap in the sequence numbers of its partition.
+    pub fn holds_sequence_of(&self, batch: &ProducerBatch) -> bool {
+        batch.base_sequence >= 0 && batch.producer_id == self.producer_id && batch.producer_epoch == self.producer_epoch
+    }
+
+    /// Records that a batch failed for good. An idempotent producer obtains a new producer id
+    /// when the batch leaves a gap, a transactional one can only abort its transaction.
+    pub fn batch_failed(&mut self, batch: &ProducerBatch, error: &KafkaError) {
+        let leaves_gap = self.holds_sequence_of(batch);
+        if self.is_transactional() {
+            self.epoch_bump_required |= leaves_gap;
+            self.transaction_failed(error);
+        } else if leaves_gap {
+            self.reset_producer_id();
+        }
+    }
+
+    pub fn state(&self) -> TransactionState {
+        self.state
+    }
+
+    /// Moves to `FatalError` on errors that fence the producer, to `AbortableError` on
+    /// any other error during a transaction, keeping the first error for later calls.
+    pub fn transaction_failed(&mut self, error: &KafkaError) {
+        let fatal = matches!(error, KafkaError::Broker(code) if code.is_fatal_for_producer());
+        if fatal && self.state != TransactionState::FatalError {
+            self.state = TransactionState::FatalError;
+            self.last_error = Some(error.clone());
+        } else if self.state == TransactionState::InTransaction {
+            self.state = TransactionState::AbortableError;
+            self.last_error = Some(error.clone());
+        }
+    }
+
+    /// The error to fail the records with once the transaction can no longer commit.
+    pub fn transaction_error(&self) -> Option<KafkaError> {
+        match self.state {
+            TransactionState::AbortableError | TransactionState::FatalError => self.last_error.clone(),
+            _ => None,
+        }
+    }
+
+    /// Fails unless the producer is in one of the given states, with the error that moved
+    /// it out of the transaction when there is one.
+    pub fn require_state(&self, allowed: &[TransactionState], action: &str) -> Result<()> {
+        if !self.is_transactional() {
+            return Err(KafkaError::IllegalState(format!("Cannot {} without a transactional id", action)));
+        }
+        if allowed.contains(&self.state) {
+            return Ok(());
+        }
+        match self.transaction_error() {
+            Some(error) => Err(error),
+            None => Err(KafkaError::IllegalState(format!("Cannot {} in state {:?}", action, self.state))),
+        }
+    }
+
+    /// Records can only be sent inside a transaction by a transactional producer.
+    pub fn check_can_send(&self) -> Result<()> {
+        if !self.is_transactional() {
+            return Ok(());
+        }
+        self.require_state(&[TransactionState::InTransaction], "send records outside of a transaction")
+    }
+
+    pub fn transition_to(&mut self, state: TransactionState) {
+        self.state = state;
+    }
+
+    pub fn contains_partition(&self, topic: &str, partition: i32) -> bool {
+        self.partitions.contains(&(topic.to_string(), partition))
+    }
+
+    pub fn add_partitions(&mut self, partitions: Vec<(String, i32)>) {
+        self.partitions.extend(partitions);
+        self.transaction_started = true;
+    }
+
+    /// Records that offsets were added to the current transaction.
+    pub fn add_offsets(&mut self) {
+        self.transaction_started = true;
+    }
+
+    pub fn transaction_started(&self) -> bool {
+        self.transaction_started
+    }
+
+    /// Gets ready for the next transaction once the current one committed or aborted.
+    pub fn complete_transaction(&mut self) {
+        self.state = TransactionState::Ready;
+        self.last_error = None;
+        self.partitions.clear();
+        self.transaction_started = false;
+    }
+}
+
+/// Sequence numbers wrap around to 0 after `i32::MAX`, like the broker expects.
+fn increment_sequence(sequence: i32, increment: i32) -> i32 {
+    if sequence > i32::MAX - increment {
+        increment - (i32::MAX - sequence) - 1
+    } else {
+        sequence + increment
+    }
+}
@@ -0,0 +1,146 @@
+use crate::error::{KafkaError, Result};
+
+const CLIENT_ID: &str = "consumer-client";
+const REQUEST_TIMEOUT_MS: i32 = 30000;
+const FETCH_MIN_BYTES: i32 = 1;
+const FETCH_MAX_BYTES: i32 = 50 * 1024 * 1024;
+const FETCH_MAX_WAIT_MS: i32 = 500;
+const MAX_PARTITION_FETCH_BYTES: i32 = 1024 * 1024;
+const MAX_POLL_RECORDS: usize = 500;
+const SESSION_TIMEOUT_MS: i32 = 45000;
+const HEARTBEAT_INTERVAL_MS: u64 = 3000;
+const MAX_POLL_INTERVAL_MS: i32 = 300000;
+const RETRY_BACKOFF_MS: u64 = 100;
+
+
+/// Where to start consuming a partition without a committed offset, or whose position
+/// is no longer in the log.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum AutoOffsetReset {
+    /// From the first record still in the log.
+    Earliest,
+    /// From the records written after the position is reset.
+    Latest,
+    /// Fail the fetch instead.
+    None,
+}
+
+impl AutoOffsetReset {
+    /// Parses the `auto.offset.reset` values used by the Java client: "earliest", "latest" or "none".
+    pub fn from_name(name: &str) -> Result<Self> {
+        match name {
+            "earliest" => Ok(AutoOffsetReset::Earliest),
+            "latest" => Ok(AutoOffsetReset::Latest),
+            "none" => Ok(AutoOffsetReset::None),
+            _ => Err(KafkaError::InvalidArgument(format!("invalid auto.offset.reset value {}", name))),
+        }
+    }
+}
+
+/// Which records of transactional producers a consumer reads.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum IsolationLevel {
+    /// Every record, including those of transactions still open or aborted.
+    ReadUncommitted,
+    /// Only the records of committed transactions, up to the last stable offset of each partition.
+    ReadCommitted,
+}
+
+impl IsolationLevel {
+    /// Parses the `isolation.level` values used by the Java client: "read_uncommitted" or "read_committed".
+    pub fn from_name(name: &str) -> Result<Self> {
+        match name {
+            "read_uncommitted" => Ok(IsolationLevel::ReadUncommitted),
+            "read_committed" => Ok(IsolationLevel::ReadCommitted),
+            _ => Err(KafkaError::InvalidArgument(format!("invalid isolation.level value {}", name))),
+        }
+    }
+
+    pub fn as_i8(self) -> i8 {
+        match self {
+            IsolationLevel::ReadUncommitted => 0,
+            IsolationLevel::ReadCommitted => 1,
+        }
+    }
+}
+
+/// The standard ways of assigning the partitions of a group to its members.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum AssignmentStrategy {
+    Range,
+    RoundRobin,
+    Sticky,
+    CooperativeSticky,
+}
+
+impl AssignmentStrategy {
+    /// Parses the assignment protocol names used by the Java client: "range", "roundrobin",
+    /// "sticky" or "cooperative-sticky".
+    pub fn from_name(name: &str) -> Result<Self> {
+        match name {
+            "range" => Ok(AssignmentStrategy::Range),
+            "roundrobin" => Ok(AssignmentStrategy::RoundRobin),
+            "sticky" => Ok(AssignmentStrategy::Sticky),
+            "cooperative-sticky" => Ok(AssignmentStrategy::CooperativeSticky),
+            _ => Err(KafkaError::InvalidArgument(format!("invalid partition.assignment.strategy value {}", name))),
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct ConsumerConfig {
+    pub bootstrap_servers: Vec<String>,
+    pub client_id: String,
+    /// The group the consumer joins to share the partitions of its subscription with the
+    /// other members, starting from the offsets the group committed.
+    pub group_id: Option<String>,
+    /// Makes the consumer a static member of its group, keeping its partitions across restarts
+    /// shorter than `session_timeout_ms`.
+    pub group_instance_id: Option<String>,
+    pub auto_offset_reset: AutoOffsetReset,
+    /// Whether the records of aborted and still open transactions are returned.
+    pub isolation_level: IsolationLevel,
+    pub request_timeout_ms: i32,
+    /// Bytes of records the broker waits for before answering a fetch, up to `fetch_max_wait_ms`.
+    pub fetch_min_bytes: i32,
+    /// Bytes of records a fetch returns at most, unless the first batch is larger.
+    pub fetch_max_bytes: i32,
+    pub fetch_max_wait_ms: i32,
+    /// Bytes of records a fetch returns per partition at most, unless the first batch is larger.
+    pub max_partition_fetch_bytes: i32,
+    /// Records a single `poll` returns at most, the others staying buffered for the next one.
+    pub max_poll_records: usize,
+    /// How long the coordinator waits for a heartbeat before removing the consumer from its group.
+    pub session_timeout_ms: i32,
+    pub heartbeat_interval_ms: u64,
+    /// How long the coordinator waits for every member to join again once the group rebalances.
+    pub max_poll_interval_ms: i32,
+    pub retry_backoff_ms: u64,
+    /// The assignors offered to the group, in order of preference. The group rebalances
+    /// cooperatively only when all of them support it.
+    pub partition_assignment_strategy: Vec<AssignmentStrategy>,
+}
+
+impl ConsumerConfig {
+    pub fn new(bootstrap_servers: Vec<String>) -> Self {
+        Self {
+            bootstrap_servers,
+            client_id: CLIENT_ID.to_string(),
+            group_id: None,
+            group_instance_id: None,
+            auto_offset_reset: AutoOffsetReset::Latest,
+            isolation_level: IsolationLevel::ReadUncommitted,
+            request_timeout_ms: REQUEST_TIMEOUT_MS,
+            fetch_min_bytes: FETCH_MIN_BYTES,
+            fetch_max_bytes: FETCH_MAX_BYTES,
+            fetch_max_wait_ms: FETCH_MAX_WAIT_MS,
+            max_partition_fetch_bytes: MAX_PARTITION_FETCH_BYTES,
+            max_poll_records: MAX_POLL_RECORDS,
+            session_timeout_ms: SESSION_TIMEOUT_MS,
+            heartbeat_interval_ms: HEARTBEAT_INTERVAL_MS,
+            max_poll_interval_ms: MAX_POLL_INTERVAL_MS,
+            retry_backoff_ms: RETRY_BACKOFF_MS,
+            partition_assignment_strategy: vec![AssignmentStrategy::Range, AssignmentStrategy::CooperativeSticky],
+        }
+    }
+}
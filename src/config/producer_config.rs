@@ -0,0 +1,102 @@
+use crate::error::{KafkaError, Result};
+use crate::protocol::compression::Compression;
+
+const CLIENT_ID: &str = "producer-client";
+const REQUEST_TIMEOUT_MS: i32 = 30000;
+const BATCH_SIZE: usize = 16384;
+const LINGER_MS: u64 = 0;
+const BUFFER_MEMORY: usize = 32 * 1024 * 1024;
+const RETRIES: u32 = i32::MAX as u32;
+const RETRY_BACKOFF_MS: u64 = 100;
+const DELIVERY_TIMEOUT_MS: u64 = 120000;
+const MAX_IN_FLIGHT_REQUESTS_PER_CONNECTION: usize = 5;
+const TRANSACTION_TIMEOUT_MS: i32 = 60000;
+const MAX_BLOCK_MS: u64 = 60000;
+
+
+/// How many replicas must have written a batch before the broker answers.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Acks {
+    /// The broker does not answer, no offsets are returned.
+    None,
+    /// The leader answers once it wrote the batch to its log.
+    Leader,
+    /// The leader answers once every in-sync replica wrote the batch.
+    All,
+}
+
+impl Acks {
+    /// Parses the `acks` values used by the Java client: "0", "1", "all" or "-1".
+    pub fn from_name(name: &str) -> Result<Self> {
+        match name {
+            "0" => Ok(Acks::None),
+            "1" => Ok(Acks::Leader),
+            "all" | "-1" => Ok(Acks::All),
+            _ => Err(KafkaError::InvalidArgument(format!("invalid acks value {}", name))),
+        }
+    }
+
+    pub fn as_i16(self) -> i16 {
+        match self {
+            Acks::None => 0,
+            Acks::Leader => 1,
+            Acks::All => -1,
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct ProducerConfig {
+    pub bootstrap_servers: Vec<String>,
+    pub client_id: String,
+    pub acks: Acks,
+    /// How long the broker waits for the replicas and the client waits for the broker.
+    pub request_timeout_ms: i32,
+    /// The codec of the batches sent, whose cargo feature must be enabled.
+    pub compression: Compression,
+    /// Bytes of records after which a batch is sent without waiting for `linger_ms`.
+    pub batch_size: usize,
+    /// How long a batch waits for more records before it is sent.
+    pub linger_ms: u64,
+    /// Bytes of records the producer holds before `send` has to wait for them to be sent.
+    pub buffer_memory: usize,
+    /// How many times a batch failing with a retriable error is sent again.
+    pub retries: u32,
+    pub retry_backoff_ms: u64,
+    /// How long a record may take from `send` until it is acknowledged, retries included.
+    pub delivery_timeout_ms: u64,
+    pub max_in_flight_requests_per_connection: usize,
+    /// Numbers the batches under a producer id so that the broker writes a batch sent again
+    /// only once. Requires `acks` to be `All`.
+    pub enable_idempotence: bool,
+    /// Identifies the producer across restarts to make transactions possible, enabling
+    /// idempotence as well. A new instance with the same id fences the previous one.
+    pub transactional_id: Option<String>,
+    /// How long the coordinator waits for a transaction to end before aborting it.
+    pub transaction_timeout_ms: i32,
+    /// How long the transactional calls wait for the coordinator, retries included.
+    pub max_block_ms: u64,
+}
+
+impl ProducerConfig {
+    pub fn new(bootstrap_servers: Vec<String>) -> Self {
+        Self {
+            bootstrap_servers,
+            client_id: CLIENT_ID.to_string(),
+            acks: Acks::All,
+            request_timeout_ms: REQUEST_TIMEOUT_MS,
+            compression: Compression::None,
+            batch_size: BATCH_SIZE,
+            linger_ms: LINGER_MS,
+            buffer_memory: BUFFER_MEMORY,
+            retries: RETRIES,
+            retry_backoff_ms: RETRY_BACKOFF_MS,
+            delivery_timeout_ms: DELIVERY_TIMEOUT_MS,
+            max_in_flight_requests_per_connection: MAX_IN_FLIGHT_REQUESTS_PER_CONNECTION,
+            enable_idempotence: false,
+            transactional_id: None,
+            transaction_timeout_ms: TRANSACTION_TIMEOUT_MS,
+            max_block_ms: MAX_BLOCK_MS,
+        }
+    }
+}
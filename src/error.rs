@@ -0,0 +1,115 @@
+use std::fmt;
+use std::io;
+use std::str::Utf8Error;
+
+use crate::protocol::kafka_error_codes::ErrorCode;
+
+pub type Result<T> = std::result::Result<T, KafkaError>;
+
+#[derive(Debug)]
+pub enum KafkaError {
+    /// The socket to a broker failed while reading or writing.
+    Io(io::Error),
+    /// A frame ended before the value being decoded was complete.
+    Truncated,
+    /// The bytes received could not be decoded into the expected message.
+    Codec(String),
+    /// The broker did not follow the protocol, e.g. answered out of order.
+    Protocol(String),
+    /// The broker answered with a non-zero error code.
+    Broker(ErrorCode),
+    /// No answer arrived in the time allowed.
+    Timeout,
+    /// The client and the broker share no version of the api.
+    UnsupportedVersion(i16),
+    /// None of the known brokers could be reached.
+    Connection(String),
+    /// The client was called in a state that does not allow it, e.g. committing a transaction never begun.
+    IllegalState(String),
+    /// The client was given a configuration or an argument it cannot work with.
+    InvalidArgument(String),
+}
+
+impl fmt::Display for KafkaError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            KafkaError::Io(e) => write!(f, "I/O error: {}", e),
+            KafkaError::Truncated => write!(f, "Frame ended before the value could be decoded"),
+            KafkaError::Codec(reason) => write!(f, "Could not decode message: {}", reason),
+            KafkaError::Protocol(reason) => write!(f, "Protocol error: {}", reason),
+            KafkaError::Broker(code) => {
+                write!(f, "{} ({}): {}", code.name(), code.code(), code.description())
+            }
+            KafkaError::Timeout => write!(f, "The request timed out"),
+            KafkaError::UnsupportedVersion(api_key) => {
+                write!(f, "No common version with the broker for api key {}", api_key)
+            }
+            KafkaError::Connection(reason) => write!(f, "Connection error: {}", reason),
+            KafkaError::IllegalState(reason) => write!(f, "Illegal state: {}", reason),
+            KafkaError::InvalidArgument(reason) => write!(f, "Invalid argument: {}", reason),
+        }
+    }
+}
+
+impl KafkaError {
+    /// Whether sending the same request again may succeed.
+    pub fn is_retriable(&self) -> bool {
+        match self {
+            KafkaError::Io(_) | KafkaError::Timeout => true,
+            KafkaError::Broker(code) => code.is_retriable(),
+            _ => false,
+        }
+    }
+
+    /// Whether the cluster metadata should be refreshed before retrying.
+    pub fn needs_metadata_refresh(&self) -> bool {
+        match self {
+            KafkaError::Io(_) => true,
+            KafkaError::Broker(code) => code.needs_metadata_refresh(),
+            _ => false,
+        }
+    }
+}
+
+/// An error is cloned to report the failure of one request to every record it carried,
+/// the io errors keeping their kind and message only.
+impl Clone for KafkaError {
+    fn clone(&self) -> Self {
+        match self {
+            KafkaError::Io(e) => KafkaError::Io(io::Error::new(e.kind(), e.to_string())),
+            KafkaError::Truncated => KafkaError::Truncated,
+            KafkaError::Codec(reason) => KafkaError::Codec(reason.clone()),
+            KafkaError::Protocol(reason) => KafkaError::Protocol(reason.clone()),
+            KafkaError::Broker(code) => KafkaError::Broker(*code),
+            KafkaError::Timeout => KafkaError::Timeout,
+            KafkaError::UnsupportedVersion(api_key) => KafkaError::UnsupportedVersion(*api_key),
+            KafkaError::Connection(reason) => KafkaError::Connection(reason.clone()),
+            KafkaError::IllegalState(reason) => KafkaError::IllegalState(reason.clone()),
+            KafkaError::InvalidArgument(reason) => KafkaError::InvalidArgument(reason.clone()),
+        }
+    }
+}
+
+impl std::error::Error for KafkaError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            KafkaError::Io(e) => Some(e),
+            _ => None,
+        }
+    }
+}
+
+impl From<io::Error> for KafkaError {
+    fn from(e: io::Error) -> Self {
+        match e.kind() {
+            io::ErrorKind::TimedOut | io::ErrorKind::WouldBlock => KafkaError::Timeout,
+            _ => KafkaError::Io(e),
+        }
+    }
+}
+
+impl From<Utf8Error> for KafkaError {
+    fn from(e: Utf8Error) -> Self {
+        KafkaError::Codec(e.to_string())
+    }
+}
@@ -0,0 +1,9 @@
+#[macro_use] extern crate lazy_static;
+
+pub mod protocol;
+pub mod clients;
+pub mod utils;
+pub mod config;
+pub mod error;
+
+pub use error::{KafkaError, Result};
@@ -1,31 +1,16 @@
-#[macro_use] extern crate lazy_static;
-
-mod protocol;
-mod clients;
-mod utils;
-mod config;
-
-
-
-use std::borrow::Borrow;
-use std::collections::HashMap;
-use std::str::from_utf8;
-use std::time::Instant;
-use serde_json::json;
-use serde::{Deserialize, Serialize};
-use protocol::kafka_error_codes::KAFKA_ERRORS;
-
-use crate::clients::consumer_client::ConsumerClient;
-use std::any::Any;
-use regex::Regex;
-use crate::protocol::primitives::{KafkaString};
-
-fn main() {
-    let hosts = vec!["localhost:9092"];
-    let topics = vec!["test2"];
-    let mut consumer = ConsumerClient::new(hosts);
-    consumer.produce("test4", "test".as_bytes().to_vec(), "value".as_bytes().to_vec());
-    // consumer.fetch(topics);
+use my_rust_kafka::clients::producer_client::ProducerClient;
+use my_rust_kafka::clients::producer_record::ProducerRecord;
+use my_rust_kafka::config::producer_config::ProducerConfig;
+use my_rust_kafka::Result;
+
+fn main() -> Result<()> {
+    let config = ProducerConfig::new(vec!["localhost:9092".to_string()]);
+    let mut producer = ProducerClient::new(config)?;
+    let record = ProducerRecord::new("test4".to_string(), Some("test".as_bytes().to_vec()), Some("value".as_bytes().to_vec()));
+    let delivery = producer.send(record)?;
+    producer.flush()?;
+    let metadata = delivery.wait()?;
+    println!("Record written to {}-{} at offset {}", metadata.topic, metadata.partition, metadata.offset);
+    // consumer.fetch(vec!["test2"])?;
+    Ok(())
 }
-
-
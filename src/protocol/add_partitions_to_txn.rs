@@ -0,0 +1,134 @@
+use std::io::Cursor;
+
+use crate::error::Result;
+use crate::protocol::api_keys::ApiKeys;
+use crate::protocol::primitives::{read_array_length, skip_tagged_fields, write_array_length, write_tagged_fields,
+                                  FlexiblePrimitive, KafkaPrimitive, KafkaString};
+use crate::protocol::request::{KafkaRequest, ToBytes};
+use crate::protocol::response::FromBytes;
+
+
+/// AddPartitionsToTxn Request (Version: 0-3) => transactional_id producer_id producer_epoch [topics] TAG_BUFFER
+///   transactional_id => STRING
+///   producer_id => INT64
+///   producer_epoch => INT16
+///   topics => name [partitions] TAG_BUFFER
+///     name => STRING
+///     partitions => INT32
+#[derive(Debug)]
+pub struct AddPartitionsToTxnRequest {
+    transactional_id: KafkaString,
+    producer_id: i64,
+    producer_epoch: i16,
+    topics: Vec<AddPartitionsToTxnTopic>,
+}
+
+#[derive(Debug)]
+struct AddPartitionsToTxnTopic {
+    name: KafkaString,
+    partitions: Vec<i32>,
+}
+
+impl AddPartitionsToTxnRequest {
+    pub fn new(transactional_id: String, producer_id: i64, producer_epoch: i16) -> Self {
+        Self {
+            transactional_id: KafkaString(transactional_id),
+            producer_id,
+            producer_epoch,
+            topics: vec![],
+        }
+    }
+
+    pub fn add_partition(&mut self, topic: &str, partition: i32) {
+        match self.topics.iter_mut().find(|t| t.name.0 == topic) {
+            Some(t) => t.partitions.push(partition),
+            None => self.topics.push(AddPartitionsToTxnTopic {
+                name: KafkaString(topic.to_string()),
+                partitions: vec![partition],
+            }),
+        }
+    }
+}
+
+impl ToBytes for AddPartitionsToTxnRequest {
+    fn get_in_bytes(&self, version: i16) -> Vec<u8> {
+        let flexible = Self::is_flexible(version);
+        let mut buffer = Vec::new();
+        self.transactional_id.write_flexible(&mut buffer, flexible);
+        self.producer_id.write_to_buffer(&mut buffer);
+        self.producer_epoch.write_to_buffer(&mut buffer);
+        write_array_length(&mut buffer, self.topics.len(), flexible);
+        for topic in self.topics.iter() {
+            topic.name.write_flexible(&mut buffer, flexible);
+            write_array_length(&mut buffer, topic.partitions.len(), flexible);
+            for partition in topic.partitions.iter() {
+                partition.write_to_buffer(&mut buffer);
+            }
+            write_tagged_fields(&mut buffer, flexible);
+        }
+        write_tagged_fields(&mut buffer, flexible);
+        buffer
+    }
+}
+
+impl KafkaRequest for AddPartitionsToTxnRequest {
+    type Response = AddPartitionsToTxnResponse;
+    const API_KEY: ApiKeys = ApiKeys::AddPartitionsToTxn;
+    const MIN_VERSION: i16 = 0;
+    const MAX_VERSION: i16 = 3;
+    const FIRST_FLEXIBLE_VERSION: i16 = 3;
+}
+
+/// AddPartitionsToTxn Response (Version: 0-3) => throttle_time_ms [results] TAG_BUFFER
+///   throttle_time_ms => INT32
+///   results => name [results] TAG_BUFFER
+///     name => STRING
+///     results => partition_index error_code TAG_BUFFER
+///       partition_index => INT32
+///       error_code => INT16
+#[derive(Debug)]
+pub struct AddPartitionsToTxnResponse {
+    pub throttle_time_ms: i32,
+    pub results: Vec<AddPartitionsToTxnTopicResult>,
+}
+
+#[derive(Debug)]
+pub struct AddPartitionsToTxnTopicResult {
+    pub name: KafkaString,
+    pub results: Vec<AddPartitionsToTxnPartitionResult>,
+}
+
+#[derive(Debug)]
+pub struct AddPartitionsToTxnPartitionResult {
+    pub partition_index: i32,
+    pub error_code: i16,
+}
+
+impl FromBytes for AddPartitionsToTxnResponse {
+    fn get_from_bytes(buffer: &mut Cursor<Vec<u8>>, version: i16) -> Result<Self> {
+        let flexible = AddPartitionsToTxnRequest::is_flexible(version);
+        let mut response = Self {
+            throttle_time_ms: i32::read_from_buffer(buffer)?,
+            results: vec![],
+        };
+        let topics_length = read_array_length(buffer, flexible)?;
+        for _ in 0..topics_length {
+            let mut topic = AddPartitionsToTxnTopicResult {
+                name: KafkaString::read_flexible(buffer, flexible)?,
+                results: vec![],
+            };
+            let partitions_length = read_array_length(buffer, flexible)?;
+            for _ in 0..partitions_length {
+                topic.results.push(AddPartitionsToTxnPartitionResult {
+                    partition_index: i32::read_from_buffer(buffer)?,
+                    error_code: i16::read_from_buffer(buffer)?,
+                });
+                skip_tagged_fields(buffer, flexible)?;
+            }
+            skip_tagged_fields(buffer, flexible)?;
+            response.results.push(topic);
+        }
+        skip_tagged_fields(buffer, flexible)?;
+        Ok(response)
+    }
+}
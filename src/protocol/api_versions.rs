@@ -1,15 +1,19 @@
 use std::io::{Cursor, Write};
-use crate::protocol::request::{ToBytes, Request};
+use crate::protocol::request::{KafkaRequest, ToBytes, Request};
 use crate::protocol::response::{FromBytes, Response};
-use crate::protocol::primitives::{KafkaPrimitive, KafkaString};
+use crate::protocol::primitives::{read_array_length, skip_tagged_fields, write_tagged_fields, KafkaPrimitive,
+                                  KafkaString, FlexiblePrimitive};
 use std::collections::HashMap;
 use std::net::TcpStream;
 use crate::protocol::api_keys::ApiKeys;
 use crate::protocol::header::RequestHeader;
-use crate::clients::kafka_client::KafkaClient;
+use crate::protocol::kafka_error_codes::check_errors;
+use crate::error::{KafkaError, Result};
 
-const API_VERSIONS_VERSION: i16 = 1;
-const CLIENT_ID: &str = "api-versions-fetcher";
+pub const API_VERSIONS_VERSION: i16 = 1;
+pub const CLIENT_ID: &str = "api-versions-fetcher";
+const CLIENT_SOFTWARE_NAME: &str = env!("CARGO_PKG_NAME");
+const CLIENT_SOFTWARE_VERSION: &str = env!("CARGO_PKG_VERSION");
 
 #[derive(Debug, Copy, Clone)]
 pub struct ApiVersion {
@@ -18,31 +22,88 @@ pub struct ApiVersion {
     pub max_version: i16,
 }
 
+impl ApiVersion {
+    /// Returns the highest version within both the broker's range and the given one.
+    pub fn highest_common(&self, min_version: i16, max_version: i16) -> Option<i16> {
+        let version = self.max_version.min(max_version);
+        if version < self.min_version.max(min_version) {
+            return None;
+        }
+        Some(version)
+    }
+}
 
-pub fn api_versions(stream: &mut TcpStream, correlation_id: i32) -> HashMap<i16, ApiVersion> {
+/// Picks the version to send a request in, given the versions supported by the broker.
+pub fn negotiate_version<T: KafkaRequest>(api_versions: &HashMap<i16, ApiVersion>) -> Result<i16> {
+    let api_key = T::API_KEY as i16;
+    api_versions.get(&api_key)
+        .and_then(|api_version| api_version.highest_common(T::MIN_VERSION, T::MAX_VERSION))
+        .ok_or(KafkaError::UnsupportedVersion(api_key))
+}
+
+
+pub fn api_versions(stream: &mut TcpStream, correlation_id: i32) -> Result<HashMap<i16, ApiVersion>> {
     let api_versions_key = ApiKeys::ApiVersions as i16;
     let header = RequestHeader::new(api_versions_key, API_VERSIONS_VERSION,
                                     correlation_id, CLIENT_ID.to_string());
 
-    let body = ApiVersionsRequest {};
+    let body = ApiVersionsRequest::new();
     let request = Request::new(header, body);
-    stream.write(request.buffer.as_slice()).unwrap();
-    let response = Response::<ApiVersionsResponse>::build(stream);
-    let mut api_keys: HashMap<i16, ApiVersion> = HashMap::new();
-    for api_version in response.body.api_versions {
-        api_keys.insert(api_version.api_key, api_version);
-    }
-    api_keys
+    stream.write_all(request.buffer.as_slice())?;
+    let header_version = ApiVersionsRequest::response_header_version(API_VERSIONS_VERSION);
+    let response = Response::<ApiVersionsResponse>::build(stream, header_version, API_VERSIONS_VERSION)?;
+    response.body.into_map()
 }
 
 // -------- Request --------
 
 #[derive(Debug, Clone)]
-pub struct ApiVersionsRequest {}
+pub struct ApiVersionsRequest {
+    client_software_name: KafkaString,
+    client_software_version: KafkaString,
+}
+
+impl ApiVersionsRequest {
+    pub fn new() -> Self {
+        Self {
+            client_software_name: KafkaString(CLIENT_SOFTWARE_NAME.to_string()),
+            client_software_version: KafkaString(CLIENT_SOFTWARE_VERSION.to_string()),
+        }
+    }
+}
 
+impl Default for ApiVersionsRequest {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// ApiVersions Request (Version: 0-3) => client_software_name client_software_version TAG_BUFFER
+///   client_software_name => COMPACT_STRING (version 3+)
+///   client_software_version => COMPACT_STRING (version 3+)
 impl ToBytes for ApiVersionsRequest {
-    fn get_in_bytes(&self) -> Vec<u8> {
-        Vec::new()
+    fn get_in_bytes(&self, version: i16) -> Vec<u8> {
+        let mut buffer = Vec::new();
+        if Self::is_flexible(version) {
+            self.client_software_name.write_compact(&mut buffer);
+            self.client_software_version.write_compact(&mut buffer);
+            write_tagged_fields(&mut buffer, true);
+        }
+        buffer
+    }
+}
+
+impl KafkaRequest for ApiVersionsRequest {
+    type Response = ApiVersionsResponse;
+    const API_KEY: ApiKeys = ApiKeys::ApiVersions;
+    const MIN_VERSION: i16 = 0;
+    const MAX_VERSION: i16 = 3;
+    const FIRST_FLEXIBLE_VERSION: i16 = 3;
+
+    /// Brokers answer ApiVersions with a version 0 header whatever the request version,
+    /// so that clients can read the error of a version the broker does not support.
+    fn response_header_version(_version: i16) -> i16 {
+        0
     }
 }
 
@@ -64,37 +125,51 @@ impl ApiVersionsResponse {
             throttle_time_ms: 0,
         }
     }
+
+    /// Returns the supported versions keyed by api key.
+    pub fn into_map(self) -> Result<HashMap<i16, ApiVersion>> {
+        check_errors(self.error_code)?;
+        let mut api_keys: HashMap<i16, ApiVersion> = HashMap::new();
+        for api_version in self.api_versions {
+            api_keys.insert(api_version.api_key, api_version);
+        }
+        Ok(api_keys)
+    }
 }
 
-/// ApiVersions Response (Version: 2) => error_code [api_keys] throttle_time_ms
+/// ApiVersions Response (Version: 0-3) => error_code [api_keys] throttle_time_ms TAG_BUFFER
 ///   error_code => INT16
-///   api_keys => api_key min_version max_version
+///   api_keys => api_key min_version max_version TAG_BUFFER
 ///     api_key => INT16
 ///     min_version => INT16
 ///     max_version => INT16
-///   throttle_time_ms => INT32
-
+///   throttle_time_ms => INT32 (version 1+)
 impl FromBytes for ApiVersionsResponse {
-    fn get_from_bytes(buffer: &mut Cursor<Vec<u8>>) -> Self {
+    fn get_from_bytes(buffer: &mut Cursor<Vec<u8>>, version: i16) -> Result<Self> {
+        let flexible = ApiVersionsRequest::is_flexible(version);
         let mut response = Self::new();
-        response.error_code = i16::read_from_buffer(buffer);
+        response.error_code = i16::read_from_buffer(buffer)?;
 
-        let api_keys_len = i32::read_from_buffer(buffer);
-        let mut api_keys: Vec<ApiVersion> = Vec::with_capacity(api_keys_len as usize);
+        let api_keys_len = read_array_length(buffer, flexible)?;
+        let mut api_keys: Vec<ApiVersion> = Vec::new();
         for _ in 0..api_keys_len {
             let mut api_key = ApiVersion {
                 api_key: 0,
                 min_version: 0,
                 max_version: 0,
             };
-            api_key.api_key = i16::read_from_buffer(buffer);
-            api_key.min_version = i16::read_from_buffer(buffer);
-            api_key.max_version = i16::read_from_buffer(buffer);
+            api_key.api_key = i16::read_from_buffer(buffer)?;
+            api_key.min_version = i16::read_from_buffer(buffer)?;
+            api_key.max_version = i16::read_from_buffer(buffer)?;
+            skip_tagged_fields(buffer, flexible)?;
             api_keys.push(api_key);
         }
         response.api_versions = api_keys;
-        response.throttle_time_ms = i32::read_from_buffer(buffer);
-        response
+        if version >= 1 {
+            response.throttle_time_ms = i32::read_from_buffer(buffer)?;
+        }
+        skip_tagged_fields(buffer, flexible)?;
+        Ok(response)
     }
 }
 
@@ -0,0 +1,281 @@
+use crate::error::{KafkaError, Result};
+
+/// The codec of a record batch, stored in bits 0~2 of its attributes.
+///
+/// Each codec other than `None` is only available when the cargo feature of the
+/// same name is enabled; batches using a disabled codec fail to encode or decode.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Compression {
+    #[default]
+    None = 0,
+    Gzip = 1,
+    Snappy = 2,
+    Lz4 = 3,
+    Zstd = 4,
+}
+
+const COMPRESSION_CODEC_MASK: i16 = 0x07;
+
+impl Compression {
+    pub fn from_attributes(attributes: i16) -> Result<Self> {
+        match attributes & COMPRESSION_CODEC_MASK {
+            0 => Ok(Compression::None),
+            1 => Ok(Compression::Gzip),
+            2 => Ok(Compression::Snappy),
+            3 => Ok(Compression::Lz4),
+            4 => Ok(Compression::Zstd),
+            codec => Err(KafkaError::Codec(format!("unknown compression codec {}", codec))),
+        }
+    }
+
+    /// Returns the attributes with their codec bits replaced by this codec.
+    pub fn apply_to_attributes(self, attributes: i16) -> i16 {
+        (attributes & !COMPRESSION_CODEC_MASK) | self as i16
+    }
+
+    /// Parses the `compression.type` names used by the Java client, rejecting the codecs
+    /// whose feature is disabled.
+    pub fn from_name(name: &str) -> Result<Self> {
+        let compression = match name {
+            "none" => Compression::None,
+            "gzip" => Compression::Gzip,
+            "snappy" => Compression::Snappy,
+            "lz4" => Compression::Lz4,
+            "zstd" => Compression::Zstd,
+            _ => return Err(KafkaError::InvalidArgument(format!("unknown compression type {}", name))),
+        };
+        if !compression.is_enabled() {
+            return Err(KafkaError::InvalidArgument(requires_feature(compression)));
+        }
+        Ok(compression)
+    }
+
+    pub fn is_enabled(self) -> bool {
+        match self {
+            Compression::None => true,
+            Compression::Gzip => cfg!(feature = "gzip"),
+            Compression::Snappy => cfg!(feature = "snappy"),
+            Compression::Lz4 => cfg!(feature = "lz4"),
+            Compression::Zstd => cfg!(feature = "zstd"),
+        }
+    }
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Compression::None => "none",
+            Compression::Gzip => "gzip",
+            Compression::Snappy => "snappy",
+            Compression::Lz4 => "lz4",
+            Compression::Zstd => "zstd",
+        }
+    }
+
+    pub fn compress(self, data: &[u8]) -> Result<Vec<u8>> {
+        match self {
+            Compression::None => Ok(data.to_vec()),
+            Compression::Gzip => gzip::compress(data),
+            Compression::Snappy => snappy::compress(data),
+            Compression::Lz4 => lz4::compress(data),
+            Compression::Zstd => zstd::compress(data),
+        }
+    }
+
+    pub fn decompress(self, data: &[u8]) -> Result<Vec<u8>> {
+        match self {
+            Compression::None => Ok(data.to_vec()),
+            Compression::Gzip => gzip::decompress(data),
+            Compression::Snappy => snappy::decompress(data),
+            Compression::Lz4 => lz4::decompress(data),
+            Compression::Zstd => zstd::decompress(data),
+        }
+    }
+}
+
+#[cfg(any(feature = "gzip", feature = "snappy", feature = "lz4", feature = "zstd"))]
+fn codec_error(compression: Compression, e: impl std::fmt::Display) -> KafkaError {
+    KafkaError::Codec(format!("{} codec failed: {}", compression.name(), e))
+}
+
+fn requires_feature(compression: Compression) -> String {
+    format!("{0} compression requires the `{0}` feature", compression.name())
+}
+
+#[cfg(not(all(feature = "gzip", feature = "snappy", feature = "lz4", feature = "zstd")))]
+fn disabled(compression: Compression) -> KafkaError {
+    KafkaError::Codec(requires_feature(compression))
+}
+
+#[cfg(feature = "gzip")]
+mod gzip {
+    use std::io::{Read, Write};
+
+    use flate2::read::MultiGzDecoder;
+    use flate2::write::GzEncoder;
+
+    use super::{codec_error, Compression};
+    use crate::error::Result;
+
+    pub fn compress(data: &[u8]) -> Result<Vec<u8>> {
+        let mut encoder = GzEncoder::new(Vec::new(), flate2::Compression::default());
+        encoder.write_all(data).map_err(|e| codec_error(Compression::Gzip, e))?;
+        encoder.finish().map_err(|e| codec_error(Compression::Gzip, e))
+    }
+
+    pub fn decompress(data: &[u8]) -> Result<Vec<u8>> {
+        let mut decompressed = Vec::new();
+        MultiGzDecoder::new(data).read_to_end(&mut decompressed)
+            .map_err(|e| codec_error(Compression::Gzip, e))?;
+        Ok(decompressed)
+    }
+}
+
+/// The Java client writes snappy in the xerial framing: a magic header followed by
+/// blocks each prefixed by their INT32 length. Other clients write a single raw block.
+#[cfg(feature = "snappy")]
+mod snappy {
+    use std::convert::{TryFrom, TryInto};
+
+    use super::{codec_error, Compression};
+    use crate::error::Result;
+
+    const XERIAL_MAGIC: [u8; 8] = [0x82, b'S', b'N', b'A', b'P', b'P', b'Y', 0];
+    const XERIAL_VERSION: i32 = 1;
+    const XERIAL_MIN_COMPATIBLE_VERSION: i32 = 1;
+    const XERIAL_HEADER_LENGTH: usize = 16;
+    const XERIAL_BLOCK_SIZE: usize = 32 * 1024;
+
+    pub fn compress(data: &[u8]) -> Result<Vec<u8>> {
+        let mut encoder = snap::raw::Encoder::new();
+        let mut compressed = Vec::with_capacity(XERIAL_HEADER_LENGTH + data.len());
+        compressed.extend_from_slice(&XERIAL_MAGIC);
+        compressed.extend_from_slice(&XERIAL_VERSION.to_be_bytes());
+        compressed.extend_from_slice(&XERIAL_MIN_COMPATIBLE_VERSION.to_be_bytes());
+        for block in data.chunks(XERIAL_BLOCK_SIZE) {
+            let compressed_block = encoder.compress_vec(block).map_err(|e| codec_error(Compression::Snappy, e))?;
+            compressed.extend_from_slice(&(compressed_block.len() as i32).to_be_bytes());
+            compressed.extend_from_slice(&compressed_block);
+        }
+        Ok(compressed)
+    }
+
+    pub fn decompress(data: &[u8]) -> Result<Vec<u8>> {
+        let mut decoder = snap::raw::Decoder::new();
+        if !data.starts_with(&XERIAL_MAGIC) {
+            return decoder.decompress_vec(data).map_err(|e| codec_error(Compression::Snappy, e));
+        }
+        if data.len() < XERIAL_HEADER_LENGTH {
+            return Err(codec_error(Compression::Snappy, "truncated xerial header"));
+        }
+        let mut decompressed = Vec::new();
+        let mut position = XERIAL_HEADER_LENGTH;
+        while position < data.len() {
+            let length_bytes: [u8; 4] = data.get(position..position + 4)
+                .and_then(|bytes| bytes.try_into().ok())
+                .ok_or_else(|| codec_error(Compression::Snappy, "truncated xerial block length"))?;
+            let length = i32::from_be_bytes(length_bytes);
+            position += 4;
+            let block = usize::try_from(length).ok()
+                .and_then(|length| data.get(position..position + length))
+                .ok_or_else(|| codec_error(Compression::Snappy, "truncated xerial block"))?;
+            position += block.len();
+            decompressed.extend(decoder.decompress_vec(block).map_err(|e| codec_error(Compression::Snappy, e))?);
+        }
+        Ok(decompressed)
+    }
+}
+
+/// Kafka uses the LZ4 frame format with independent blocks.
+#[cfg(feature = "lz4")]
+mod lz4 {
+    use std::io::{Read, Write};
+
+    use lz4_flex::frame::{FrameDecoder, FrameEncoder};
+
+    use super::{codec_error, Compression};
+    use crate::error::Result;
+
+    pub fn compress(data: &[u8]) -> Result<Vec<u8>> {
+        let mut encoder = FrameEncoder::new(Vec::new());
+        encoder.write_all(data).map_err(|e| codec_error(Compression::Lz4, e))?;
+        encoder.finish().map_err(|e| codec_error(Compression::Lz4, e))
+    }
+
+    pub fn decompress(data: &[u8]) -> Result<Vec<u8>> {
+        let mut decompressed = Vec::new();
+        FrameDecoder::new(data).read_to_end(&mut decompressed)
+            .map_err(|e| codec_error(Compression::Lz4, e))?;
+        Ok(decompressed)
+    }
+}
+
+#[cfg(feature = "zstd")]
+mod zstd {
+    use super::{codec_error, Compression};
+    use crate::error::Result;
+
+    const ZSTD_LEVEL: i32 = 3;
+
+    pub fn compress(data: &[u8]) -> Result<Vec<u8>> {
+        ::zstd::stream::encode_all(data, ZSTD_LEVEL).map_err(|e| codec_error(Compression::Zstd, e))
+    }
+
+    pub fn decompress(data: &[u8]) -> Result<Vec<u8>> {
+        ::zstd::stream::decode_all(data).map_err(|e| codec_error(Compression::Zstd, e))
+    }
+}
+
+#[cfg(not(feature = "gzip"))]
+mod gzip {
+    use super::{disabled, Compression};
+    use crate::error::Result;
+
+    pub fn compress(_data: &[u8]) -> Result<Vec<u8>> {
+        Err(disabled(Compression::Gzip))
+    }
+
+    pub fn decompress(_data: &[u8]) -> Result<Vec<u8>> {
+        Err(disabled(Compression::Gzip))
+    }
+}
+
+#[cfg(not(feature = "snappy"))]
+mod snappy {
+    use super::{disabled, Compression};
+    use crate::error::Result;
+
+    pub fn compress(_data: &[u8]) -> Result<Vec<u8>> {
+        Err(disabled(Compression::Snappy))
+    }
+
+    pub fn decompress(_data: &[u8]) -> Result<Vec<u8>> {
+        Err(disabled(Compression::Snappy))
+    }
+}
+
+#[cfg(not(feature = "lz4"))]
+mod lz4 {
+    use super::{disabled, Compression};
+    use crate::error::Result;
+
+    pub fn compress(_data: &[u8]) -> Result<Vec<u8>> {
+        Err(disabled(Compression::Lz4))
+    }
+
+    pub fn decompress(_data: &[u8]) -> Result<Vec<u8>> {
+        Err(disabled(Compression::Lz4))
+    }
+}
+
+#[cfg(not(feature = "zstd"))]
+mod zstd {
+    use super::{disabled, Compression};
+    use crate::error::Result;
+
+    pub fn compress(_data: &[u8]) -> Result<Vec<u8>> {
+        Err(disabled(Compression::Zstd))
+    }
+
+    pub fn decompress(_data: &[u8]) -> Result<Vec<u8>> {
+        Err(disabled(Compression::Zstd))
+    }
+}
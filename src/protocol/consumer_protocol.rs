@@ -0,0 +1,302 @@
+use std::io::Cursor;
+
+use crate::error::{KafkaError, Result};
+use crate::protocol::primitives::{KafkaNullableBytes, KafkaNullableString, KafkaPrimitive, KafkaString};
+
+
+/// The version of the consumer protocol written, the latest of the Java client. A member
+/// reading a later version decodes the fields it knows of and ignores the others.
+pub const CONSUMER_PROTOCOL_VERSION: i16 = 3;
+
+/// A topic and some of its partitions, as listed in the subscriptions and assignments.
+///
+/// TopicPartition => topic [partitions]
+///   topic => STRING
+///   partitions => INT32
+#[derive(Debug, Clone)]
+pub struct ConsumerProtocolTopicPartition {
+    pub topic: KafkaString,
+    pub partitions: Vec<i32>,
+}
+
+/// The metadata a consumer joins its group with, for each assignment protocol it offers.
+/// It is opaque to the coordinator and decoded by the leader of the group.
+///
+/// ConsumerProtocolSubscription (Version: 0-3) => version [topics] user_data [owned_partitions] generation_id rack_id
+///   version => INT16
+///   topics => STRING
+///   user_data => NULLABLE_BYTES
+///   owned_partitions => topic [partitions] (version 1+)
+///     topic => STRING
+///     partitions => INT32
+///   generation_id => INT32 (version 2+)
+///   rack_id => NULLABLE_STRING (version 3+)
+#[derive(Debug, Clone)]
+pub struct ConsumerProtocolSubscription {
+    pub version: i16,
+    pub topics: Vec<KafkaString>,
+    pub user_data: KafkaNullableBytes,
+    pub owned_partitions: Vec<ConsumerProtocolTopicPartition>,
+    /// The generation the owned partitions were assigned in, -1 when unknown.
+    pub generation_id: i32,
+    pub rack_id: KafkaNullableString,
+}
+
+impl ConsumerProtocolSubscription {
+    pub fn new(topics: &[String], user_data: Option<Vec<u8>>, owned_partitions: &[(String, i32)], generation_id: i32) -> Self {
+        Self {
+            version: CONSUMER_PROTOCOL_VERSION,
+            topics: topics.iter().cloned().map(KafkaString).collect(),
+            user_data: KafkaNullableBytes(user_data),
+            owned_partitions: group_by_topic(owned_partitions),
+            generation_id,
+            rack_id: KafkaNullableString(None),
+        }
+    }
+
+    pub fn topics(&self) -> Vec<String> {
+        self.topics.iter().map(|topic| topic.0.clone()).collect()
+    }
+
+    /// The partitions owned by the member, one entry each.
+    pub fn owned_partitions(&self) -> Vec<(String, i32)> {
+        flatten_topic_partitions(&self.owned_partitions)
+    }
+
+    pub fn get_in_bytes(&self) -> Vec<u8> {
+        let mut buffer = Vec::new();
+        self.version.write_to_buffer(&mut buffer);
+        (self.topics.len() as i32).write_to_buffer(&mut buffer);
+        for topic in self.topics.iter() {
+            topic.write_to_buffer(&mut buffer);
+        }
+        self.user_data.write_to_buffer(&mut buffer);
+        if self.version >= 1 {
+            write_topic_partitions(&mut buffer, &self.owned_partitions);
+        }
+        if self.version >= 2 {
+            self.generation_id.write_to_buffer(&mut buffer);
+        }
+        if self.version >= 3 {
+            self.rack_id.write_to_buffer(&mut buffer);
+        }
+        buffer
+    }
+
+    pub fn from_bytes(metadata: Vec<u8>) -> Result<Self> {
+        let mut buffer = Cursor::new(metadata);
+        let version = read_version(&mut buffer)?;
+        let mut subscription = Self {
+            version,
+            topics: vec![],
+            user_data: KafkaNullableBytes(None),
+            owned_partitions: vec![],
+            generation_id: -1,
+            rack_id: KafkaNullableString(None),
+        };
+        let topics_length = i32::read_from_buffer(&mut buffer)?;
+        for _ in 0..topics_length.max(0) {
+            subscription.topics.push(KafkaString::read_from_buffer(&mut buffer)?);
+        }
+        subscription.user_data = KafkaNullableBytes::read_from_buffer(&mut buffer)?;
+        if version >= 1 {
+            subscription.owned_partitions = read_topic_partitions(&mut buffer)?;
+        }
+        if version >= 2 {
+            subscription.generation_id = i32::read_from_buffer(&mut buffer)?;
+        }
+        if version >= 3 {
+            subscription.rack_id = KafkaNullableString::read_from_buffer(&mut buffer)?;
+        }
+        Ok(subscription)
+    }
+}
+
+/// The partitions the leader assigned to a member, carried as the assignment bytes of SyncGroup.
+///
+/// ConsumerProtocolAssignment (Version: 0-3) => version [assigned_partitions] user_data
+///   version => INT16
+///   assigned_partitions => topic [partitions]
+///     topic => STRING
+///     partitions => INT32
+///   user_data => NULLABLE_BYTES
+#[derive(Debug, Clone)]
+pub struct ConsumerProtocolAssignment {
+    pub version: i16,
+    pub assigned_partitions: Vec<ConsumerProtocolTopicPartition>,
+    pub user_data: KafkaNullableBytes,
+}
+
+impl ConsumerProtocolAssignment {
+    pub fn new(partitions: &[(String, i32)], user_data: Option<Vec<u8>>) -> Self {
+        Self {
+            version: CONSUMER_PROTOCOL_VERSION,
+            assigned_partitions: group_by_topic(partitions),
+            user_data: KafkaNullableBytes(user_data),
+        }
+    }
+
+    /// The partitions assigned, one entry each.
+    pub fn partitions(&self) -> Vec<(String, i32)> {
+        flatten_topic_partitions(&self.assigned_partitions)
+    }
+
+    pub fn get_in_bytes(&self) -> Vec<u8> {
+        let mut buffer = Vec::new();
+        self.version.write_to_buffer(&mut buffer);
+        write_topic_partitions(&mut buffer, &self.assigned_partitions);
+        self.user_data.write_to_buffer(&mut buffer);
+        buffer
+    }
+
+    /// Decodes an assignment, empty bytes standing for no partition at all, which is what
+    /// the coordinator sends to a member the leader left out.
+    pub fn from_bytes(assignment: Vec<u8>) -> Result<Self> {
+        if assignment.is_empty() {
+            return Ok(Self::new(&[], None));
+        }
+        let mut buffer = Cursor::new(assignment);
+        Ok(Self {
+            version: read_version(&mut buffer)?,
+            assigned_partitions: read_topic_partitions(&mut buffer)?,
+            user_data: KafkaNullableBytes::read_from_buffer(&mut buffer)?,
+        })
+    }
+}
+
+/// Reads the version of a subscription or an assignment, decoding a later version than
+/// this client knows of as the latest it knows, like the Java client does.
+fn read_version(buffer: &mut Cursor<Vec<u8>>) -> Result<i16> {
+    let version = i16::read_from_buffer(buffer)?;
+    if version < 0 {
+        return Err(KafkaError::Codec(format!("invalid consumer protocol version {}", version)));
+    }
+    Ok(version.min(CONSUMER_PROTOCOL_VERSION))
+}
+
+pub fn write_topic_partitions(buffer: &mut Vec<u8>, topic_partitions: &[ConsumerProtocolTopicPartition]) {
+    (topic_partitions.len() as i32).write_to_buffer(buffer);
+    for topic_partition in topic_partitions.iter() {
+        topic_partition.topic.write_to_buffer(buffer);
+        (topic_partition.partitions.len() as i32).write_to_buffer(buffer);
+        for partition in topic_partition.partitions.iter() {
+            partition.write_to_buffer(buffer);
+        }
+    }
+}
+
+pub fn read_topic_partitions(buffer: &mut Cursor<Vec<u8>>) -> Result<Vec<ConsumerProtocolTopicPartition>> {
+    let topics_length = i32::read_from_buffer(buffer)?;
+    let mut topic_partitions = Vec::new();
+    for _ in 0..topics_length.max(0) {
+        let topic = KafkaString::read_from_buffer(buffer)?;
+        let partitions_length = i32::read_from_buffer(buffer)?;
+        let mut partitions = Vec::new();
+        for _ in 0..partitions_length.max(0) {
+            partitions.push(i32::read_from_buffer(buffer)?);
+        }
+        topic_partitions.push(ConsumerProtocolTopicPartition { topic, partitions });
+    }
+    Ok(topic_partitions)
+}
+
+/// Groups topic-partitions by topic, keeping the order the topics first appear in.
+pub fn group_by_topic(partitions: &[(String, i32)]) -> Vec<ConsumerProtocolTopicPartition> {
+    let mut topic_partitions: Vec<ConsumerProtocolTopicPartition> = Vec::new();
+    for (topic, partition) in partitions.iter() {
+        match topic_partitions.iter_mut().find(|t| &t.topic.0 == topic) {
+            Some(t) => t.partitions.push(*partition),
+            None => topic_partitions.push(ConsumerProtocolTopicPartition {
+                topic: KafkaString(topic.clone()),
+                partitions: vec![*partition],
+            }),
+        }
+    }
+    topic_partitions
+}
+
+/// Lists topic-partitions grouped by topic as one entry each.
+pub fn flatten_topic_partitions(topic_partitions: &[ConsumerProtocolTopicPartition]) -> Vec<(String, i32)> {
+    topic_partitions.iter()
+        .flat_map(|t| t.partitions.iter().map(move |&partition| (t.topic.0.clone(), partition)))
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn partitions(partitions: &[(&str, i32)]) -> Vec<(String, i32)> {
+        partitions.iter().map(|&(topic, partition)| (topic.to_string(), partition)).collect()
+    }
+
+    #[test]
+    fn subscription_v3_matches_java() {
+        let bytes = vec![
+            0, 3, 0, 0, 0, 1, 0, 2, b't', b'0', 255, 255, 255, 255,
+            0, 0, 0, 1, 0, 2, b't', b'0', 0, 0, 0, 1, 0, 0, 0, 1,
+            0, 0, 0, 4, 255, 255,
+        ];
+        let subscription = ConsumerProtocolSubscription::new(&["t0".to_string()], None, &partitions(&[("t0", 1)]), 4);
+        assert_eq!(subscription.get_in_bytes(), bytes);
+
+        let decoded = ConsumerProtocolSubscription::from_bytes(bytes).unwrap();
+        assert_eq!(decoded.version, 3);
+        assert_eq!(decoded.topics(), vec!["t0".to_string()]);
+        assert_eq!(decoded.user_data.0, None);
+        assert_eq!(decoded.owned_partitions(), partitions(&[("t0", 1)]));
+        assert_eq!(decoded.generation_id, 4);
+        assert_eq!(decoded.rack_id.0, None);
+    }
+
+    #[test]
+    fn subscription_v0_has_no_owned_partitions() {
+        let bytes = vec![0, 0, 0, 0, 0, 2, 0, 2, b't', b'0', 0, 2, b't', b'1', 0, 0, 0, 2, 1, 2];
+        let decoded = ConsumerProtocolSubscription::from_bytes(bytes.clone()).unwrap();
+        assert_eq!(decoded.version, 0);
+        assert_eq!(decoded.topics(), vec!["t0".to_string(), "t1".to_string()]);
+        assert_eq!(decoded.user_data.0, Some(vec![1, 2]));
+        assert!(decoded.owned_partitions().is_empty());
+        assert_eq!(decoded.generation_id, -1);
+        assert_eq!(decoded.get_in_bytes(), bytes);
+    }
+
+    #[test]
+    fn subscription_of_later_version_reads_known_fields() {
+        let mut bytes = ConsumerProtocolSubscription::new(&["t0".to_string()], Some(vec![9]), &[], 2).get_in_bytes();
+        bytes[1] = 4;
+        bytes.extend_from_slice(&[0, 0, 0, 0]);
+        let decoded = ConsumerProtocolSubscription::from_bytes(bytes).unwrap();
+        assert_eq!(decoded.version, 3);
+        assert_eq!(decoded.user_data.0, Some(vec![9]));
+        assert_eq!(decoded.generation_id, 2);
+    }
+
+    #[test]
+    fn assignment_matches_java() {
+        let v3 = vec![0, 3, 0, 0, 0, 1, 0, 2, b't', b'0', 0, 0, 0, 1, 0, 0, 0, 1, 255, 255, 255, 255];
+        assert_eq!(ConsumerProtocolAssignment::new(&partitions(&[("t0", 1)]), None).get_in_bytes(), v3);
+
+        let v0 = vec![
+            0, 0, 0, 0, 0, 2, 0, 2, b't', b'0', 0, 0, 0, 2, 0, 0, 0, 0, 0, 0, 0, 2,
+            0, 2, b't', b'1', 0, 0, 0, 1, 0, 0, 0, 1, 0, 0, 0, 1, 7,
+        ];
+        let decoded = ConsumerProtocolAssignment::from_bytes(v0.clone()).unwrap();
+        assert_eq!(decoded.version, 0);
+        assert_eq!(decoded.partitions(), partitions(&[("t0", 0), ("t0", 2), ("t1", 1)]));
+        assert_eq!(decoded.user_data.0, Some(vec![7]));
+        assert_eq!(decoded.get_in_bytes(), v0);
+    }
+
+    #[test]
+    fn empty_assignment_has_no_partitions() {
+        let decoded = ConsumerProtocolAssignment::from_bytes(vec![]).unwrap();
+        assert!(decoded.partitions().is_empty());
+        assert_eq!(decoded.user_data.0, None);
+    }
+
+    #[test]
+    fn rejects_negative_version() {
+        assert!(ConsumerProtocolAssignment::from_bytes(vec![255, 255, 0, 0, 0, 0, 255, 255, 255, 255]).is_err());
+    }
+}
@@ -1,27 +1,30 @@
 
-use crate::protocol::primitives::{KafkaPrimitive, KafkaString};
-use crate::protocol::request::{ToBytes};
+use crate::protocol::primitives::{read_array_length, skip_tagged_fields, write_array_length, write_tagged_fields,
+                                  FlexiblePrimitive, KafkaPrimitive, KafkaString};
+use crate::protocol::api_keys::ApiKeys;
+use crate::protocol::request::{KafkaRequest, ToBytes};
 use crate::protocol::response::FromBytes;
 use std::io::Cursor;
+use crate::error::Result;
 
 
 const TIMEOUT: i32 = 5000;
-const TOPICS_LENGTH: i32 = 1; // Always creates one topic at a time
+const TOPICS_LENGTH: usize = 1; // Always creates one topic at a time
 
 
-/// CreateTopics Request (Version: 3) => [topics] timeout_ms validate_only
-///   topics => name num_partitions replication_factor [assignments] [configs]
+/// CreateTopics Request (Version: 0-5) => [topics] timeout_ms validate_only TAG_BUFFER
+///   topics => name num_partitions replication_factor [assignments] [configs] TAG_BUFFER
 ///     name => STRING
 ///     num_partitions => INT32
 ///     replication_factor => INT16
-///     assignments => partition_index [broker_ids]
+///     assignments => partition_index [broker_ids] TAG_BUFFER
 ///       partition_index => INT32
 ///       broker_ids => INT32
-///     configs => name value
+///     configs => name value TAG_BUFFER
 ///       name => STRING
 ///       value => NULLABLE_STRING
 ///   timeout_ms => INT32
-///   validate_only => BOOLEAN
+///   validate_only => BOOLEAN (version 1+)
 #[derive(Debug)]
 pub struct CreateTopicRequest {
     topic: TopicRequest,
@@ -62,36 +65,48 @@ impl CreateTopicRequest {
 }
 
 impl ToBytes for CreateTopicRequest {
-    fn get_in_bytes(&self) -> Vec<u8> {
+    fn get_in_bytes(&self, version: i16) -> Vec<u8> {
+        let flexible = Self::is_flexible(version);
         let mut buffer = Vec::new();
-        let assignments_len = self.topic.assignments.len() as i32;
-        let configs_len = self.topic.configs.len() as i32;
 
-        TOPICS_LENGTH.write_to_buffer(&mut buffer);
-        self.topic.name.write_to_buffer(&mut buffer);
+        write_array_length(&mut buffer, TOPICS_LENGTH, flexible);
+        self.topic.name.write_flexible(&mut buffer, flexible);
         self.topic.num_partitions.write_to_buffer(&mut buffer);
         self.topic.replication_factor.write_to_buffer(&mut buffer);
 
-        assignments_len.write_to_buffer(&mut buffer);
+        write_array_length(&mut buffer, self.topic.assignments.len(), flexible);
         for assignment in  self.topic.assignments.iter() {
             assignment.partition_index.write_to_buffer(&mut buffer);
-            let broker_ids_len = assignment.broker_ids.len() as i32;
-            broker_ids_len.write_to_buffer(&mut buffer);
+            write_array_length(&mut buffer, assignment.broker_ids.len(), flexible);
             for i in &assignment.broker_ids {
                 i.write_to_buffer(&mut buffer)
             }
+            write_tagged_fields(&mut buffer, flexible);
         }
-        configs_len.write_to_buffer(&mut buffer);
+        write_array_length(&mut buffer, self.topic.configs.len(), flexible);
         for config in  self.topic.configs.iter() {
-            config.name.write_to_buffer(&mut buffer);
-            config.value.write_to_buffer(&mut buffer);
+            config.name.write_flexible(&mut buffer, flexible);
+            config.value.write_flexible(&mut buffer, flexible);
+            write_tagged_fields(&mut buffer, flexible);
         }
-        TIMEOUT.write_to_buffer(&mut buffer);
-        self.validate_only.write_to_buffer(&mut buffer);
+        write_tagged_fields(&mut buffer, flexible);
+        self.timeout_ms.write_to_buffer(&mut buffer);
+        if version >= 1 {
+            self.validate_only.write_to_buffer(&mut buffer);
+        }
+        write_tagged_fields(&mut buffer, flexible);
         buffer
     }
 }
 
+impl KafkaRequest for CreateTopicRequest {
+    type Response = CreateTopicResponse;
+    const API_KEY: ApiKeys = ApiKeys::CreateTopics;
+    const MIN_VERSION: i16 = 0;
+    const MAX_VERSION: i16 = 5;
+    const FIRST_FLEXIBLE_VERSION: i16 = 5;
+}
+
 
 
 
@@ -109,50 +124,89 @@ impl TopicRequest {
         }
     }
 }
-/// CreateTopics Response (Version: 3) => throttle_time_ms [topics]
-///   throttle_time_ms => INT32
-///   topics => name error_code error_message
+/// CreateTopics Response (Version: 0-5) => throttle_time_ms [topics] TAG_BUFFER
+///   throttle_time_ms => INT32 (version 2+)
+///   topics => name error_code error_message num_partitions replication_factor [configs] TAG_BUFFER
 ///     name => STRING
 ///     error_code => INT16
-///     error_message => NULLABLE_STRING
+///     error_message => NULLABLE_STRING (version 1+)
+///     num_partitions => INT32 (version 5+)
+///     replication_factor => INT16 (version 5+)
+///     configs => name value read_only config_source is_sensitive TAG_BUFFER (version 5+)
+///       name => STRING
+///       value => NULLABLE_STRING
+///       read_only => BOOLEAN
+///       config_source => INT8
+///       is_sensitive => BOOLEAN
 
 #[derive(Debug)]
 pub struct CreateTopicResponse {
-    throttle_time_ms: i32,
-    topics: Vec<TopicRequest>,
+    pub throttle_time_ms: i32,
+    pub topics: Vec<TopicResponse>,
 }
 
-struct TopicResponse {
-    name: KafkaString,
-    error_code: i16,
-    error_message: KafkaString,
+#[derive(Debug)]
+pub struct TopicResponse {
+    pub name: KafkaString,
+    pub error_code: i16,
+    pub error_message: KafkaString,
+    pub num_partitions: i32,
+    pub replication_factor: i16,
+    pub configs: Vec<ConfigResponse>,
 }
 
-impl CreateTopicResponse {
-    fn new() -> Self {
-        Self {
-            throttle_time_ms: 0,
-            topics: vec![],
-        }
-    }
+#[derive(Debug)]
+pub struct ConfigResponse {
+    pub name: KafkaString,
+    pub value: KafkaString,
+    pub read_only: bool,
+    pub config_source: i8,
+    pub is_sensitive: bool,
 }
 
 impl FromBytes for CreateTopicResponse {
-    fn get_from_bytes(buffer: &mut Cursor<Vec<u8>>) -> Self {
+    fn get_from_bytes(buffer: &mut Cursor<Vec<u8>>, version: i16) -> Result<Self> {
+        let flexible = CreateTopicRequest::is_flexible(version);
         let mut response = Self {
-            throttle_time_ms: i32::read_from_buffer(buffer),
+            throttle_time_ms: 0,
             topics: vec![]
         };
-        let topics_length = i32::read_from_buffer(buffer);
-        let mut topics: Vec<TopicResponse> = Vec::with_capacity(topics_length as usize);
+        if version >= 2 {
+            response.throttle_time_ms = i32::read_from_buffer(buffer)?;
+        }
+        let topics_length = read_array_length(buffer, flexible)?;
         for _ in 0..topics_length {
             let mut topic = TopicResponse {
-                name: KafkaString::read_from_buffer(buffer),
-                error_code: i16::read_from_buffer(buffer),
-                error_message: KafkaString::read_from_buffer(buffer),
+                name: KafkaString::read_flexible(buffer, flexible)?,
+                error_code: i16::read_from_buffer(buffer)?,
+                error_message: KafkaString("".to_string()),
+                num_partitions: -1,
+                replication_factor: -1,
+                configs: vec![],
             };
-            topics.push(topic);
+            if version >= 1 {
+                topic.error_message = KafkaString::read_flexible(buffer, flexible)?;
+            }
+            if version >= 5 {
+                topic.num_partitions = i32::read_from_buffer(buffer)?;
+                topic.replication_factor = i16::read_from_buffer(buffer)?;
+                let configs_length = read_array_length(buffer, flexible)?;
+                for _ in 0..configs_length {
+                    let config = ConfigResponse {
+                        name: KafkaString::read_flexible(buffer, flexible)?,
+                        value: KafkaString::read_flexible(buffer, flexible)?,
+                        read_only: bool::read_from_buffer(buffer)?,
+                        config_source: i8::read_from_buffer(buffer)?,
+                        is_sensitive: bool::read_from_buffer(buffer)?,
+                    };
+                    skip_tagged_fields(buffer, flexible)?;
+                    topic.configs.push(config);
+                }
+            }
+            skip_tagged_fields(buffer, flexible)?;
+            response.topics.push(topic);
         }
-        response
+        skip_tagged_fields(buffer, flexible)?;
+        Ok(response)
     }
 }
\ No newline at end of file
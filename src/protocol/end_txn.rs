@@ -0,0 +1,77 @@
+use std::io::Cursor;
+
+use crate::error::Result;
+use crate::protocol::api_keys::ApiKeys;
+use crate::protocol::kafka_error_codes::check_errors;
+use crate::protocol::primitives::{skip_tagged_fields, write_tagged_fields, FlexiblePrimitive, KafkaPrimitive,
+                                  KafkaString};
+use crate::protocol::request::{KafkaRequest, ToBytes};
+use crate::protocol::response::FromBytes;
+
+
+/// EndTxn Request (Version: 0-3) => transactional_id producer_id producer_epoch committed TAG_BUFFER
+///   transactional_id => STRING
+///   producer_id => INT64
+///   producer_epoch => INT16
+///   committed => BOOLEAN
+#[derive(Debug)]
+pub struct EndTxnRequest {
+    transactional_id: KafkaString,
+    producer_id: i64,
+    producer_epoch: i16,
+    committed: bool,
+}
+
+impl EndTxnRequest {
+    pub fn new(transactional_id: String, producer_id: i64, producer_epoch: i16, committed: bool) -> Self {
+        Self {
+            transactional_id: KafkaString(transactional_id),
+            producer_id,
+            producer_epoch,
+            committed,
+        }
+    }
+}
+
+impl ToBytes for EndTxnRequest {
+    fn get_in_bytes(&self, version: i16) -> Vec<u8> {
+        let flexible = Self::is_flexible(version);
+        let mut buffer = Vec::new();
+        self.transactional_id.write_flexible(&mut buffer, flexible);
+        self.producer_id.write_to_buffer(&mut buffer);
+        self.producer_epoch.write_to_buffer(&mut buffer);
+        self.committed.write_to_buffer(&mut buffer);
+        write_tagged_fields(&mut buffer, flexible);
+        buffer
+    }
+}
+
+impl KafkaRequest for EndTxnRequest {
+    type Response = EndTxnResponse;
+    const API_KEY: ApiKeys = ApiKeys::EndTxn;
+    const MIN_VERSION: i16 = 0;
+    const MAX_VERSION: i16 = 3;
+    const FIRST_FLEXIBLE_VERSION: i16 = 3;
+}
+
+/// EndTxn Response (Version: 0-3) => throttle_time_ms error_code TAG_BUFFER
+///   throttle_time_ms => INT32
+///   error_code => INT16
+#[derive(Debug)]
+pub struct EndTxnResponse {
+    pub throttle_time_ms: i32,
+    pub error_code: i16,
+}
+
+impl FromBytes for EndTxnResponse {
+    fn get_from_bytes(buffer: &mut Cursor<Vec<u8>>, version: i16) -> Result<Self> {
+        let flexible = EndTxnRequest::is_flexible(version);
+        let response = Self {
+            throttle_time_ms: i32::read_from_buffer(buffer)?,
+            error_code: i16::read_from_buffer(buffer)?,
+        };
+        skip_tagged_fields(buffer, flexible)?;
+        check_errors(response.error_code)?;
+        Ok(response)
+    }
+}
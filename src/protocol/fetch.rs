@@ -1,14 +1,12 @@
-use std::collections::HashMap;
 use std::io::Cursor;
-use std::str::{from_utf8, Utf8Error};
 
-use crate::clients::kafka_client::PartitionMetadata;
-use crate::protocol::api_keys::ApiKeys::Metadata;
+use crate::error::Result;
+use crate::protocol::api_keys::ApiKeys;
 use crate::protocol::kafka_error_codes::check_errors;
-use crate::protocol::metadata::MetadataResponse;
-use crate::protocol::primitives::{KafkaPrimitive, KafkaString, VarInt};
-use crate::protocol::record::{Record, RecordBatch, RecordHeader};
-use crate::protocol::request::ToBytes;
+use crate::protocol::primitives::{read_array_length, read_bytes_length, skip_tagged_fields, write_array_length,
+                                  write_tagged_fields, FlexiblePrimitive, KafkaPrimitive, KafkaString};
+use crate::protocol::record::RecordBatch;
+use crate::protocol::request::{KafkaRequest, ToBytes};
 use crate::protocol::response::FromBytes;
 
 #[derive(Debug)]
@@ -22,6 +20,7 @@ pub struct FetchRequest {
     session_epoch: i32,
     topics: Vec<TopicRequest>,
     forgotten_topics_data: Vec<ForgottenTopics>,
+    rack_id: KafkaString,
 }
 
 #[derive(Debug)]
@@ -33,7 +32,9 @@ struct TopicRequest {
 #[derive(Debug)]
 struct PartitionFetchRequest {
     partition: i32,
+    current_leader_epoch: i32,
     fetch_offset: i64,
+    last_fetched_epoch: i32,
     log_start_offset: i64,
     partition_max_bytes: i32,
 }
@@ -47,205 +48,245 @@ struct ForgottenTopics {
 
 
 impl FetchRequest {
-    pub fn new(partitions_by_topics: HashMap<String, &Vec<i32>>) -> Self {
-        let mut topic_requests = Vec::new();
-        for (topic, partitions) in partitions_by_topics {
-            let mut topic_request = TopicRequest {
-                topic: KafkaString(topic),
-                partitions: Vec::new(),
-            };
-            for &partition in partitions {
-                let partition_request = PartitionFetchRequest {
-                    partition,
-                    fetch_offset: 0,
-                    log_start_offset: 0,
-                    partition_max_bytes: 10000
-                };
-                topic_request.partitions.push(partition_request);
-            }
-            topic_requests.push(topic_request);
-        }
+    /// With an `isolation_level` of 1, read_committed, the broker returns records up to the
+    /// last stable offset and lists the aborted transactions among them.
+    pub fn new(max_wait_ms: i32, min_bytes: i32, max_bytes: i32, isolation_level: i8) -> Self {
         Self {
             replica_id: -1,
-            max_wait_ms: 5000,
-            min_bytes: 100000,
-            max_bytes: 1000000,
-            isolation_level: 0,
+            max_wait_ms,
+            min_bytes,
+            max_bytes,
+            isolation_level,
             session_id: 0,
             session_epoch: 0,
-            topics: topic_requests,
+            topics: vec![],
             forgotten_topics_data: vec![],
+            rack_id: KafkaString("".to_string()),
         }
     }
+
+    /// Asks for the records of a partition from the given offset on.
+    pub fn add_partition(&mut self, topic: &str, partition: i32, fetch_offset: i64, partition_max_bytes: i32) {
+        let partition = PartitionFetchRequest {
+            partition,
+            current_leader_epoch: -1,
+            fetch_offset,
+            last_fetched_epoch: -1,
+            log_start_offset: -1,
+            partition_max_bytes,
+        };
+        match self.topics.iter_mut().find(|t| t.topic.0 == topic) {
+            Some(t) => t.partitions.push(partition),
+            None => self.topics.push(TopicRequest {
+                topic: KafkaString(topic.to_string()),
+                partitions: vec![partition],
+            }),
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.topics.is_empty()
+    }
 }
 
-/// Fetch Request (Version: 8) => replica_id max_wait_ms min_bytes max_bytes isolation_level session_id session_epoch [topics] [ForgottenTopicsData]
+/// Fetch Request (Version: 4-12) => replica_id max_wait_ms min_bytes max_bytes isolation_level session_id session_epoch [topics] [ForgottenTopicsData] rack_id TAG_BUFFER
 ///   replica_id => INT32
 ///   max_wait_ms => INT32
 ///   min_bytes => INT32
 ///   max_bytes => INT32
 ///   isolation_level => INT8
-///   session_id => INT32
-///   session_epoch => INT32
-///   topics => topic [partitions]
+///   session_id => INT32 (version 7+)
+///   session_epoch => INT32 (version 7+)
+///   topics => topic [partitions] TAG_BUFFER
 ///     topic => STRING
-///     partitions => partition fetch_offset log_start_offset partition_max_bytes
+///     partitions => partition current_leader_epoch fetch_offset last_fetched_epoch log_start_offset partition_max_bytes TAG_BUFFER
 ///       partition => INT32
+///       current_leader_epoch => INT32 (version 9+)
 ///       fetch_offset => INT64
-///       log_start_offset => INT64
+///       last_fetched_epoch => INT32 (version 12+)
+///       log_start_offset => INT64 (version 5+)
 ///       partition_max_bytes => INT32
-///   ForgottenTopicsData => topic [partitions]
+///   ForgottenTopicsData => topic [partitions] TAG_BUFFER (version 7+)
 ///     topic => STRING
 ///     partitions => INT32
+///   rack_id => STRING (version 11+)
 impl ToBytes for FetchRequest {
-    fn get_in_bytes(&self) -> Vec<u8> {
+    fn get_in_bytes(&self, version: i16) -> Vec<u8> {
+        let flexible = Self::is_flexible(version);
         let mut buffer = Vec::new();
         self.replica_id.write_to_buffer(&mut buffer);
         self.max_wait_ms.write_to_buffer(&mut buffer);
         self.min_bytes.write_to_buffer(&mut buffer);
         self.max_bytes.write_to_buffer(&mut buffer);
         self.isolation_level.write_to_buffer(&mut buffer);
-        self.session_id.write_to_buffer(&mut buffer);
-        self.session_epoch.write_to_buffer(&mut buffer);
+        if version >= 7 {
+            self.session_id.write_to_buffer(&mut buffer);
+            self.session_epoch.write_to_buffer(&mut buffer);
+        }
 
-        let topics_len = self.topics.len() as i32;
-        topics_len.write_to_buffer(&mut buffer);
+        write_array_length(&mut buffer, self.topics.len(), flexible);
         for topic in self.topics.iter() {
-            topic.topic.write_to_buffer(&mut buffer);
+            topic.topic.write_flexible(&mut buffer, flexible);
 
-            let partitions_len = topic.partitions.len() as i32;
-            partitions_len.write_to_buffer(&mut buffer);
+            write_array_length(&mut buffer, topic.partitions.len(), flexible);
             for partition in topic.partitions.iter() {
                 partition.partition.write_to_buffer(&mut buffer);
+                if version >= 9 {
+                    partition.current_leader_epoch.write_to_buffer(&mut buffer);
+                }
                 partition.fetch_offset.write_to_buffer(&mut buffer);
-                partition.log_start_offset.write_to_buffer(&mut buffer);
+                if version >= 12 {
+                    partition.last_fetched_epoch.write_to_buffer(&mut buffer);
+                }
+                if version >= 5 {
+                    partition.log_start_offset.write_to_buffer(&mut buffer);
+                }
                 partition.partition_max_bytes.write_to_buffer(&mut buffer);
+                write_tagged_fields(&mut buffer, flexible);
             }
+            write_tagged_fields(&mut buffer, flexible);
         }
 
-        let forgotten_topics_len = self.forgotten_topics_data.len() as i32;
-        forgotten_topics_len.write_to_buffer(&mut buffer);
-        for forgotten_topic in self.forgotten_topics_data.iter() {
-            forgotten_topic.topic.write_to_buffer(&mut buffer);
-            let partitions_len = forgotten_topic.partitions.len() as i32;
-            partitions_len.write_to_buffer(&mut buffer);
-            for partition in forgotten_topic.partitions.iter() {
-                partition.write_to_buffer(&mut buffer);
+        if version >= 7 {
+            write_array_length(&mut buffer, self.forgotten_topics_data.len(), flexible);
+            for forgotten_topic in self.forgotten_topics_data.iter() {
+                forgotten_topic.topic.write_flexible(&mut buffer, flexible);
+                write_array_length(&mut buffer, forgotten_topic.partitions.len(), flexible);
+                for partition in forgotten_topic.partitions.iter() {
+                    partition.write_to_buffer(&mut buffer);
+                }
+                write_tagged_fields(&mut buffer, flexible);
             }
         }
+        if version >= 11 {
+            self.rack_id.write_flexible(&mut buffer, flexible);
+        }
+        write_tagged_fields(&mut buffer, flexible);
         buffer
     }
 }
 
+impl KafkaRequest for FetchRequest {
+    type Response = FetchResponse;
+    const API_KEY: ApiKeys = ApiKeys::Fetch;
+    const MIN_VERSION: i16 = 4;
+    const MAX_VERSION: i16 = 12;
+    const FIRST_FLEXIBLE_VERSION: i16 = 12;
+}
+
 
 #[derive(Debug)]
 pub struct FetchResponse {
-    throttle_time_ms: i32,
-    error_code: i16,
-    session_id: i32,
+    pub throttle_time_ms: i32,
+    pub error_code: i16,
+    pub session_id: i32,
     pub responses: Vec<TopicResponse>,
 }
 
 #[derive(Debug)]
 pub struct TopicResponse {
-    topic: KafkaString,
+    pub topic: KafkaString,
     pub partition_responses: Vec<PartitionResponse>,
 }
 
 #[derive(Debug)]
 pub struct PartitionResponse {
-    partition: i32,
-    error_code: i16,
-    high_watermark: i64,
-    last_stable_offset: i64,
-    log_start_offset: i64,
-    aborted_transactions: Vec<AbortedTransactions>,
-    records: Vec<RecordBatch>,
+    pub partition: i32,
+    pub error_code: i16,
+    pub high_watermark: i64,
+    pub last_stable_offset: i64,
+    pub log_start_offset: i64,
+    pub aborted_transactions: Vec<AbortedTransactions>,
+    pub preferred_read_replica: i32,
+    pub records: Vec<RecordBatch>,
 }
 
 #[derive(Debug)]
-struct AbortedTransactions {
-    producer_id: i64,
-    first_offset: i64,
+pub struct AbortedTransactions {
+    pub producer_id: i64,
+    pub first_offset: i64,
 }
 
-
-impl FetchResponse {
-    pub fn new() -> Self {
-        Self {
-            throttle_time_ms: 0,
-            error_code: 0,
-            session_id: 0,
-            responses: vec![],
-        }
-    }
-}
-
-/// Fetch Response (Version: 8) => throttle_time_ms error_code session_id [responses]
+/// Fetch Response (Version: 4-12) => throttle_time_ms error_code session_id [responses] TAG_BUFFER
 ///   throttle_time_ms => INT32
-///   error_code => INT16
-///   session_id => INT32
-///   responses => topic [partition_responses]
+///   error_code => INT16 (version 7+)
+///   session_id => INT32 (version 7+)
+///   responses => topic [partition_responses] TAG_BUFFER
 ///     topic => STRING
-///     partition_responses => partition error_code high_watermark last_stable_offset log_start_offset [aborted_transactions] record_set
+///     partition_responses => partition error_code high_watermark last_stable_offset log_start_offset [aborted_transactions] preferred_read_replica record_set TAG_BUFFER
 ///       partition => INT32
 ///       error_code => INT16
 ///       high_watermark => INT64
 ///       last_stable_offset => INT64
-///       log_start_offset => INT64
-///       aborted_transactions => producer_id first_offset
+///       log_start_offset => INT64 (version 5+)
+///       aborted_transactions => producer_id first_offset TAG_BUFFER
 ///         producer_id => INT64
 ///         first_offset => INT64
+///       preferred_read_replica => INT32 (version 11+)
 ///       record_set => RECORDS
+///
+/// The error code of each partition is left for the caller to check, so that one partition
+/// failing does not lose the records of the others.
 impl FromBytes for FetchResponse {
-    fn get_from_bytes(buffer: &mut Cursor<Vec<u8>>) -> Self {
+    fn get_from_bytes(buffer: &mut Cursor<Vec<u8>>, version: i16) -> Result<Self> {
+        let flexible = FetchRequest::is_flexible(version);
         let mut response = Self {
-            throttle_time_ms: i32::read_from_buffer(buffer),
-            error_code: i16::read_from_buffer(buffer),
-            session_id: i32::read_from_buffer(buffer),
+            throttle_time_ms: i32::read_from_buffer(buffer)?,
+            error_code: 0,
+            session_id: 0,
             responses: vec![],
         };
-        check_errors(response.error_code);
+        if version >= 7 {
+            response.error_code = i16::read_from_buffer(buffer)?;
+            response.session_id = i32::read_from_buffer(buffer)?;
+        }
+        check_errors(response.error_code)?;
 
-        let topics_len = i32::read_from_buffer(buffer);
+        let topics_len = read_array_length(buffer, flexible)?;
         for _ in 0..topics_len {
             let mut topic = TopicResponse {
-                topic: KafkaString::read_from_buffer(buffer),
+                topic: KafkaString::read_flexible(buffer, flexible)?,
                 partition_responses: vec![],
             };
 
-            let partitions_len = i32::read_from_buffer(buffer);
+            let partitions_len = read_array_length(buffer, flexible)?;
             for _ in 0..partitions_len {
                 let mut partition = PartitionResponse {
-                    partition: i32::read_from_buffer(buffer),
-                    error_code: i16::read_from_buffer(buffer),
-                    high_watermark: i64::read_from_buffer(buffer),
-                    last_stable_offset: i64::read_from_buffer(buffer),
-                    log_start_offset: i64::read_from_buffer(buffer),
+                    partition: i32::read_from_buffer(buffer)?,
+                    error_code: i16::read_from_buffer(buffer)?,
+                    high_watermark: i64::read_from_buffer(buffer)?,
+                    last_stable_offset: i64::read_from_buffer(buffer)?,
+                    log_start_offset: -1,
                     aborted_transactions: vec![],
+                    preferred_read_replica: -1,
                     records: vec![],
                 };
-                check_errors(partition.error_code);
+                if version >= 5 {
+                    partition.log_start_offset = i64::read_from_buffer(buffer)?;
+                }
 
-                let aborted_txn_len = i32::read_from_buffer(buffer);
+                let aborted_txn_len = read_array_length(buffer, flexible)?;
                 for _ in 0..aborted_txn_len {
                     let aborted_txn = AbortedTransactions {
-                        producer_id: i64::read_from_buffer(buffer),
-                        first_offset: i64::read_from_buffer(buffer),
+                        producer_id: i64::read_from_buffer(buffer)?,
+                        first_offset: i64::read_from_buffer(buffer)?,
                     };
+                    skip_tagged_fields(buffer, flexible)?;
                     partition.aborted_transactions.push(aborted_txn);
                 }
-                let records_bytes_len = i32::read_from_buffer(buffer);
-                let end = buffer.position() as i32 + records_bytes_len;
-                while (buffer.position() as i32) < end {
-                    let record = RecordBatch::get_from_bytes(buffer);
-                    partition.records.push(record);
+                if version >= 11 {
+                    partition.preferred_read_replica = i32::read_from_buffer(buffer)?;
                 }
+                let records_len = read_bytes_length(buffer, flexible)?;
+                partition.records = RecordBatch::read_batches(buffer, records_len, version)?;
+                skip_tagged_fields(buffer, flexible)?;
                 topic.partition_responses.push(partition);
             }
+            skip_tagged_fields(buffer, flexible)?;
             response.responses.push(topic);
         }
-        response
+        skip_tagged_fields(buffer, flexible)?;
+        Ok(response)
     }
 }
 
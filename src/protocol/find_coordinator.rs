@@ -1,12 +1,22 @@
-use crate::protocol::primitives::{KafkaString, KafkaPrimitive, KafkaCompactString};
-use crate::protocol::request::ToBytes;
+use crate::protocol::primitives::{skip_tagged_fields, write_tagged_fields, FlexiblePrimitive, KafkaPrimitive,
+                                  KafkaString};
+use crate::protocol::api_keys::ApiKeys;
+use crate::protocol::request::{KafkaRequest, ToBytes};
 use crate::protocol::response::FromBytes;
 use std::io::Cursor;
 use crate::protocol::kafka_error_codes::check_errors;
+use crate::error::Result;
 
-/// FindCoordinator Request (Version: 2) => key key_type
+/// What the coordinator is looked up for, the key being a group id or a transactional id.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum CoordinatorType {
+    Group = 0,
+    Transaction = 1,
+}
+
+/// FindCoordinator Request (Version: 0-3) => key key_type TAG_BUFFER
 /// key => STRING
-/// key_type => INT8
+/// key_type => INT8 (version 1+)
 #[derive(Debug)]
 pub struct FindCoordinatorRequest {
     key: KafkaString,
@@ -14,50 +24,74 @@ pub struct FindCoordinatorRequest {
 }
 
 impl FindCoordinatorRequest {
-    pub fn new() -> Self {
+    pub fn new(key: String, key_type: CoordinatorType) -> Self {
         Self {
-            key: KafkaString("".to_string()),
-            key_type: 0
+            key: KafkaString(key),
+            key_type: key_type as i8
         }
     }
 }
 
 impl ToBytes for FindCoordinatorRequest {
-    fn get_in_bytes(&self) -> Vec<u8> {
+    fn get_in_bytes(&self, version: i16) -> Vec<u8> {
+        let flexible = Self::is_flexible(version);
         let mut buffer = Vec::new();
-        self.key.write_to_buffer(&mut buffer);
-        self.key_type.write_to_buffer(&mut buffer);
+        self.key.write_flexible(&mut buffer, flexible);
+        if version >= 1 {
+            self.key_type.write_to_buffer(&mut buffer);
+        }
+        write_tagged_fields(&mut buffer, flexible);
         buffer
     }
 }
-/// FindCoordinator Response (Version: 3) => throttle_time_ms error_code error_message node_id host port TAG_BUFFER
-///   throttle_time_ms => INT32
+
+impl KafkaRequest for FindCoordinatorRequest {
+    type Response = FindCoordinatorResponse;
+    const API_KEY: ApiKeys = ApiKeys::FindCoordinator;
+    const MIN_VERSION: i16 = 0;
+    const MAX_VERSION: i16 = 3;
+    const FIRST_FLEXIBLE_VERSION: i16 = 3;
+}
+/// FindCoordinator Response (Version: 0-3) => throttle_time_ms error_code error_message node_id host port TAG_BUFFER
+///   throttle_time_ms => INT32 (version 1+)
 ///   error_code => INT16
-///   error_message => COMPACT_NULLABLE_STRING
+///   error_message => NULLABLE_STRING (version 1+)
 ///   node_id => INT32
-///   host => COMPACT_STRING
+///   host => STRING
 ///   port => INT32
 #[derive(Debug)]
 pub struct FindCoordinatorResponse {
-    throttle_time_ms: i32,
-    error_code: i16,
-    error_message: KafkaString,
-    node_id: i32,
-    host: KafkaString,
-    port: i32,
+    pub throttle_time_ms: i32,
+    pub error_code: i16,
+    pub error_message: KafkaString,
+    pub node_id: i32,
+    pub host: KafkaString,
+    pub port: i32,
 }
 
 impl FromBytes for FindCoordinatorResponse {
-    fn get_from_bytes(buffer: &mut Cursor<Vec<u8>>) -> Self {
-        let response = Self {
-            throttle_time_ms: i32::read_from_buffer(buffer),
-            error_code: i16::read_from_buffer(buffer),
-            error_message: KafkaString::read_from_buffer(buffer),
-            node_id: i32::read_from_buffer(buffer),
-            host: KafkaString::read_from_buffer(buffer),
-            port: i32::read_from_buffer(buffer),
+    fn get_from_bytes(buffer: &mut Cursor<Vec<u8>>, version: i16) -> Result<Self> {
+        let flexible = FindCoordinatorRequest::is_flexible(version);
+        let mut response = Self {
+            throttle_time_ms: 0,
+            error_code: 0,
+            error_message: KafkaString("".to_string()),
+            node_id: 0,
+            host: KafkaString("".to_string()),
+            port: 0,
         };
-        check_errors(response.error_code);
-        response
+        if version >= 1 {
+            response.throttle_time_ms = i32::read_from_buffer(buffer)?;
+        }
+        response.error_code = i16::read_from_buffer(buffer)?;
+        if version >= 1 {
+            response.error_message = KafkaString::read_flexible(buffer, flexible)?;
+        }
+        response.node_id = i32::read_from_buffer(buffer)?;
+        response.host = KafkaString::read_flexible(buffer, flexible)?;
+        response.port = i32::read_from_buffer(buffer)?;
+        skip_tagged_fields(buffer, flexible)?;
+        check_errors(response.error_code)?;
+        Ok(response)
     }
 }
\ No newline at end of file
@@ -1,12 +1,21 @@
-use crate::protocol::primitives::{KafkaPrimitive, KafkaString};
+use crate::protocol::primitives::{skip_tagged_fields, write_tagged_fields, KafkaPrimitive, KafkaString};
 use std::io::Cursor;
 use crate::protocol::request::ToBytes;
 use crate::protocol::response::FromBytes;
+use crate::error::Result;
 
 // -------------------------
 // -------- Request --------
 // -------------------------
 
+/// Request Header (Version: 1-2) => request_api_key request_api_version correlation_id client_id TAG_BUFFER
+///   request_api_key => INT16
+///   request_api_version => INT16
+///   correlation_id => INT32
+///   client_id => NULLABLE_STRING
+///   TAG_BUFFER => tagged fields (version 2+)
+///
+/// Version 2 is sent with the flexible versions of a request, see `KafkaRequest::request_header_version`.
 #[derive(Debug, Clone)]
 pub struct RequestHeader {
     pub api_key: i16,
@@ -23,13 +32,16 @@ impl RequestHeader {
 }
 
 impl ToBytes for RequestHeader {
-    fn get_in_bytes(&self) -> Vec<u8> {
+    fn get_in_bytes(&self, version: i16) -> Vec<u8> {
         let mut buffer = Vec::new();
         0_i32.write_to_buffer(&mut buffer); // 4 first bytes reserved for the size of message.
         self.api_key.write_to_buffer(&mut buffer);
         self.api_version.write_to_buffer(&mut buffer);
         self.correlation_id.write_to_buffer(&mut buffer);
-        self.client_id.write_to_buffer(&mut buffer);
+        if version >= 1 {
+            self.client_id.write_to_buffer(&mut buffer);
+        }
+        write_tagged_fields(&mut buffer, version >= 2);
         buffer
     }
 }
@@ -38,6 +50,9 @@ impl ToBytes for RequestHeader {
 // -------- Response -------
 // -------------------------
 
+/// Response Header (Version: 0-1) => correlation_id TAG_BUFFER
+///   correlation_id => INT32
+///   TAG_BUFFER => tagged fields (version 1+)
 #[derive(Debug)]
 pub struct ResponseHeader {
     pub correlation_id: i32,
@@ -53,19 +68,11 @@ impl ResponseHeader {
 }
 
 impl FromBytes for ResponseHeader {
-    fn get_from_bytes(buffer: &mut Cursor<Vec<u8>>) -> Self {
-        let mut response = Self {
-            correlation_id: i32::read_from_buffer(buffer),
+    fn get_from_bytes(buffer: &mut Cursor<Vec<u8>>, version: i16) -> Result<Self> {
+        let header = Self {
+            correlation_id: i32::read_from_buffer(buffer)?,
         };
-        response
+        skip_tagged_fields(buffer, version >= 1)?;
+        Ok(header)
     }
 }
-
-
-
-
-
-
-
-
-
@@ -0,0 +1,83 @@
+use std::io::Cursor;
+
+use crate::error::Result;
+use crate::protocol::api_keys::ApiKeys;
+use crate::protocol::kafka_error_codes::check_errors;
+use crate::protocol::primitives::{skip_tagged_fields, write_tagged_fields, FlexiblePrimitive, KafkaNullableString,
+                                  KafkaPrimitive, KafkaString};
+use crate::protocol::request::{KafkaRequest, ToBytes};
+use crate::protocol::response::FromBytes;
+
+
+/// Heartbeat Request (Version: 0-4) => group_id generation_id member_id group_instance_id TAG_BUFFER
+///   group_id => STRING
+///   generation_id => INT32
+///   member_id => STRING
+///   group_instance_id => NULLABLE_STRING (version 3+)
+#[derive(Debug)]
+pub struct HeartbeatRequest {
+    group_id: KafkaString,
+    generation_id: i32,
+    member_id: KafkaString,
+    group_instance_id: KafkaNullableString,
+}
+
+impl HeartbeatRequest {
+    pub fn new(group_id: String, generation_id: i32, member_id: String, group_instance_id: Option<String>) -> Self {
+        Self {
+            group_id: KafkaString(group_id),
+            generation_id,
+            member_id: KafkaString(member_id),
+            group_instance_id: KafkaNullableString(group_instance_id),
+        }
+    }
+}
+
+impl ToBytes for HeartbeatRequest {
+    fn get_in_bytes(&self, version: i16) -> Vec<u8> {
+        let flexible = Self::is_flexible(version);
+        let mut buffer = Vec::new();
+        self.group_id.write_flexible(&mut buffer, flexible);
+        self.generation_id.write_to_buffer(&mut buffer);
+        self.member_id.write_flexible(&mut buffer, flexible);
+        if version >= 3 {
+            self.group_instance_id.write_flexible(&mut buffer, flexible);
+        }
+        write_tagged_fields(&mut buffer, flexible);
+        buffer
+    }
+}
+
+impl KafkaRequest for HeartbeatRequest {
+    type Response = HeartbeatResponse;
+    const API_KEY: ApiKeys = ApiKeys::Heartbeat;
+    const MIN_VERSION: i16 = 0;
+    const MAX_VERSION: i16 = 4;
+    const FIRST_FLEXIBLE_VERSION: i16 = 4;
+}
+
+/// Heartbeat Response (Version: 0-4) => throttle_time_ms error_code TAG_BUFFER
+///   throttle_time_ms => INT32 (version 1+)
+///   error_code => INT16
+#[derive(Debug)]
+pub struct HeartbeatResponse {
+    pub throttle_time_ms: i32,
+    pub error_code: i16,
+}
+
+impl FromBytes for HeartbeatResponse {
+    fn get_from_bytes(buffer: &mut Cursor<Vec<u8>>, version: i16) -> Result<Self> {
+        let flexible = HeartbeatRequest::is_flexible(version);
+        let mut response = Self {
+            throttle_time_ms: 0,
+            error_code: 0,
+        };
+        if version >= 1 {
+            response.throttle_time_ms = i32::read_from_buffer(buffer)?;
+        }
+        response.error_code = i16::read_from_buffer(buffer)?;
+        skip_tagged_fields(buffer, flexible)?;
+        check_errors(response.error_code)?;
+        Ok(response)
+    }
+}
@@ -0,0 +1,86 @@
+use std::io::Cursor;
+
+use crate::error::Result;
+use crate::protocol::api_keys::ApiKeys;
+use crate::protocol::kafka_error_codes::check_errors;
+use crate::protocol::primitives::{skip_tagged_fields, write_tagged_fields, FlexiblePrimitive, KafkaPrimitive,
+                                  KafkaNullableString};
+use crate::protocol::request::{KafkaRequest, ToBytes};
+use crate::protocol::response::FromBytes;
+
+
+/// InitProducerId Request (Version: 0-4) => transactional_id transaction_timeout_ms producer_id producer_epoch TAG_BUFFER
+///   transactional_id => NULLABLE_STRING
+///   transaction_timeout_ms => INT32
+///   producer_id => INT64 (version 3+)
+///   producer_epoch => INT16 (version 3+)
+#[derive(Debug)]
+pub struct InitProducerIdRequest {
+    transactional_id: KafkaNullableString,
+    transaction_timeout_ms: i32,
+    producer_id: i64,
+    producer_epoch: i16,
+}
+
+impl InitProducerIdRequest {
+    /// Asks for a new producer id, or for the current one of the transactional id.
+    pub fn new(transactional_id: Option<String>, transaction_timeout_ms: i32) -> Self {
+        Self {
+            transactional_id: KafkaNullableString(transactional_id),
+            transaction_timeout_ms,
+            producer_id: -1,
+            producer_epoch: -1,
+        }
+    }
+}
+
+impl ToBytes for InitProducerIdRequest {
+    fn get_in_bytes(&self, version: i16) -> Vec<u8> {
+        let flexible = Self::is_flexible(version);
+        let mut buffer = Vec::new();
+        self.transactional_id.write_flexible(&mut buffer, flexible);
+        self.transaction_timeout_ms.write_to_buffer(&mut buffer);
+        if version >= 3 {
+            self.producer_id.write_to_buffer(&mut buffer);
+            self.producer_epoch.write_to_buffer(&mut buffer);
+        }
+        write_tagged_fields(&mut buffer, flexible);
+        buffer
+    }
+}
+
+impl KafkaRequest for InitProducerIdRequest {
+    type Response = InitProducerIdResponse;
+    const API_KEY: ApiKeys = ApiKeys::InitProducerId;
+    const MIN_VERSION: i16 = 0;
+    const MAX_VERSION: i16 = 4;
+    const FIRST_FLEXIBLE_VERSION: i16 = 2;
+}
+
+/// InitProducerId Response (Version: 0-4) => throttle_time_ms error_code producer_id producer_epoch TAG_BUFFER
+///   throttle_time_ms => INT32
+///   error_code => INT16
+///   producer_id => INT64
+///   producer_epoch => INT16
+#[derive(Debug)]
+pub struct InitProducerIdResponse {
+    pub throttle_time_ms: i32,
+    pub error_code: i16,
+    pub producer_id: i64,
+    pub producer_epoch: i16,
+}
+
+impl FromBytes for InitProducerIdResponse {
+    fn get_from_bytes(buffer: &mut Cursor<Vec<u8>>, version: i16) -> Result<Self> {
+        let flexible = InitProducerIdRequest::is_flexible(version);
+        let response = Self {
+            throttle_time_ms: i32::read_from_buffer(buffer)?,
+            error_code: i16::read_from_buffer(buffer)?,
+            producer_id: i64::read_from_buffer(buffer)?,
+            producer_epoch: i16::read_from_buffer(buffer)?,
+        };
+        skip_tagged_fields(buffer, flexible)?;
+        check_errors(response.error_code)?;
+        Ok(response)
+    }
+}
@@ -1,132 +1,174 @@
-use crate::protocol::request::ToBytes;
+use crate::protocol::api_keys::ApiKeys;
+use crate::protocol::request::{KafkaRequest, ToBytes};
 use crate::protocol::response::FromBytes;
 use std::io::Cursor;
-use crate::protocol::primitives::{KafkaString, KafkaPrimitive, KafkaArray};
-use crate::protocol::kafka_error_codes::check_errors;
+use crate::protocol::primitives::{read_array_length, skip_tagged_fields, write_array_length, write_tagged_fields,
+                                  FlexiblePrimitive, KafkaBytes, KafkaNullableString, KafkaPrimitive, KafkaString};
+use crate::error::Result;
 
 
-/// JoinGroup Request (Version: 3) => group_id session_timeout_ms rebalance_timeout_ms member_id protocol_type [protocols]
+/// JoinGroup Request (Version: 0-7) => group_id session_timeout_ms rebalance_timeout_ms member_id group_instance_id protocol_type [protocols] TAG_BUFFER
 ///   group_id => STRING
 ///   session_timeout_ms => INT32
-///   rebalance_timeout_ms => INT32
+///   rebalance_timeout_ms => INT32 (version 1+)
 ///   member_id => STRING
+///   group_instance_id => NULLABLE_STRING (version 5+)
 ///   protocol_type => STRING
-///   protocols => name metadata
+///   protocols => name metadata TAG_BUFFER
 ///     name => STRING
 ///     metadata => BYTES
 ///
+#[derive(Debug)]
 pub struct JoinGroupRequest {
     group_id: KafkaString,
     session_timeout_ms: i32,
     rebalance_timeout_ms: i32,
     member_id: KafkaString,
+    group_instance_id: KafkaNullableString,
     protocol_type: KafkaString,
     protocols: Vec<Protocol>,
 }
 
+#[derive(Debug)]
 struct Protocol {
     name: KafkaString,
-    metadata: ProtocolMetadata,
-}
-
-struct ProtocolMetadata {
-    version: i16,
-    topics: KafkaArray<String>,
-    user_data: Vec<u8>,
+    metadata: KafkaBytes,
 }
 
 impl JoinGroupRequest {
-    pub fn new(group_id: String) -> Self {
-        let metadata = ProtocolMetadata {
-            version: 3,
-            topics: KafkaArray(vec!["test".to_string()]),
-            user_data: vec![]
-        };
-        let protocols = vec![
-            Protocol { name: KafkaString("".to_string()), metadata }
-        ];
+    /// Joins a group of consumers, with an empty member id the first time.
+    pub fn new(group_id: String, session_timeout_ms: i32, rebalance_timeout_ms: i32, member_id: String,
+               group_instance_id: Option<String>) -> Self {
         Self {
             group_id: KafkaString(group_id),
-            session_timeout_ms: 6000,
-            rebalance_timeout_ms: 6000,
-            member_id: KafkaString("".to_string()),
+            session_timeout_ms,
+            rebalance_timeout_ms,
+            member_id: KafkaString(member_id),
+            group_instance_id: KafkaNullableString(group_instance_id),
             protocol_type: KafkaString("consumer".to_string()),
-            protocols,
+            protocols: vec![],
         }
     }
+
+    /// Offers an assignment protocol, in order of preference, with the subscription encoded
+    /// for it as a `ConsumerProtocolSubscription`.
+    pub fn add_protocol(&mut self, name: &str, metadata: Vec<u8>) {
+        self.protocols.push(Protocol {
+            name: KafkaString(name.to_string()),
+            metadata: KafkaBytes(metadata),
+        });
+    }
 }
 
 
 impl ToBytes for JoinGroupRequest {
-    fn get_in_bytes(&self) -> Vec<u8> {
+    fn get_in_bytes(&self, version: i16) -> Vec<u8> {
+        let flexible = Self::is_flexible(version);
         let mut buffer = Vec::new();
-        self.group_id.write_to_buffer(&mut buffer);
+        self.group_id.write_flexible(&mut buffer, flexible);
         self.session_timeout_ms.write_to_buffer(&mut buffer);
-        self.rebalance_timeout_ms.write_to_buffer(&mut buffer);
-        self.member_id.write_to_buffer(&mut buffer);
-        self.protocol_type.write_to_buffer(&mut buffer);
+        if version >= 1 {
+            self.rebalance_timeout_ms.write_to_buffer(&mut buffer);
+        }
+        self.member_id.write_flexible(&mut buffer, flexible);
+        if version >= 5 {
+            self.group_instance_id.write_flexible(&mut buffer, flexible);
+        }
+        self.protocol_type.write_flexible(&mut buffer, flexible);
 
-        let protocols_len = self.protocols.len() as i32;
-        protocols_len.write_to_buffer(&mut buffer);
+        write_array_length(&mut buffer, self.protocols.len(), flexible);
         for protocol in self.protocols.iter() {
-            protocol.name.write_to_buffer(&mut buffer);
-            protocol.metadata.topics.write_to_buffer(&mut buffer);
-            protocol.metadata.version.write_to_buffer(&mut buffer);
-            protocol.metadata.user_data.write_to_buffer(&mut buffer);
+            protocol.name.write_flexible(&mut buffer, flexible);
+            protocol.metadata.write_flexible(&mut buffer, flexible);
+            write_tagged_fields(&mut buffer, flexible);
         }
+        write_tagged_fields(&mut buffer, flexible);
         buffer
     }
 }
 
-/// JoinGroup Response (Version: 3) => throttle_time_ms error_code generation_id protocol_name leader member_id [members]
-///   throttle_time_ms => INT32
+impl KafkaRequest for JoinGroupRequest {
+    type Response = JoinGroupResponse;
+    const API_KEY: ApiKeys = ApiKeys::JoinGroup;
+    const MIN_VERSION: i16 = 0;
+    const MAX_VERSION: i16 = 7;
+    const FIRST_FLEXIBLE_VERSION: i16 = 6;
+}
+
+/// JoinGroup Response (Version: 0-7) => throttle_time_ms error_code generation_id protocol_type protocol_name leader member_id [members] TAG_BUFFER
+///   throttle_time_ms => INT32 (version 2+)
 ///   error_code => INT16
 ///   generation_id => INT32
+///   protocol_type => NULLABLE_STRING (version 7+)
 ///   protocol_name => STRING
 ///   leader => STRING
 ///   member_id => STRING
-///   members => member_id metadata
+///   members => member_id group_instance_id metadata TAG_BUFFER
 ///     member_id => STRING
+///     group_instance_id => NULLABLE_STRING (version 5+)
 ///     metadata => BYTES
 ///
+/// The error code is left to the caller: a MEMBER_ID_REQUIRED error comes with the member id
+/// to join again with.
 #[derive(Debug)]
 pub struct JoinGroupResponse {
-    throttle_time_ms: i32,
-    error_code: i16,
-    generation_id: i32,
-    protocol_name: KafkaString,
-    leader: KafkaString,
-    member_id: KafkaString,
-    members: Vec<Member>,
+    pub throttle_time_ms: i32,
+    pub error_code: i16,
+    pub generation_id: i32,
+    pub protocol_type: KafkaNullableString,
+    pub protocol_name: KafkaString,
+    pub leader: KafkaString,
+    pub member_id: KafkaString,
+    pub members: Vec<Member>,
 }
 
 #[derive(Debug)]
-struct Member {
-    member_id: KafkaString,
-    metadata: Vec<u8>,
+pub struct Member {
+    pub member_id: KafkaString,
+    pub group_instance_id: KafkaNullableString,
+    pub metadata: Vec<u8>,
 }
 
 impl FromBytes for JoinGroupResponse {
-    fn get_from_bytes(buffer: &mut Cursor<Vec<u8>>) -> Self {
+    fn get_from_bytes(buffer: &mut Cursor<Vec<u8>>, version: i16) -> Result<Self> {
+        let flexible = JoinGroupRequest::is_flexible(version);
         let mut response = Self {
-            throttle_time_ms: i32::read_from_buffer(buffer),
-            error_code: i16::read_from_buffer(buffer),
-            generation_id: i32::read_from_buffer(buffer),
-            protocol_name: KafkaString::read_from_buffer(buffer),
-            leader: KafkaString::read_from_buffer(buffer),
-            member_id: KafkaString::read_from_buffer(buffer),
+            throttle_time_ms: 0,
+            error_code: 0,
+            generation_id: 0,
+            protocol_type: KafkaNullableString(None),
+            protocol_name: KafkaString("".to_string()),
+            leader: KafkaString("".to_string()),
+            member_id: KafkaString("".to_string()),
             members: vec![],
         };
-        check_errors(response.error_code);
+        if version >= 2 {
+            response.throttle_time_ms = i32::read_from_buffer(buffer)?;
+        }
+        response.error_code = i16::read_from_buffer(buffer)?;
+        response.generation_id = i32::read_from_buffer(buffer)?;
+        if version >= 7 {
+            response.protocol_type = KafkaNullableString::read_flexible(buffer, flexible)?;
+        }
+        response.protocol_name = KafkaString::read_flexible(buffer, flexible)?;
+        response.leader = KafkaString::read_flexible(buffer, flexible)?;
+        response.member_id = KafkaString::read_flexible(buffer, flexible)?;
 
-        let members_len = i32::read_from_buffer(buffer);
+        let members_len = read_array_length(buffer, flexible)?;
         for _ in 0..members_len {
-            let member = Member {
-                member_id: KafkaString::read_from_buffer(buffer),
+            let mut member = Member {
+                member_id: KafkaString::read_flexible(buffer, flexible)?,
+                group_instance_id: KafkaNullableString(None),
                 metadata: vec![],
             };
+            if version >= 5 {
+                member.group_instance_id = KafkaNullableString::read_flexible(buffer, flexible)?;
+            }
+            member.metadata = KafkaBytes::read_flexible(buffer, flexible)?.0;
+            skip_tagged_fields(buffer, flexible)?;
             response.members.push(member);
         }
-        response
+        skip_tagged_fields(buffer, flexible)?;
+        Ok(response)
     }
-}
\ No newline at end of file
+}
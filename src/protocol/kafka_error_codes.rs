@@ -1,130 +1,431 @@
 use std::collections::HashMap;
-use std::process::exit;
+use std::convert::TryFrom;
+
+use crate::error::{KafkaError, Result};
+use self::ErrorClass::*;
+
+/// Error codes a broker can answer with, as listed in the protocol guide.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum ErrorCode {
+    UnknownServerError,
+    None,
+    OffsetOutOfRange,
+    CorruptMessage,
+    UnknownTopicOrPartition,
+    InvalidFetchSize,
+    LeaderNotAvailable,
+    NotLeaderOrFollower,
+    RequestTimedOut,
+    BrokerNotAvailable,
+    ReplicaNotAvailable,
+    MessageTooLarge,
+    StaleControllerEpoch,
+    OffsetMetadataTooLarge,
+    NetworkException,
+    CoordinatorLoadInProgress,
+    CoordinatorNotAvailable,
+    NotCoordinator,
+    InvalidTopicException,
+    RecordListTooLarge,
+    NotEnoughReplicas,
+    NotEnoughReplicasAfterAppend,
+    InvalidRequiredAcks,
+    IllegalGeneration,
+    InconsistentGroupProtocol,
+    InvalidGroupId,
+    UnknownMemberId,
+    InvalidSessionTimeout,
+    RebalanceInProgress,
+    InvalidCommitOffsetSize,
+    TopicAuthorizationFailed,
+    GroupAuthorizationFailed,
+    ClusterAuthorizationFailed,
+    InvalidTimestamp,
+    UnsupportedSaslMechanism,
+    IllegalSaslState,
+    UnsupportedVersion,
+    TopicAlreadyExists,
+    InvalidPartitions,
+    InvalidReplicationFactor,
+    InvalidReplicaAssignment,
+    InvalidConfig,
+    NotController,
+    InvalidRequest,
+    UnsupportedForMessageFormat,
+    PolicyViolation,
+    OutOfOrderSequenceNumber,
+    DuplicateSequenceNumber,
+    InvalidProducerEpoch,
+    InvalidTxnState,
+    InvalidProducerIdMapping,
+    InvalidTransactionTimeout,
+    ConcurrentTransactions,
+    TransactionCoordinatorFenced,
+    TransactionalIdAuthorizationFailed,
+    SecurityDisabled,
+    OperationNotAttempted,
+    KafkaStorageError,
+    LogDirNotFound,
+    SaslAuthenticationFailed,
+    UnknownProducerId,
+    ReassignmentInProgress,
+    DelegationTokenAuthDisabled,
+    DelegationTokenNotFound,
+    DelegationTokenOwnerMismatch,
+    DelegationTokenRequestNotAllowed,
+    DelegationTokenAuthorizationFailed,
+    DelegationTokenExpired,
+    InvalidPrincipalType,
+    NonEmptyGroup,
+    GroupIdNotFound,
+    FetchSessionIdNotFound,
+    InvalidFetchSessionEpoch,
+    ListenerNotFound,
+    TopicDeletionDisabled,
+    FencedLeaderEpoch,
+    UnknownLeaderEpoch,
+    UnsupportedCompressionType,
+    StaleBrokerEpoch,
+    OffsetNotAvailable,
+    MemberIdRequired,
+    PreferredLeaderNotAvailable,
+    GroupMaxSizeReached,
+    FencedInstanceId,
+    EligibleLeadersNotAvailable,
+    ElectionNotNeeded,
+    NoReassignmentInProgress,
+    GroupSubscribedToTopic,
+    InvalidRecord,
+    UnstableOffsetCommit,
+    ThrottlingQuotaExceeded,
+    ProducerFenced,
+    ResourceNotFound,
+    DuplicateResource,
+    UnacceptableCredential,
+    InconsistentVoterSet,
+    InvalidUpdateVersion,
+    FeatureUpdateFailed,
+    /// A code missing from the table above, e.g. added by a later broker, kept as received.
+    Unknown(i16),
+}
+
+/// How a client should react when a broker answers with an error code.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ErrorClass {
+    /// The request may succeed if it is sent again as is.
+    Retriable,
+    /// The request may succeed once the client refreshed its cluster metadata.
+    RefreshMetadata,
+    /// Retrying cannot help, but the client itself is still usable.
+    NonRetriable,
+    /// The producer can not be used anymore and has to be closed.
+    Fatal,
+}
 
 #[derive(Debug)]
-pub struct KafkaError {
-    pub code: i16,
+pub struct KafkaErrorCode {
+    pub code: ErrorCode,
     pub error: String,
+    pub class: ErrorClass,
     pub description: String,
 }
 
-pub fn check_errors(error_code: i16) {
+impl ErrorCode {
+    /// The code as sent by the broker.
+    pub fn code(self) -> i16 {
+        match self {
+            ErrorCode::UnknownServerError => -1,
+            ErrorCode::None => 0,
+            ErrorCode::OffsetOutOfRange => 1,
+            ErrorCode::CorruptMessage => 2,
+            ErrorCode::UnknownTopicOrPartition => 3,
+            ErrorCode::InvalidFetchSize => 4,
+            ErrorCode::LeaderNotAvailable => 5,
+            ErrorCode::NotLeaderOrFollower => 6,
+            ErrorCode::RequestTimedOut => 7,
+            ErrorCode::BrokerNotAvailable => 8,
+            ErrorCode::ReplicaNotAvailable => 9,
+            ErrorCode::MessageTooLarge => 10,
+            ErrorCode::StaleControllerEpoch => 11,
+            ErrorCode::OffsetMetadataTooLarge => 12,
+            ErrorCode::NetworkException => 13,
+            ErrorCode::CoordinatorLoadInProgress => 14,
+            ErrorCode::CoordinatorNotAvailable => 15,
+            ErrorCode::NotCoordinator => 16,
+            ErrorCode::InvalidTopicException => 17,
+            ErrorCode::RecordListTooLarge => 18,
+            ErrorCode::NotEnoughReplicas => 19,
+            ErrorCode::NotEnoughReplicasAfterAppend => 20,
+            ErrorCode::InvalidRequiredAcks => 21,
+            ErrorCode::IllegalGeneration => 22,
+            ErrorCode::InconsistentGroupProtocol => 23,
+            ErrorCode::InvalidGroupId => 24,
+            ErrorCode::UnknownMemberId => 25,
+            ErrorCode::InvalidSessionTimeout => 26,
+            ErrorCode::RebalanceInProgress => 27,
+            ErrorCode::InvalidCommitOffsetSize => 28,
+            ErrorCode::TopicAuthorizationFailed => 29,
+            ErrorCode::GroupAuthorizationFailed => 30,
+            ErrorCode::ClusterAuthorizationFailed => 31,
+            ErrorCode::InvalidTimestamp => 32,
+            ErrorCode::UnsupportedSaslMechanism => 33,
+            ErrorCode::IllegalSaslState => 34,
+            ErrorCode::UnsupportedVersion => 35,
+            ErrorCode::TopicAlreadyExists => 36,
+            ErrorCode::InvalidPartitions => 37,
+            ErrorCode::InvalidReplicationFactor => 38,
+            ErrorCode::InvalidReplicaAssignment => 39,
+            ErrorCode::InvalidConfig => 40,
+            ErrorCode::NotController => 41,
+            ErrorCode::InvalidRequest => 42,
+            ErrorCode::UnsupportedForMessageFormat => 43,
+            ErrorCode::PolicyViolation => 44,
+            ErrorCode::OutOfOrderSequenceNumber => 45,
+            ErrorCode::DuplicateSequenceNumber => 46,
+            ErrorCode::InvalidProducerEpoch => 47,
+            ErrorCode::InvalidTxnState => 48,
+            ErrorCode::InvalidProducerIdMapping => 49,
+            ErrorCode::InvalidTransactionTimeout => 50,
+            ErrorCode::ConcurrentTransactions => 51,
+            ErrorCode::TransactionCoordinatorFenced => 52,
+            ErrorCode::TransactionalIdAuthorizationFailed => 53,
+            ErrorCode::SecurityDisabled => 54,
+            ErrorCode::OperationNotAttempted => 55,
+            ErrorCode::KafkaStorageError => 56,
+            ErrorCode::LogDirNotFound => 57,
+            ErrorCode::SaslAuthenticationFailed => 58,
+            ErrorCode::UnknownProducerId => 59,
+            ErrorCode::ReassignmentInProgress => 60,
+            ErrorCode::DelegationTokenAuthDisabled => 61,
+            ErrorCode::DelegationTokenNotFound => 62,
+            ErrorCode::DelegationTokenOwnerMismatch => 63,
+            ErrorCode::DelegationTokenRequestNotAllowed => 64,
+            ErrorCode::DelegationTokenAuthorizationFailed => 65,
+            ErrorCode::DelegationTokenExpired => 66,
+            ErrorCode::InvalidPrincipalType => 67,
+            ErrorCode::NonEmptyGroup => 68,
+            ErrorCode::GroupIdNotFound => 69,
+            ErrorCode::FetchSessionIdNotFound => 70,
+            ErrorCode::InvalidFetchSessionEpoch => 71,
+            ErrorCode::ListenerNotFound => 72,
+            ErrorCode::TopicDeletionDisabled => 73,
+            ErrorCode::FencedLeaderEpoch => 74,
+            ErrorCode::UnknownLeaderEpoch => 75,
+            ErrorCode::UnsupportedCompressionType => 76,
+            ErrorCode::StaleBrokerEpoch => 77,
+            ErrorCode::OffsetNotAvailable => 78,
+            ErrorCode::MemberIdRequired => 79,
+            ErrorCode::PreferredLeaderNotAvailable => 80,
+            ErrorCode::GroupMaxSizeReached => 81,
+            ErrorCode::FencedInstanceId => 82,
+            ErrorCode::EligibleLeadersNotAvailable => 83,
+            ErrorCode::ElectionNotNeeded => 84,
+            ErrorCode::NoReassignmentInProgress => 85,
+            ErrorCode::GroupSubscribedToTopic => 86,
+            ErrorCode::InvalidRecord => 87,
+            ErrorCode::UnstableOffsetCommit => 88,
+            ErrorCode::ThrottlingQuotaExceeded => 89,
+            ErrorCode::ProducerFenced => 90,
+            ErrorCode::ResourceNotFound => 91,
+            ErrorCode::DuplicateResource => 92,
+            ErrorCode::UnacceptableCredential => 93,
+            ErrorCode::InconsistentVoterSet => 94,
+            ErrorCode::InvalidUpdateVersion => 95,
+            ErrorCode::FeatureUpdateFailed => 96,
+            ErrorCode::Unknown(code) => code,
+        }
+    }
+
+    /// The code answered by a broker, kept as `Unknown` when this client does not know it.
+    pub fn from_code(code: i16) -> Self {
+        ErrorCode::try_from(code).unwrap_or(ErrorCode::Unknown(code))
+    }
+
+    /// The name, description and class of the code, `None` for an unknown one.
+    pub fn info(self) -> Option<&'static KafkaErrorCode> {
+        KAFKA_ERRORS.get(&self.code())
+    }
+
+    pub fn name(self) -> &'static str {
+        self.info().map_or("UNKNOWN", |info| &info.error)
+    }
+
+    pub fn description(self) -> &'static str {
+        self.info().map_or("The broker answered with an error code this client does not know.", |info| &info.description)
+    }
+
+    /// Unknown codes are taken as non-retriable.
+    pub fn class(self) -> ErrorClass {
+        self.info().map_or(NonRetriable, |info| info.class)
+    }
+
+    pub fn is_retriable(self) -> bool {
+        matches!(self.class(), Retriable | RefreshMetadata)
+    }
+
+    pub fn needs_metadata_refresh(self) -> bool {
+        self.class() == RefreshMetadata
+    }
+
+    pub fn is_fatal_for_producer(self) -> bool {
+        self.class() == Fatal
+    }
+}
+
+impl TryFrom<i16> for ErrorCode {
+    type Error = KafkaError;
+
+    fn try_from(code: i16) -> Result<Self> {
+        KAFKA_ERRORS.get(&code)
+            .map(|error| error.code)
+            .ok_or_else(|| KafkaError::Codec(format!("Unknown error code {}", code)))
+    }
+}
+
+pub fn check_errors(error_code: i16) -> Result<()> {
     if error_code != 0 {
-        let kafka_error = KAFKA_ERRORS.get(&error_code).unwrap();
-        eprintln!("Error: {}\nReason: {}", kafka_error.error, kafka_error.description);
-        exit(1);
+        return Err(KafkaError::Broker(ErrorCode::from_code(error_code)));
     }
+    Ok(())
 }
 
-fn kafka_error(code: i16, error: &str, description: &str) -> KafkaError {
-    KafkaError {
+fn kafka_error(code: ErrorCode, error: &str, class: ErrorClass, description: &str) -> KafkaErrorCode {
+    KafkaErrorCode {
         code,
         error: error.to_string(),
+        class,
         description: description.to_string(),
     }
 }
 
 lazy_static! {
-    pub static ref KAFKA_ERRORS: HashMap<i16, KafkaError> = {
-        let mut m = HashMap::new();
-        m.insert(-1, kafka_error(-1, "UNKNOWN_SERVER_ERROR", "The server experienced an unexpected error when processing the request."));
-        m.insert(0, kafka_error(0, "NONE", ""));
-        m.insert(1, kafka_error(1, "OFFSET_OUT_OF_RANGE", "The requested offset is not within the range of offsets maintained by the server."));
-        m.insert(2, kafka_error(2, "CORRUPT_MESSAGE", "This message has failed its CRC checksum, exceeds the valid size, has a null key for a compacted topic, or is otherwise corrupt."));
-        m.insert(3, kafka_error(3, "UNKNOWN_TOPIC_OR_PARTITION", "This server does not host this topic-partition."));
-        m.insert(4, kafka_error(4, "INVALID_FETCH_SIZE", "The requested fetch size is invalid."));
-        m.insert(5, kafka_error(5, "LEADER_NOT_AVAILABLE", "There is no leader for this topic-partition as we are in the middle of a leadership election."));
-        m.insert(6, kafka_error(6, "NOT_LEADER_OR_FOLLOWER", "For requests intended only for the leader, this error indicates that the broker is not the current leader. For requests intended for any replica, this error indicates that the broker is not a replica of the topic partition."));
-        m.insert(7, kafka_error(7, "REQUEST_TIMED_OUT", "The request timed out."));
-        m.insert(8, kafka_error(8, "BROKER_NOT_AVAILABLE", "The broker is not available."));
-        m.insert(9, kafka_error(9, "REPLICA_NOT_AVAILABLE", "The replica is not available for the requested topic-partition. Produce/Fetch requests and other requests intended only for the leader or follower return NOT_LEADER_OR_FOLLOWER if the broker is not a replica of the topic-partition."));
-        m.insert(10, kafka_error(10, "MESSAGE_TOO_LARGE", "The request included a message larger than the max message size the server will accept."));
-        m.insert(11, kafka_error(11, "STALE_CONTROLLER_EPOCH", "The controller moved to another broker."));
-        m.insert(12, kafka_error(12, "OFFSET_METADATA_TOO_LARGE", "The metadata field of the offset request was too large."));
-        m.insert(13, kafka_error(13, "NETWORK_EXCEPTION", "The server disconnected before a response was received."));
-        m.insert(14, kafka_error(14, "COORDINATOR_LOAD_IN_PROGRESS", "The coordinator is loading and hence can't process requests."));
-        m.insert(15, kafka_error(15, "COORDINATOR_NOT_AVAILABLE", "The coordinator is not available."));
-        m.insert(16, kafka_error(16, "NOT_COORDINATOR", "This is not the correct coordinator."));
-        m.insert(17, kafka_error(17, "INVALID_TOPIC_EXCEPTION", "The request attempted to perform an operation on an invalid topic."));
-        m.insert(18, kafka_error(18, "RECORD_LIST_TOO_LARGE", "The request included message batch larger than the configured segment size on the server."));
-        m.insert(19, kafka_error(19, "NOT_ENOUGH_REPLICAS", "Messages are rejected since there are fewer in-sync replicas than required."));
-        m.insert(20, kafka_error(20, "NOT_ENOUGH_REPLICAS_AFTER_APPEND", "Messages are written to the log, but to fewer in-sync replicas than required."));
-        m.insert(21, kafka_error(21, "INVALID_REQUIRED_ACKS", "Produce request specified an invalid value for required acks."));
-        m.insert(22, kafka_error(22, "ILLEGAL_GENERATION", "Specified group generation id is not valid."));
-        m.insert(23, kafka_error(23, "INCONSISTENT_GROUP_PROTOCOL", "The group member's supported protocols are incompatible with those of existing members or first group member tried to join with empty protocol type or empty protocol list."));
-        m.insert(24, kafka_error(24, "INVALID_GROUP_ID", "The configured groupId is invalid."));
-        m.insert(25, kafka_error(25, "UNKNOWN_MEMBER_ID", "The coordinator is not aware of this member."));
-        m.insert(26, kafka_error(26, "INVALID_SESSION_TIMEOUT", "The session timeout is not within the range allowed by the broker (as configured by group.min.session.timeout.ms and group.max.session.timeout.ms)."));
-        m.insert(27, kafka_error(27, "REBALANCE_IN_PROGRESS", "The group is rebalancing, so a rejoin is needed."));
-        m.insert(28, kafka_error(28, "INVALID_COMMIT_OFFSET_SIZE", "The committing offset data size is not valid."));
-        m.insert(29, kafka_error(29, "TOPIC_AUTHORIZATION_FAILED", "Topic authorization failed."));
-        m.insert(30, kafka_error(30, "GROUP_AUTHORIZATION_FAILED", "Group authorization failed."));
-        m.insert(31, kafka_error(31, "CLUSTER_AUTHORIZATION_FAILED", "Cluster authorization failed."));
-        m.insert(32, kafka_error(32, "INVALID_TIMESTAMP", "The timestamp of the message is out of acceptable range."));
-        m.insert(33, kafka_error(33, "UNSUPPORTED_SASL_MECHANISM", "The broker does not support the requested SASL mechanism."));
-        m.insert(34, kafka_error(34, "ILLEGAL_SASL_STATE", "Request is not valid given the current SASL state."));
-        m.insert(35, kafka_error(35, "UNSUPPORTED_VERSION", "The version of API is not supported."));
-        m.insert(36, kafka_error(36, "TOPIC_ALREADY_EXISTS", "Topic with this name already exists."));
-        m.insert(37, kafka_error(37, "INVALID_PARTITIONS", "Number of partitions is below 1."));
-        m.insert(38, kafka_error(38, "INVALID_REPLICATION_FACTOR", "Replication factor is below 1 or larger than the number of available brokers."));
-        m.insert(39, kafka_error(39, "INVALID_REPLICA_ASSIGNMENT", "Replica assignment is invalid."));
-        m.insert(40, kafka_error(40, "INVALID_CONFIG", "Configuration is invalid."));
-        m.insert(41, kafka_error(41, "NOT_CONTROLLER", "This is not the correct controller for this cluster."));
-        m.insert(42, kafka_error(42, "INVALID_REQUEST", "This most likely occurs because of a request being malformed by the client library or the message was sent to an incompatible broker. See the broker logs for more details."));
-        m.insert(43, kafka_error(43, "UNSUPPORTED_FOR_MESSAGE_FORMAT", "The message format version on the broker does not support the request."));
-        m.insert(44, kafka_error(44, "POLICY_VIOLATION", "Request parameters do not satisfy the configured policy."));
-        m.insert(45, kafka_error(45, "OUT_OF_ORDER_SEQUENCE_NUMBER", "The broker received an out of order sequence number."));
-        m.insert(46, kafka_error(46, "DUPLICATE_SEQUENCE_NUMBER", "The broker received a duplicate sequence number."));
-        m.insert(47, kafka_error(47, "INVALID_PRODUCER_EPOCH", "Producer attempted to produce with an old epoch."));
-        m.insert(48, kafka_error(48, "INVALID_TXN_STATE", "The producer attempted a transactional operation in an invalid state."));
-        m.insert(49, kafka_error(49, "INVALID_PRODUCER_ID_MAPPING", "The producer attempted to use a producer id which is not currently assigned to its transactional id."));
-        m.insert(50, kafka_error(50, "INVALID_TRANSACTION_TIMEOUT", "The transaction timeout is larger than the maximum value allowed by the broker (as configured by transaction.max.timeout.ms)."));
-        m.insert(51, kafka_error(51, "CONCURRENT_TRANSACTIONS", "The producer attempted to update a transaction while another concurrent operation on the same transaction was ongoing."));
-        m.insert(52, kafka_error(52, "TRANSACTION_COORDINATOR_FENCED", "Indicates that the transaction coordinator sending a WriteTxnMarker is no longer the current coordinator for a given producer."));
-        m.insert(53, kafka_error(53, "TRANSACTIONAL_ID_AUTHORIZATION_FAILED", "Transactional Id authorization failed."));
-        m.insert(54, kafka_error(54, "SECURITY_DISABLED", "Security features are disabled."));
-        m.insert(55, kafka_error(55, "OPERATION_NOT_ATTEMPTED", "The broker did not attempt to execute this operation. This may happen for batched RPCs where some operations in the batch failed, causing the broker to respond without trying the rest."));
-        m.insert(56, kafka_error(56, "KAFKA_STORAGE_ERROR", "Disk error when trying to access log file on the disk."));
-        m.insert(57, kafka_error(57, "LOG_DIR_NOT_FOUND", "The user-specified log directory is not found in the broker config."));
-        m.insert(58, kafka_error(58, "SASL_AUTHENTICATION_FAILED", "SASL Authentication failed."));
-        m.insert(59, kafka_error(59, "UNKNOWN_PRODUCER_ID", "This exception is raised by the broker if it could not locate the producer metadata associated with the producerId in question. This could happen if, for instance, the producer's records were deleted because their retention time had elapsed. Once the last records of the producerId are removed, the producer's metadata is removed from the broker, and future appends by the producer will return this exception."));
-        m.insert(60, kafka_error(60, "REASSIGNMENT_IN_PROGRESS", "A partition reassignment is in progress."));
-        m.insert(61, kafka_error(61, "DELEGATION_TOKEN_AUTH_DISABLED", "Delegation Token feature is not enabled."));
-        m.insert(62, kafka_error(62, "DELEGATION_TOKEN_NOT_FOUND", "Delegation Token is not found on server."));
-        m.insert(63, kafka_error(63, "DELEGATION_TOKEN_OWNER_MISMATCH", "Specified Principal is not valid Owner/Renewer."));
-        m.insert(64, kafka_error(64, "DELEGATION_TOKEN_REQUEST_NOT_ALLOWED", "Delegation Token requests are not allowed on PLAINTEXT/1-way SSL channels and on delegation token authenticated channels."));
-        m.insert(65, kafka_error(65, "DELEGATION_TOKEN_AUTHORIZATION_FAILED", "Delegation Token authorization failed."));
-        m.insert(66, kafka_error(66, "DELEGATION_TOKEN_EXPIRED", "Delegation Token is expired."));
-        m.insert(67, kafka_error(67, "INVALID_PRINCIPAL_TYPE", "Supplied principalType is not supported."));
-        m.insert(68, kafka_error(68, "NON_EMPTY_GROUP", "The group is not empty."));
-        m.insert(69, kafka_error(69, "GROUP_ID_NOT_FOUND", "The group id does not exist."));
-        m.insert(70, kafka_error(70, "FETCH_SESSION_ID_NOT_FOUND", "The fetch session ID was not found."));
-        m.insert(71, kafka_error(71, "INVALID_FETCH_SESSION_EPOCH", "The fetch session epoch is invalid."));
-        m.insert(72, kafka_error(72, "LISTENER_NOT_FOUND", "There is no listener on the leader broker that matches the listener on which metadata request was processed."));
-        m.insert(73, kafka_error(73, "TOPIC_DELETION_DISABLED", "Topic deletion is disabled."));
-        m.insert(74, kafka_error(74, "FENCED_LEADER_EPOCH", "The leader epoch in the request is older than the epoch on the broker."));
-        m.insert(75, kafka_error(75, "UNKNOWN_LEADER_EPOCH", "The leader epoch in the request is newer than the epoch on the broker."));
-        m.insert(76, kafka_error(76, "UNSUPPORTED_COMPRESSION_TYPE", "The requesting client does not support the compression type of given partition."));
-        m.insert(77, kafka_error(77, "STALE_BROKER_EPOCH", "Broker epoch has changed."));
-        m.insert(78, kafka_error(78, "OFFSET_NOT_AVAILABLE", "The leader high watermark has not caught up from a recent leader election so the offsets cannot be guaranteed to be monotonically increasing."));
-        m.insert(79, kafka_error(79, "MEMBER_ID_REQUIRED", "The group member needs to have a valid member id before actually entering a consumer group."));
-        m.insert(80, kafka_error(80, "PREFERRED_LEADER_NOT_AVAILABLE", "The preferred leader was not available."));
-        m.insert(81, kafka_error(81, "GROUP_MAX_SIZE_REACHED", "The consumer group has reached its max size."));
-        m.insert(82, kafka_error(82, "FENCED_INSTANCE_ID", "The broker rejected this static consumer since another consumer with the same group.instance.id has registered with a different member.id."));
-        m.insert(83, kafka_error(83, "ELIGIBLE_LEADERS_NOT_AVAILABLE", "Eligible topic partition leaders are not available."));
-        m.insert(84, kafka_error(84, "ELECTION_NOT_NEEDED", "Leader election not needed for topic partition."));
-        m.insert(85, kafka_error(85, "NO_REASSIGNMENT_IN_PROGRESS", "No partition reassignment is in progress."));
-        m.insert(86, kafka_error(86, "GROUP_SUBSCRIBED_TO_TOPIC", "Deleting offsets of a topic is forbidden while the consumer group is actively subscribed to it."));
-        m.insert(87, kafka_error(87, "INVALID_RECORD", "This record has failed the validation on broker and hence will be rejected."));
-        m.insert(88, kafka_error(88, "UNSTABLE_OFFSET_COMMIT", "There are unstable offsets that need to be cleared."));
-        m.insert(89, kafka_error(89, "THROTTLING_QUOTA_EXCEEDED", "The throttling quota has been exceeded."));
-        m.insert(90, kafka_error(90, "PRODUCER_FENCED", "There is a newer producer with the same transactionalId which fences the current one."));
-        m.insert(91, kafka_error(91, "RESOURCE_NOT_FOUND", "A request illegally referred to a resource that does not exist."));
-        m.insert(92, kafka_error(92, "DUPLICATE_RESOURCE", "A request illegally referred to the same resource twice."));
-        m.insert(93, kafka_error(93, "UNACCEPTABLE_CREDENTIAL", "Requested credential would not meet criteria for acceptability."));
-        m.insert(94, kafka_error(94, "INCONSISTENT_VOTER_SET", "Indicates that the either the sender or recipient of a voter-only request is not one of the expected voters"));
-        m.insert(95, kafka_error(95, "INVALID_UPDATE_VERSION", "The given update version was invalid."));
-        m.insert(96, kafka_error(96, "FEATURE_UPDATE_FAILED", "Unable to update finalized features due to an unexpected server error."));
-        m
+    pub static ref KAFKA_ERRORS: HashMap<i16, KafkaErrorCode> = {
+        let errors = vec![
+            kafka_error(ErrorCode::UnknownServerError, "UNKNOWN_SERVER_ERROR", NonRetriable, "The server experienced an unexpected error when processing the request."),
+            kafka_error(ErrorCode::None, "NONE", NonRetriable, ""),
+            kafka_error(ErrorCode::OffsetOutOfRange, "OFFSET_OUT_OF_RANGE", NonRetriable, "The requested offset is not within the range of offsets maintained by the server."),
+            kafka_error(ErrorCode::CorruptMessage, "CORRUPT_MESSAGE", Retriable, "This message has failed its CRC checksum, exceeds the valid size, has a null key for a compacted topic, or is otherwise corrupt."),
+            kafka_error(ErrorCode::UnknownTopicOrPartition, "UNKNOWN_TOPIC_OR_PARTITION", RefreshMetadata, "This server does not host this topic-partition."),
+            kafka_error(ErrorCode::InvalidFetchSize, "INVALID_FETCH_SIZE", NonRetriable, "The requested fetch size is invalid."),
+            kafka_error(ErrorCode::LeaderNotAvailable, "LEADER_NOT_AVAILABLE", RefreshMetadata, "There is no leader for this topic-partition as we are in the middle of a leadership election."),
+            kafka_error(ErrorCode::NotLeaderOrFollower, "NOT_LEADER_OR_FOLLOWER", RefreshMetadata, "For requests intended only for the leader, this error indicates that the broker is not the current leader. For requests intended for any replica, this error indicates that the broker is not a replica of the topic partition."),
+            kafka_error(ErrorCode::RequestTimedOut, "REQUEST_TIMED_OUT", Retriable, "The request timed out."),
+            kafka_error(ErrorCode::BrokerNotAvailable, "BROKER_NOT_AVAILABLE", NonRetriable, "The broker is not available."),
+            kafka_error(ErrorCode::ReplicaNotAvailable, "REPLICA_NOT_AVAILABLE", RefreshMetadata, "The replica is not available for the requested topic-partition. Produce/Fetch requests and other requests intended only for the leader or follower return NOT_LEADER_OR_FOLLOWER if the broker is not a replica of the topic-partition."),
+            kafka_error(ErrorCode::MessageTooLarge, "MESSAGE_TOO_LARGE", NonRetriable, "The request included a message larger than the max message size the server will accept."),
+            kafka_error(ErrorCode::StaleControllerEpoch, "STALE_CONTROLLER_EPOCH", NonRetriable, "The controller moved to another broker."),
+            kafka_error(ErrorCode::OffsetMetadataTooLarge, "OFFSET_METADATA_TOO_LARGE", NonRetriable, "The metadata field of the offset request was too large."),
+            kafka_error(ErrorCode::NetworkException, "NETWORK_EXCEPTION", RefreshMetadata, "The server disconnected before a response was received."),
+            kafka_error(ErrorCode::CoordinatorLoadInProgress, "COORDINATOR_LOAD_IN_PROGRESS", Retriable, "The coordinator is loading and hence can't process requests."),
+            kafka_error(ErrorCode::CoordinatorNotAvailable, "COORDINATOR_NOT_AVAILABLE", Retriable, "The coordinator is not available."),
+            kafka_error(ErrorCode::NotCoordinator, "NOT_COORDINATOR", Retriable, "This is not the correct coordinator."),
+            kafka_error(ErrorCode::InvalidTopicException, "INVALID_TOPIC_EXCEPTION", NonRetriable, "The request attempted to perform an operation on an invalid topic."),
+            kafka_error(ErrorCode::RecordListTooLarge, "RECORD_LIST_TOO_LARGE", NonRetriable, "The request included message batch larger than the configured segment size on the server."),
+            kafka_error(ErrorCode::NotEnoughReplicas, "NOT_ENOUGH_REPLICAS", Retriable, "Messages are rejected since there are fewer in-sync replicas than required."),
+            kafka_error(ErrorCode::NotEnoughReplicasAfterAppend, "NOT_ENOUGH_REPLICAS_AFTER_APPEND", Retriable, "Messages are written to the log, but to fewer in-sync replicas than required."),
+            kafka_error(ErrorCode::InvalidRequiredAcks, "INVALID_REQUIRED_ACKS", NonRetriable, "Produce request specified an invalid value for required acks."),
+            kafka_error(ErrorCode::IllegalGeneration, "ILLEGAL_GENERATION", NonRetriable, "Specified group generation id is not valid."),
+            kafka_error(ErrorCode::InconsistentGroupProtocol, "INCONSISTENT_GROUP_PROTOCOL", NonRetriable, "The group member's supported protocols are incompatible with those of existing members or first group member tried to join with empty protocol type or empty protocol list."),
+            kafka_error(ErrorCode::InvalidGroupId, "INVALID_GROUP_ID", NonRetriable, "The configured groupId is invalid."),
+            kafka_error(ErrorCode::UnknownMemberId, "UNKNOWN_MEMBER_ID", NonRetriable, "The coordinator is not aware of this member."),
+            kafka_error(ErrorCode::InvalidSessionTimeout, "INVALID_SESSION_TIMEOUT", NonRetriable, "The session timeout is not within the range allowed by the broker (as configured by group.min.session.timeout.ms and group.max.session.timeout.ms)."),
+            kafka_error(ErrorCode::RebalanceInProgress, "REBALANCE_IN_PROGRESS", NonRetriable, "The group is rebalancing, so a rejoin is needed."),
+            kafka_error(ErrorCode::InvalidCommitOffsetSize, "INVALID_COMMIT_OFFSET_SIZE", NonRetriable, "The committing offset data size is not valid."),
+            kafka_error(ErrorCode::TopicAuthorizationFailed, "TOPIC_AUTHORIZATION_FAILED", NonRetriable, "Topic authorization failed."),
+            kafka_error(ErrorCode::GroupAuthorizationFailed, "GROUP_AUTHORIZATION_FAILED", NonRetriable, "Group authorization failed."),
+            kafka_error(ErrorCode::ClusterAuthorizationFailed, "CLUSTER_AUTHORIZATION_FAILED", Fatal, "Cluster authorization failed."),
+            kafka_error(ErrorCode::InvalidTimestamp, "INVALID_TIMESTAMP", NonRetriable, "The timestamp of the message is out of acceptable range."),
+            kafka_error(ErrorCode::UnsupportedSaslMechanism, "UNSUPPORTED_SASL_MECHANISM", NonRetriable, "The broker does not support the requested SASL mechanism."),
+            kafka_error(ErrorCode::IllegalSaslState, "ILLEGAL_SASL_STATE", NonRetriable, "Request is not valid given the current SASL state."),
+            kafka_error(ErrorCode::UnsupportedVersion, "UNSUPPORTED_VERSION", Fatal, "The version of API is not supported."),
+            kafka_error(ErrorCode::TopicAlreadyExists, "TOPIC_ALREADY_EXISTS", NonRetriable, "Topic with this name already exists."),
+            kafka_error(ErrorCode::InvalidPartitions, "INVALID_PARTITIONS", NonRetriable, "Number of partitions is below 1."),
+            kafka_error(ErrorCode::InvalidReplicationFactor, "INVALID_REPLICATION_FACTOR", NonRetriable, "Replication factor is below 1 or larger than the number of available brokers."),
+            kafka_error(ErrorCode::InvalidReplicaAssignment, "INVALID_REPLICA_ASSIGNMENT", NonRetriable, "Replica assignment is invalid."),
+            kafka_error(ErrorCode::InvalidConfig, "INVALID_CONFIG", NonRetriable, "Configuration is invalid."),
+            kafka_error(ErrorCode::NotController, "NOT_CONTROLLER", NonRetriable, "This is not the correct controller for this cluster."),
+            kafka_error(ErrorCode::InvalidRequest, "INVALID_REQUEST", NonRetriable, "This most likely occurs because of a request being malformed by the client library or the message was sent to an incompatible broker. See the broker logs for more details."),
+            kafka_error(ErrorCode::UnsupportedForMessageFormat, "UNSUPPORTED_FOR_MESSAGE_FORMAT", Fatal, "The message format version on the broker does not support the request."),
+            kafka_error(ErrorCode::PolicyViolation, "POLICY_VIOLATION", NonRetriable, "Request parameters do not satisfy the configured policy."),
+            kafka_error(ErrorCode::OutOfOrderSequenceNumber, "OUT_OF_ORDER_SEQUENCE_NUMBER", NonRetriable, "The broker received an out of order sequence number."),
+            kafka_error(ErrorCode::DuplicateSequenceNumber, "DUPLICATE_SEQUENCE_NUMBER", NonRetriable, "The broker received a duplicate sequence number."),
+            kafka_error(ErrorCode::InvalidProducerEpoch, "INVALID_PRODUCER_EPOCH", Fatal, "Producer attempted to produce with an old epoch."),
+            kafka_error(ErrorCode::InvalidTxnState, "INVALID_TXN_STATE", NonRetriable, "The producer attempted a transactional operation in an invalid state."),
+            kafka_error(ErrorCode::InvalidProducerIdMapping, "INVALID_PRODUCER_ID_MAPPING", NonRetriable, "The producer attempted to use a producer id which is not currently assigned to its transactional id."),
+            kafka_error(ErrorCode::InvalidTransactionTimeout, "INVALID_TRANSACTION_TIMEOUT", NonRetriable, "The transaction timeout is larger than the maximum value allowed by the broker (as configured by transaction.max.timeout.ms)."),
+            kafka_error(ErrorCode::ConcurrentTransactions, "CONCURRENT_TRANSACTIONS", Retriable, "The producer attempted to update a transaction while another concurrent operation on the same transaction was ongoing."),
+            kafka_error(ErrorCode::TransactionCoordinatorFenced, "TRANSACTION_COORDINATOR_FENCED", NonRetriable, "Indicates that the transaction coordinator sending a WriteTxnMarker is no longer the current coordinator for a given producer."),
+            kafka_error(ErrorCode::TransactionalIdAuthorizationFailed, "TRANSACTIONAL_ID_AUTHORIZATION_FAILED", Fatal, "Transactional Id authorization failed."),
+            kafka_error(ErrorCode::SecurityDisabled, "SECURITY_DISABLED", NonRetriable, "Security features are disabled."),
+            kafka_error(ErrorCode::OperationNotAttempted, "OPERATION_NOT_ATTEMPTED", NonRetriable, "The broker did not attempt to execute this operation. This may happen for batched RPCs where some operations in the batch failed, causing the broker to respond without trying the rest."),
+            kafka_error(ErrorCode::KafkaStorageError, "KAFKA_STORAGE_ERROR", RefreshMetadata, "Disk error when trying to access log file on the disk."),
+            kafka_error(ErrorCode::LogDirNotFound, "LOG_DIR_NOT_FOUND", NonRetriable, "The user-specified log directory is not found in the broker config."),
+            kafka_error(ErrorCode::SaslAuthenticationFailed, "SASL_AUTHENTICATION_FAILED", NonRetriable, "SASL Authentication failed."),
+            kafka_error(ErrorCode::UnknownProducerId, "UNKNOWN_PRODUCER_ID", NonRetriable, "This exception is raised by the broker if it could not locate the producer metadata associated with the producerId in question. This could happen if, for instance, the producer's records were deleted because their retention time had elapsed. Once the last records of the producerId are removed, the producer's metadata is removed from the broker, and future appends by the producer will return this exception."),
+            kafka_error(ErrorCode::ReassignmentInProgress, "REASSIGNMENT_IN_PROGRESS", NonRetriable, "A partition reassignment is in progress."),
+            kafka_error(ErrorCode::DelegationTokenAuthDisabled, "DELEGATION_TOKEN_AUTH_DISABLED", NonRetriable, "Delegation Token feature is not enabled."),
+            kafka_error(ErrorCode::DelegationTokenNotFound, "DELEGATION_TOKEN_NOT_FOUND", NonRetriable, "Delegation Token is not found on server."),
+            kafka_error(ErrorCode::DelegationTokenOwnerMismatch, "DELEGATION_TOKEN_OWNER_MISMATCH", NonRetriable, "Specified Principal is not valid Owner/Renewer."),
+            kafka_error(ErrorCode::DelegationTokenRequestNotAllowed, "DELEGATION_TOKEN_REQUEST_NOT_ALLOWED", NonRetriable, "Delegation Token requests are not allowed on PLAINTEXT/1-way SSL channels and on delegation token authenticated channels."),
+            kafka_error(ErrorCode::DelegationTokenAuthorizationFailed, "DELEGATION_TOKEN_AUTHORIZATION_FAILED", NonRetriable, "Delegation Token authorization failed."),
+            kafka_error(ErrorCode::DelegationTokenExpired, "DELEGATION_TOKEN_EXPIRED", NonRetriable, "Delegation Token is expired."),
+            kafka_error(ErrorCode::InvalidPrincipalType, "INVALID_PRINCIPAL_TYPE", NonRetriable, "Supplied principalType is not supported."),
+            kafka_error(ErrorCode::NonEmptyGroup, "NON_EMPTY_GROUP", NonRetriable, "The group is not empty."),
+            kafka_error(ErrorCode::GroupIdNotFound, "GROUP_ID_NOT_FOUND", NonRetriable, "The group id does not exist."),
+            kafka_error(ErrorCode::FetchSessionIdNotFound, "FETCH_SESSION_ID_NOT_FOUND", Retriable, "The fetch session ID was not found."),
+            kafka_error(ErrorCode::InvalidFetchSessionEpoch, "INVALID_FETCH_SESSION_EPOCH", Retriable, "The fetch session epoch is invalid."),
+            kafka_error(ErrorCode::ListenerNotFound, "LISTENER_NOT_FOUND", RefreshMetadata, "There is no listener on the leader broker that matches the listener on which metadata request was processed."),
+            kafka_error(ErrorCode::TopicDeletionDisabled, "TOPIC_DELETION_DISABLED", NonRetriable, "Topic deletion is disabled."),
+            kafka_error(ErrorCode::FencedLeaderEpoch, "FENCED_LEADER_EPOCH", RefreshMetadata, "The leader epoch in the request is older than the epoch on the broker."),
+            kafka_error(ErrorCode::UnknownLeaderEpoch, "UNKNOWN_LEADER_EPOCH", Retriable, "The leader epoch in the request is newer than the epoch on the broker."),
+            kafka_error(ErrorCode::UnsupportedCompressionType, "UNSUPPORTED_COMPRESSION_TYPE", NonRetriable, "The requesting client does not support the compression type of given partition."),
+            kafka_error(ErrorCode::StaleBrokerEpoch, "STALE_BROKER_EPOCH", NonRetriable, "Broker epoch has changed."),
+            kafka_error(ErrorCode::OffsetNotAvailable, "OFFSET_NOT_AVAILABLE", Retriable, "The leader high watermark has not caught up from a recent leader election so the offsets cannot be guaranteed to be monotonically increasing."),
+            kafka_error(ErrorCode::MemberIdRequired, "MEMBER_ID_REQUIRED", NonRetriable, "The group member needs to have a valid member id before actually entering a consumer group."),
+            kafka_error(ErrorCode::PreferredLeaderNotAvailable, "PREFERRED_LEADER_NOT_AVAILABLE", RefreshMetadata, "The preferred leader was not available."),
+            kafka_error(ErrorCode::GroupMaxSizeReached, "GROUP_MAX_SIZE_REACHED", NonRetriable, "The consumer group has reached its max size."),
+            kafka_error(ErrorCode::FencedInstanceId, "FENCED_INSTANCE_ID", NonRetriable, "The broker rejected this static consumer since another consumer with the same group.instance.id has registered with a different member.id."),
+            kafka_error(ErrorCode::EligibleLeadersNotAvailable, "ELIGIBLE_LEADERS_NOT_AVAILABLE", RefreshMetadata, "Eligible topic partition leaders are not available."),
+            kafka_error(ErrorCode::ElectionNotNeeded, "ELECTION_NOT_NEEDED", NonRetriable, "Leader election not needed for topic partition."),
+            kafka_error(ErrorCode::NoReassignmentInProgress, "NO_REASSIGNMENT_IN_PROGRESS", NonRetriable, "No partition reassignment is in progress."),
+            kafka_error(ErrorCode::GroupSubscribedToTopic, "GROUP_SUBSCRIBED_TO_TOPIC", NonRetriable, "Deleting offsets of a topic is forbidden while the consumer group is actively subscribed to it."),
+            kafka_error(ErrorCode::InvalidRecord, "INVALID_RECORD", NonRetriable, "This record has failed the validation on broker and hence will be rejected."),
+            kafka_error(ErrorCode::UnstableOffsetCommit, "UNSTABLE_OFFSET_COMMIT", Retriable, "There are unstable offsets that need to be cleared."),
+            kafka_error(ErrorCode::ThrottlingQuotaExceeded, "THROTTLING_QUOTA_EXCEEDED", Retriable, "The throttling quota has been exceeded."),
+            kafka_error(ErrorCode::ProducerFenced, "PRODUCER_FENCED", Fatal, "There is a newer producer with the same transactionalId which fences the current one."),
+            kafka_error(ErrorCode::ResourceNotFound, "RESOURCE_NOT_FOUND", NonRetriable, "A request illegally referred to a resource that does not exist."),
+            kafka_error(ErrorCode::DuplicateResource, "DUPLICATE_RESOURCE", NonRetriable, "A request illegally referred to the same resource twice."),
+            kafka_error(ErrorCode::UnacceptableCredential, "UNACCEPTABLE_CREDENTIAL", NonRetriable, "Requested credential would not meet criteria for acceptability."),
+            kafka_error(ErrorCode::InconsistentVoterSet, "INCONSISTENT_VOTER_SET", NonRetriable, "Indicates that the either the sender or recipient of a voter-only request is not one of the expected voters"),
+            kafka_error(ErrorCode::InvalidUpdateVersion, "INVALID_UPDATE_VERSION", NonRetriable, "The given update version was invalid."),
+            kafka_error(ErrorCode::FeatureUpdateFailed, "FEATURE_UPDATE_FAILED", NonRetriable, "Unable to update finalized features due to an unexpected server error."),
+        ];
+        errors.into_iter().map(|error| (error.code.code(), error)).collect()
    };
-}
\ No newline at end of file
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn known_codes_round_trip() {
+        for (&code, error) in KAFKA_ERRORS.iter() {
+            assert_eq!(error.code.code(), code);
+            assert_eq!(ErrorCode::try_from(code).unwrap(), error.code);
+        }
+        assert_eq!(ErrorCode::NotCoordinator.code(), 16);
+        assert_eq!(ErrorCode::UnknownServerError.code(), -1);
+    }
+
+    #[test]
+    fn unknown_code_keeps_raw_value() {
+        assert!(ErrorCode::try_from(9999).is_err());
+        let code = ErrorCode::from_code(9999);
+        assert_eq!(code, ErrorCode::Unknown(9999));
+        assert_eq!(code.code(), 9999);
+        assert_eq!(code.name(), "UNKNOWN");
+        assert!(!code.is_retriable());
+        assert!(matches!(check_errors(9999), Err(KafkaError::Broker(ErrorCode::Unknown(9999)))));
+    }
+}
@@ -0,0 +1,122 @@
+use std::io::Cursor;
+
+use crate::error::Result;
+use crate::protocol::api_keys::ApiKeys;
+use crate::protocol::kafka_error_codes::check_errors;
+use crate::protocol::primitives::{read_array_length, skip_tagged_fields, write_array_length, write_tagged_fields,
+                                  FlexiblePrimitive, KafkaNullableString, KafkaPrimitive, KafkaString};
+use crate::protocol::request::{KafkaRequest, ToBytes};
+use crate::protocol::response::FromBytes;
+
+
+/// LeaveGroup Request (Version: 0-4) => group_id member_id [members] TAG_BUFFER
+///   group_id => STRING
+///   member_id => STRING (version 0-2)
+///   members => member_id group_instance_id TAG_BUFFER (version 3+)
+///     member_id => STRING
+///     group_instance_id => NULLABLE_STRING
+#[derive(Debug)]
+pub struct LeaveGroupRequest {
+    group_id: KafkaString,
+    members: Vec<LeaveGroupMember>,
+}
+
+#[derive(Debug)]
+struct LeaveGroupMember {
+    member_id: KafkaString,
+    group_instance_id: KafkaNullableString,
+}
+
+impl LeaveGroupRequest {
+    pub fn new(group_id: String, member_id: String, group_instance_id: Option<String>) -> Self {
+        Self {
+            group_id: KafkaString(group_id),
+            members: vec![LeaveGroupMember {
+                member_id: KafkaString(member_id),
+                group_instance_id: KafkaNullableString(group_instance_id),
+            }],
+        }
+    }
+}
+
+impl ToBytes for LeaveGroupRequest {
+    fn get_in_bytes(&self, version: i16) -> Vec<u8> {
+        let flexible = Self::is_flexible(version);
+        let mut buffer = Vec::new();
+        self.group_id.write_flexible(&mut buffer, flexible);
+        if version < 3 {
+            self.members[0].member_id.write_flexible(&mut buffer, flexible);
+        } else {
+            write_array_length(&mut buffer, self.members.len(), flexible);
+            for member in self.members.iter() {
+                member.member_id.write_flexible(&mut buffer, flexible);
+                member.group_instance_id.write_flexible(&mut buffer, flexible);
+                write_tagged_fields(&mut buffer, flexible);
+            }
+        }
+        write_tagged_fields(&mut buffer, flexible);
+        buffer
+    }
+}
+
+impl KafkaRequest for LeaveGroupRequest {
+    type Response = LeaveGroupResponse;
+    const API_KEY: ApiKeys = ApiKeys::LeaveGroup;
+    const MIN_VERSION: i16 = 0;
+    const MAX_VERSION: i16 = 4;
+    const FIRST_FLEXIBLE_VERSION: i16 = 4;
+}
+
+/// LeaveGroup Response (Version: 0-4) => throttle_time_ms error_code [members] TAG_BUFFER
+///   throttle_time_ms => INT32 (version 1+)
+///   error_code => INT16
+///   members => member_id group_instance_id error_code TAG_BUFFER (version 3+)
+///     member_id => STRING
+///     group_instance_id => NULLABLE_STRING
+///     error_code => INT16
+#[derive(Debug)]
+pub struct LeaveGroupResponse {
+    pub throttle_time_ms: i32,
+    pub error_code: i16,
+    pub members: Vec<LeaveGroupMemberResponse>,
+}
+
+#[derive(Debug)]
+pub struct LeaveGroupMemberResponse {
+    pub member_id: KafkaString,
+    pub group_instance_id: KafkaNullableString,
+    pub error_code: i16,
+}
+
+impl FromBytes for LeaveGroupResponse {
+    fn get_from_bytes(buffer: &mut Cursor<Vec<u8>>, version: i16) -> Result<Self> {
+        let flexible = LeaveGroupRequest::is_flexible(version);
+        let mut response = Self {
+            throttle_time_ms: 0,
+            error_code: 0,
+            members: vec![],
+        };
+        if version >= 1 {
+            response.throttle_time_ms = i32::read_from_buffer(buffer)?;
+        }
+        response.error_code = i16::read_from_buffer(buffer)?;
+        if version >= 3 {
+            let members_length = read_array_length(buffer, flexible)?;
+            for _ in 0..members_length {
+                let member = LeaveGroupMemberResponse {
+                    member_id: KafkaString::read_flexible(buffer, flexible)?,
+                    group_instance_id: KafkaNullableString::read_flexible(buffer, flexible)?,
+                    error_code: i16::read_from_buffer(buffer)?,
+                };
+                skip_tagged_fields(buffer, flexible)?;
+                response.members.push(member);
+            }
+        }
+        skip_tagged_fields(buffer, flexible)?;
+        check_errors(response.error_code)?;
+        for member in response.members.iter() {
+            check_errors(member.error_code)?;
+        }
+        Ok(response)
+    }
+}
@@ -1,17 +1,25 @@
-use crate::protocol::request::ToBytes;
+use crate::protocol::api_keys::ApiKeys;
+use crate::protocol::request::{KafkaRequest, ToBytes};
 use crate::protocol::response::FromBytes;
 use std::io::Cursor;
-use crate::protocol::primitives::{KafkaString, KafkaPrimitive};
+use crate::protocol::primitives::{read_array_length, skip_tagged_fields, write_array_length, write_tagged_fields,
+                                  FlexiblePrimitive, KafkaPrimitive, KafkaString};
+use crate::error::Result;
 
-/// ListOffsets Request (Version: 3) => replica_id isolation_level [topics]
+/// The timestamp asking for the offset of the first record of a partition.
+pub const EARLIEST_TIMESTAMP: i64 = -2;
+/// The timestamp asking for the offset following the last record of a partition.
+pub const LATEST_TIMESTAMP: i64 = -1;
+
+/// ListOffsets Request (Version: 1-7) => replica_id isolation_level [topics] TAG_BUFFER
 ///   replica_id => INT32
-///   isolation_level => INT8
-///   topics => name [partitions]
+///   isolation_level => INT8 (version 2+)
+///   topics => name [partitions] TAG_BUFFER
 ///     name => STRING
-///     partitions => partition_index timestamp
+///     partitions => partition_index current_leader_epoch timestamp TAG_BUFFER
 ///       partition_index => INT32
+///       current_leader_epoch => INT32 (version 4+)
 ///       timestamp => INT64
-
 pub struct ListOffsetsRequest {
     replica_id: i32,
     isolation_level: i8,
@@ -25,100 +33,148 @@ struct TopicRequest {
 
 struct PartitionRequest {
     partition_index: i32,
+    current_leader_epoch: i32,
     timestamp: i64,
 }
 
 impl ListOffsetsRequest {
-    pub fn new(topics: &Vec<&str>) -> Self {
+    /// With an `isolation_level` of 1, read_committed, the latest offset is the last stable one.
+    pub fn new(isolation_level: i8) -> Self {
         Self {
             replica_id: -1,
-            isolation_level: 0,
+            isolation_level,
             topics: vec![]
         }
     }
+
+    /// Asks for the earliest offset of a partition whose record has a timestamp at or after
+    /// the one given, or for `EARLIEST_TIMESTAMP` or `LATEST_TIMESTAMP`.
+    pub fn add_partition(&mut self, topic: &str, partition: i32, timestamp: i64) {
+        let partition = PartitionRequest {
+            partition_index: partition,
+            current_leader_epoch: -1,
+            timestamp,
+        };
+        match self.topics.iter_mut().find(|t| t.name.0 == topic) {
+            Some(t) => t.partitions.push(partition),
+            None => self.topics.push(TopicRequest {
+                name: KafkaString(topic.to_string()),
+                partitions: vec![partition],
+            }),
+        }
+    }
 }
 
 
 impl ToBytes for ListOffsetsRequest {
-    fn get_in_bytes(&self) -> Vec<u8> {
+    fn get_in_bytes(&self, version: i16) -> Vec<u8> {
+        let flexible = Self::is_flexible(version);
         let mut buffer = Vec::new();
         self.replica_id.write_to_buffer(&mut buffer);
-        self.isolation_level.write_to_buffer(&mut buffer);
-
+        if version >= 2 {
+            self.isolation_level.write_to_buffer(&mut buffer);
+        }
 
-        let topics_len = self.topics.len() as i32;
-        topics_len.write_to_buffer(&mut buffer);
+        write_array_length(&mut buffer, self.topics.len(), flexible);
         for topic in self.topics.iter() {
-            topic.name.write_to_buffer(&mut buffer);
+            topic.name.write_flexible(&mut buffer, flexible);
 
-            let partitions_len = topic.partitions.len() as i32;
-            partitions_len.write_to_buffer(&mut buffer);
+            write_array_length(&mut buffer, topic.partitions.len(), flexible);
             for partition in topic.partitions.iter() {
                 partition.partition_index.write_to_buffer(&mut buffer);
+                if version >= 4 {
+                    partition.current_leader_epoch.write_to_buffer(&mut buffer);
+                }
                 partition.timestamp.write_to_buffer(&mut buffer);
+                write_tagged_fields(&mut buffer, flexible);
             }
+            write_tagged_fields(&mut buffer, flexible);
         }
+        write_tagged_fields(&mut buffer, flexible);
         buffer
     }
 }
 
+impl KafkaRequest for ListOffsetsRequest {
+    type Response = ListOffsetsResponse;
+    const API_KEY: ApiKeys = ApiKeys::ListOffsets;
+    const MIN_VERSION: i16 = 1;
+    const MAX_VERSION: i16 = 7;
+    const FIRST_FLEXIBLE_VERSION: i16 = 6;
+}
+
 
-/// ListOffsets Response (Version: 3) => throttle_time_ms [topics]
-///  throttle_time_ms => INT32
-///  topics => name [partitions]
+/// ListOffsets Response (Version: 1-7) => throttle_time_ms [topics] TAG_BUFFER
+///  throttle_time_ms => INT32 (version 2+)
+///  topics => name [partitions] TAG_BUFFER
 ///    name => STRING
-///    partitions => partition_index error_code timestamp offset
+///    partitions => partition_index error_code timestamp offset leader_epoch TAG_BUFFER
 ///      partition_index => INT32
 ///      error_code => INT16
 ///      timestamp => INT64
 ///      offset => INT64
+///      leader_epoch => INT32 (version 4+)
 ///
 ///
 #[derive(Debug)]
 pub struct ListOffsetsResponse {
-    throttle_time_ms: i32,
-    topics: Vec<TopicResponse>,
+    pub throttle_time_ms: i32,
+    pub topics: Vec<TopicResponse>,
 
 }
 
 #[derive(Debug)]
-struct TopicResponse {
-    name: KafkaString,
-    partitions: Vec<PartitionResponse>
+pub struct TopicResponse {
+    pub name: KafkaString,
+    pub partitions: Vec<PartitionResponse>
 }
 
 #[derive(Debug)]
-struct PartitionResponse {
-    partition_index: i32,
-    timestamp: i64,
-    offset: i64,
+pub struct PartitionResponse {
+    pub partition_index: i32,
+    pub error_code: i16,
+    pub timestamp: i64,
+    pub offset: i64,
+    pub leader_epoch: i32,
 }
 
 
 impl FromBytes for ListOffsetsResponse {
-    fn get_from_bytes(buffer: &mut Cursor<Vec<u8>>) -> Self {
+    fn get_from_bytes(buffer: &mut Cursor<Vec<u8>>, version: i16) -> Result<Self> {
+        let flexible = ListOffsetsRequest::is_flexible(version);
         let mut response = Self {
-            throttle_time_ms: i32::read_from_buffer(buffer),
+            throttle_time_ms: 0,
             topics: vec![]
         };
-        let topics_len = i32::read_from_buffer(buffer);
+        if version >= 2 {
+            response.throttle_time_ms = i32::read_from_buffer(buffer)?;
+        }
+        let topics_len = read_array_length(buffer, flexible)?;
         for _ in 0..topics_len {
             let mut topic = TopicResponse {
-                name: KafkaString::read_from_buffer(buffer),
+                name: KafkaString::read_flexible(buffer, flexible)?,
                 partitions: vec![],
             };
 
-            let partitions_len = i32::read_from_buffer(buffer);
+            let partitions_len = read_array_length(buffer, flexible)?;
             for _ in 0..partitions_len {
-                let partition = PartitionResponse {
-                    partition_index: i32::read_from_buffer(buffer),
-                    timestamp: i64::read_from_buffer(buffer),
-                    offset: i64::read_from_buffer(buffer),
+                let mut partition = PartitionResponse {
+                    partition_index: i32::read_from_buffer(buffer)?,
+                    error_code: i16::read_from_buffer(buffer)?,
+                    timestamp: i64::read_from_buffer(buffer)?,
+                    offset: i64::read_from_buffer(buffer)?,
+                    leader_epoch: -1,
                 };
+                if version >= 4 {
+                    partition.leader_epoch = i32::read_from_buffer(buffer)?;
+                }
+                skip_tagged_fields(buffer, flexible)?;
                 topic.partitions.push(partition);
             }
+            skip_tagged_fields(buffer, flexible)?;
             response.topics.push(topic)
         }
-        response
+        skip_tagged_fields(buffer, flexible)?;
+        Ok(response)
     }
-}
\ No newline at end of file
+}
@@ -1,54 +1,96 @@
-use crate::protocol::request::ToBytes;
-use crate::protocol::primitives::{KafkaPrimitive, KafkaString, KafkaArray};
+use crate::protocol::api_keys::ApiKeys;
+use crate::protocol::request::{KafkaRequest, ToBytes};
+use crate::protocol::primitives::{read_array_length, skip_tagged_fields, write_array_length, write_null_array,
+                                  write_tagged_fields, FlexiblePrimitive, KafkaArray, KafkaPrimitive, KafkaString};
 use std::io::{Cursor};
 use crate::protocol::response::FromBytes;
-use crate::protocol::kafka_error_codes::check_errors;
-use byteorder::{WriteBytesExt, BE};
+use crate::error::Result;
 
+/// Value of the authorized operations fields when they were not requested.
+const AUTHORIZED_OPERATIONS_OMITTED: i32 = i32::MIN;
 
+/// Metadata Request (Version: 1-9) => [topics] allow_auto_topic_creation include_cluster_authorized_operations include_topic_authorized_operations TAG_BUFFER
+///   topics => name TAG_BUFFER
+///     name => STRING
+///   allow_auto_topic_creation => BOOLEAN (version 4+)
+///   include_cluster_authorized_operations => BOOLEAN (version 8+)
+///   include_topic_authorized_operations => BOOLEAN (version 8+)
 pub struct MetadataRequest {
-    topics: KafkaArray<String>,
+    topics: Vec<KafkaString>,
     allow_auto_topic_creation: bool,
+    include_cluster_authorized_operations: bool,
+    include_topic_authorized_operations: bool,
 }
 
 impl MetadataRequest {
-    pub fn new(topics: &Vec<String>) -> Self {
+    pub fn new(topics: &[String]) -> Self {
         Self {
-            topics: KafkaArray(topics.clone()),
-            allow_auto_topic_creation: false
+            topics: topics.iter().cloned().map(KafkaString).collect(),
+            allow_auto_topic_creation: false,
+            include_cluster_authorized_operations: false,
+            include_topic_authorized_operations: false,
         }
     }
 }
 
 impl ToBytes for MetadataRequest {
-    fn get_in_bytes(&self) -> Vec<u8> {
-        let topics_len = self.topics.0.len() as i32;
-        let mut buffer = Vec::with_capacity(topics_len as usize);
-        self.topics.write_to_buffer(&mut buffer);
-        self.allow_auto_topic_creation.write_to_buffer(&mut buffer);
+    fn get_in_bytes(&self, version: i16) -> Vec<u8> {
+        let flexible = Self::is_flexible(version);
+        let mut buffer = Vec::new();
+        if self.topics.is_empty() {
+            write_null_array(&mut buffer, flexible); // A null array stands for all topics
+        } else {
+            write_array_length(&mut buffer, self.topics.len(), flexible);
+        }
+        for topic in self.topics.iter() {
+            topic.write_flexible(&mut buffer, flexible);
+            write_tagged_fields(&mut buffer, flexible);
+        }
+        if version >= 4 {
+            self.allow_auto_topic_creation.write_to_buffer(&mut buffer);
+        }
+        if version >= 8 {
+            self.include_cluster_authorized_operations.write_to_buffer(&mut buffer);
+            self.include_topic_authorized_operations.write_to_buffer(&mut buffer);
+        }
+        write_tagged_fields(&mut buffer, flexible);
         buffer
     }
 }
-/// Metadata Response (Version: 6) => throttle_time_ms [brokers] cluster_id controller_id [topics]
-///   throttle_time_ms => INT32
-///   brokers => node_id host port rack
+
+impl KafkaRequest for MetadataRequest {
+    type Response = MetadataResponse;
+    const API_KEY: ApiKeys = ApiKeys::Metadata;
+    const MIN_VERSION: i16 = 1;
+    const MAX_VERSION: i16 = 9;
+    const FIRST_FLEXIBLE_VERSION: i16 = 9;
+}
+/// Metadata Response (Version: 1-9) => throttle_time_ms [brokers] cluster_id controller_id [topics] cluster_authorized_operations TAG_BUFFER
+///   throttle_time_ms => INT32 (version 3+)
+///   brokers => node_id host port rack TAG_BUFFER
 ///     node_id => INT32
 ///     host => STRING
 ///     port => INT32
 ///     rack => NULLABLE_STRING
-///   cluster_id => NULLABLE_STRING
+///   cluster_id => NULLABLE_STRING (version 2+)
 ///   controller_id => INT32
-///   topics => error_code name is_internal [partitions]
+///   topics => error_code name is_internal [partitions] topic_authorized_operations TAG_BUFFER
 ///     error_code => INT16
 ///     name => STRING
 ///     is_internal => BOOLEAN
-///     partitions => error_code partition_index leader_id [replica_nodes] [isr_nodes] [offline_replicas]
+///     partitions => error_code partition_index leader_id leader_epoch [replica_nodes] [isr_nodes] [offline_replicas] TAG_BUFFER
 ///       error_code => INT16
 ///       partition_index => INT32
 ///       leader_id => INT32
+///       leader_epoch => INT32 (version 7+)
 ///       replica_nodes => INT32
 ///       isr_nodes => INT32
-///       offline_replicas => INT32
+///       offline_replicas => INT32 (version 5+)
+///     topic_authorized_operations => INT32 (version 8+)
+///   cluster_authorized_operations => INT32 (version 8+)
+///
+/// The error code of each topic is left for the caller to check, so that one topic failing,
+/// e.g. while it is being created, does not lose the metadata of the others.
 #[derive(Debug)]
 pub struct MetadataResponse {
     pub throttle_time_ms: i32,
@@ -56,6 +98,7 @@ pub struct MetadataResponse {
     pub cluster_id: String,
     pub controller_id: i32,
     pub topics: Vec<TopicMetadataResponse>,
+    pub cluster_authorized_operations: i32,
 }
 
 #[derive(Debug)]
@@ -63,74 +106,141 @@ pub struct BrokerMetadataResponse {
     pub node_id: i32,
     pub host: String,
     pub port: i32,
-    rack: String,
+    pub rack: String,
 }
 
 #[derive(Debug)]
 pub struct TopicMetadataResponse {
     pub error_code: i16,
     pub name: String,
-    is_internal: bool,
+    pub is_internal: bool,
     pub partitions: Vec<PartitionMetadataResponse>,
+    pub topic_authorized_operations: i32,
 }
 
 #[derive(Debug)]
 pub struct PartitionMetadataResponse {
-    error_code: i16,
+    pub error_code: i16,
     pub partition_index: i32,
     pub leader_id: i32,
-    replica_nodes: Vec<i32>,
-    isr_nodes: Vec<i32>,
-    offline_replicas: Vec<i32>,
+    pub leader_epoch: i32,
+    pub replica_nodes: Vec<i32>,
+    pub isr_nodes: Vec<i32>,
+    pub offline_replicas: Vec<i32>,
 }
 
 impl FromBytes for MetadataResponse {
-    fn get_from_bytes(buffer: &mut Cursor<Vec<u8>>) -> Self {
+    fn get_from_bytes(buffer: &mut Cursor<Vec<u8>>, version: i16) -> Result<Self> {
+        let flexible = MetadataRequest::is_flexible(version);
         let mut response = Self {
-            throttle_time_ms: i32::read_from_buffer(buffer),
+            throttle_time_ms: 0,
             brokers: vec![],
             cluster_id: "".to_string(),
-            controller_id: 0,
-            topics: vec![]
+            controller_id: -1,
+            topics: vec![],
+            cluster_authorized_operations: AUTHORIZED_OPERATIONS_OMITTED,
         };
-        let brokers_length = i32::read_from_buffer(buffer);
+        if version >= 3 {
+            response.throttle_time_ms = i32::read_from_buffer(buffer)?;
+        }
+        let brokers_length = read_array_length(buffer, flexible)?;
         for _ in 0..brokers_length {
             let broker = BrokerMetadataResponse {
-                node_id: i32::read_from_buffer(buffer),
-                host: KafkaString::read_from_buffer(buffer).0,
-                port: i32::read_from_buffer(buffer),
-                rack: KafkaString::read_from_buffer(buffer).0,
+                node_id: i32::read_from_buffer(buffer)?,
+                host: KafkaString::read_flexible(buffer, flexible)?.0,
+                port: i32::read_from_buffer(buffer)?,
+                rack: KafkaString::read_flexible(buffer, flexible)?.0,
             };
+            skip_tagged_fields(buffer, flexible)?;
             response.brokers.push(broker);
         }
-        response.cluster_id =  KafkaString::read_from_buffer(buffer).0;
-        response.controller_id = i32::read_from_buffer(buffer);
+        if version >= 2 {
+            response.cluster_id = KafkaString::read_flexible(buffer, flexible)?.0;
+        }
+        response.controller_id = i32::read_from_buffer(buffer)?;
 
-        let topics_length = i32::read_from_buffer(buffer);
+        let topics_length = read_array_length(buffer, flexible)?;
         for _ in 0..topics_length {
             let mut topic = TopicMetadataResponse {
-                error_code: i16::read_from_buffer(buffer),
-                name: KafkaString::read_from_buffer(buffer).0,
-                is_internal: bool::read_from_buffer(buffer),
-                partitions: vec![]
+                error_code: i16::read_from_buffer(buffer)?,
+                name: KafkaString::read_flexible(buffer, flexible)?.0,
+                is_internal: bool::read_from_buffer(buffer)?,
+                partitions: vec![],
+                topic_authorized_operations: AUTHORIZED_OPERATIONS_OMITTED,
             };
-            check_errors(topic.error_code);
 
-            let partitions_length = i32::read_from_buffer(buffer);
+            let partitions_length = read_array_length(buffer, flexible)?;
             for _ in 0..partitions_length {
-                let partition = PartitionMetadataResponse {
-                    error_code:  i16::read_from_buffer(buffer),
-                    partition_index:  i32::read_from_buffer(buffer),
-                    leader_id:  i32::read_from_buffer(buffer),
-                    replica_nodes: KafkaArray::read_from_buffer(buffer).0,
-                    isr_nodes: KafkaArray::read_from_buffer(buffer).0,
-                    offline_replicas: KafkaArray::read_from_buffer(buffer).0,
+                let mut partition = PartitionMetadataResponse {
+                    error_code: i16::read_from_buffer(buffer)?,
+                    partition_index: i32::read_from_buffer(buffer)?,
+                    leader_id: i32::read_from_buffer(buffer)?,
+                    leader_epoch: -1,
+                    replica_nodes: vec![],
+                    isr_nodes: vec![],
+                    offline_replicas: vec![],
                 };
-
+                if version >= 7 {
+                    partition.leader_epoch = i32::read_from_buffer(buffer)?;
+                }
+                partition.replica_nodes = KafkaArray::read_flexible(buffer, flexible)?.0;
+                partition.isr_nodes = KafkaArray::read_flexible(buffer, flexible)?.0;
+                if version >= 5 {
+                    partition.offline_replicas = KafkaArray::read_flexible(buffer, flexible)?.0;
+                }
+                skip_tagged_fields(buffer, flexible)?;
                 topic.partitions.push(partition);
             }
+            if version >= 8 {
+                topic.topic_authorized_operations = i32::read_from_buffer(buffer)?;
+            }
+            skip_tagged_fields(buffer, flexible)?;
             response.topics.push(topic);
         }
-        response
+        if version >= 8 {
+            response.cluster_authorized_operations = i32::read_from_buffer(buffer)?;
+        }
+        skip_tagged_fields(buffer, flexible)?;
+        Ok(response)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn keeps_topics_failing_alongside_healthy_ones() {
+        let mut bytes = Vec::new();
+        1i32.write_to_buffer(&mut bytes);
+        1i32.write_to_buffer(&mut bytes);
+        KafkaString("localhost".to_string()).write_to_buffer(&mut bytes);
+        9092i32.write_to_buffer(&mut bytes);
+        KafkaString("".to_string()).write_to_buffer(&mut bytes);
+        1i32.write_to_buffer(&mut bytes);
+        2i32.write_to_buffer(&mut bytes);
+
+        5i16.write_to_buffer(&mut bytes);
+        KafkaString("creating".to_string()).write_to_buffer(&mut bytes);
+        false.write_to_buffer(&mut bytes);
+        0i32.write_to_buffer(&mut bytes);
+
+        0i16.write_to_buffer(&mut bytes);
+        KafkaString("healthy".to_string()).write_to_buffer(&mut bytes);
+        false.write_to_buffer(&mut bytes);
+        1i32.write_to_buffer(&mut bytes);
+        0i16.write_to_buffer(&mut bytes);
+        0i32.write_to_buffer(&mut bytes);
+        1i32.write_to_buffer(&mut bytes);
+        KafkaArray(vec![1i32]).write_to_buffer(&mut bytes);
+        KafkaArray(vec![1i32]).write_to_buffer(&mut bytes);
+
+        let response = MetadataResponse::get_from_bytes(&mut Cursor::new(bytes), 1).unwrap();
+        assert_eq!(response.brokers.len(), 1);
+        assert_eq!(response.topics.len(), 2);
+        assert_eq!((response.topics[0].name.as_str(), response.topics[0].error_code), ("creating", 5));
+        assert!(response.topics[0].partitions.is_empty());
+        assert_eq!((response.topics[1].name.as_str(), response.topics[1].error_code), ("healthy", 0));
+        assert_eq!(response.topics[1].partitions[0].leader_id, 1);
     }
 }
@@ -13,4 +13,15 @@ pub mod list_offsets;
 pub mod join_group;
 pub mod find_coordinator;
 pub mod produce;
-pub mod record;
\ No newline at end of file
+pub mod record;
+pub mod compression;
+pub mod init_producer_id;
+pub mod add_partitions_to_txn;
+pub mod add_offsets_to_txn;
+pub mod end_txn;
+pub mod txn_offset_commit;
+pub mod offset_fetch;
+pub mod sync_group;
+pub mod heartbeat;
+pub mod leave_group;
+pub mod consumer_protocol;
\ No newline at end of file
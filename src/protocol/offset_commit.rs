@@ -1,14 +1,19 @@
-use crate::protocol::request::ToBytes;
-use crate::protocol::primitives::{KafkaPrimitive, KafkaString};
+use crate::protocol::api_keys::ApiKeys;
+use crate::protocol::request::{KafkaRequest, ToBytes};
+use crate::protocol::primitives::{read_array_length, skip_tagged_fields, write_array_length, write_tagged_fields,
+                                  FlexiblePrimitive, KafkaPrimitive, KafkaString, KafkaNullableString};
 use crate::protocol::response::FromBytes;
 use std::io::Cursor;
-use crate::protocol::kafka_error_codes::{KAFKA_ERRORS, check_errors};
+use crate::protocol::kafka_error_codes::check_errors;
+use crate::error::Result;
 
 
 pub struct CommitOffsetRequest {
     group_id: KafkaString,
     generation_id: i32,
     member_id: KafkaString,
+    group_instance_id: KafkaNullableString,
+    retention_time_ms: i64,
     topics: Vec<TopicRequest>,
 }
 
@@ -25,7 +30,7 @@ struct PartitionRequest {
 }
 
 impl CommitOffsetRequest {
-    pub fn new(group_id: String) -> Self {
+    pub fn new(_group_id: String) -> Self {
         let partition = PartitionRequest {
             partition_index: 0,
             committed_offset: 10,
@@ -40,98 +45,127 @@ impl CommitOffsetRequest {
             group_id: KafkaString("roi".to_string()),
             generation_id: 1,
             member_id: KafkaString("roi".to_string()),
+            group_instance_id: KafkaNullableString(None),
+            retention_time_ms: -1,
             topics: vec![topic],
         }
     }
 }
 
-/// OffsetCommit Request (Version: 6) => group_id generation_id member_id [topics]
+/// OffsetCommit Request (Version: 2-8) => group_id generation_id member_id group_instance_id retention_time_ms [topics] TAG_BUFFER
 ///   group_id => STRING
 ///   generation_id => INT32
 ///   member_id => STRING
-///   topics => name [partitions]
+///   group_instance_id => NULLABLE_STRING (version 7+)
+///   retention_time_ms => INT64 (version 2-4)
+///   topics => name [partitions] TAG_BUFFER
 ///     name => STRING
-///     partitions => partition_index committed_offset committed_leader_epoch committed_metadata
+///     partitions => partition_index committed_offset committed_leader_epoch committed_metadata TAG_BUFFER
 ///       partition_index => INT32
 ///       committed_offset => INT64
-///       committed_leader_epoch => INT32
+///       committed_leader_epoch => INT32 (version 6+)
 ///       committed_metadata => NULLABLE_STRING
 ///
 impl ToBytes for CommitOffsetRequest {
-    fn get_in_bytes(&self) -> Vec<u8> {
+    fn get_in_bytes(&self, version: i16) -> Vec<u8> {
+        let flexible = Self::is_flexible(version);
         let mut buffer = Vec::new();
-        self.group_id.write_to_buffer(&mut buffer);
+        self.group_id.write_flexible(&mut buffer, flexible);
         self.generation_id.write_to_buffer(&mut buffer);
-        self.member_id.write_to_buffer(&mut buffer);
+        self.member_id.write_flexible(&mut buffer, flexible);
+        if version >= 7 {
+            self.group_instance_id.write_flexible(&mut buffer, flexible);
+        }
+        if version <= 4 {
+            self.retention_time_ms.write_to_buffer(&mut buffer);
+        }
 
-        let topics_len = self.topics.len() as i32;
-        topics_len.write_to_buffer(&mut buffer);
+        write_array_length(&mut buffer, self.topics.len(), flexible);
         for topic in self.topics.iter() {
-            topic.name.write_to_buffer(&mut buffer);
+            topic.name.write_flexible(&mut buffer, flexible);
 
-            let partitions_len = topic.partitions.len() as i32;
-            partitions_len.write_to_buffer(&mut buffer);
+            write_array_length(&mut buffer, topic.partitions.len(), flexible);
             for partition in topic.partitions.iter() {
                 partition.partition_index.write_to_buffer(&mut buffer);
                 partition.committed_offset.write_to_buffer(&mut buffer);
-                partition.committed_leader_epoch.write_to_buffer(&mut buffer);
-                partition.committed_metadata.write_to_buffer(&mut buffer);
+                if version >= 6 {
+                    partition.committed_leader_epoch.write_to_buffer(&mut buffer);
+                }
+                partition.committed_metadata.write_flexible(&mut buffer, flexible);
+                write_tagged_fields(&mut buffer, flexible);
             }
+            write_tagged_fields(&mut buffer, flexible);
         }
+        write_tagged_fields(&mut buffer, flexible);
         buffer
 
     }
 }
 
-/// OffsetCommit Response (Version: 6) => throttle_time_ms [topics]
-///   throttle_time_ms => INT32
-///   topics => name [partitions]
+impl KafkaRequest for CommitOffsetRequest {
+    type Response = CommitOffsetResponse;
+    const API_KEY: ApiKeys = ApiKeys::OffsetCommit;
+    const MIN_VERSION: i16 = 2;
+    const MAX_VERSION: i16 = 8;
+    const FIRST_FLEXIBLE_VERSION: i16 = 8;
+}
+
+/// OffsetCommit Response (Version: 2-8) => throttle_time_ms [topics] TAG_BUFFER
+///   throttle_time_ms => INT32 (version 3+)
+///   topics => name [partitions] TAG_BUFFER
 ///     name => STRING
-///     partitions => partition_index error_code
+///     partitions => partition_index error_code TAG_BUFFER
 ///       partition_index => INT32
 ///       error_code => INT16
 #[derive(Debug)]
 pub struct CommitOffsetResponse {
-    throttle_time_ms: i32,
-    topics: Vec<TopicResponse>,
+    pub throttle_time_ms: i32,
+    pub topics: Vec<TopicResponse>,
 }
 #[derive(Debug)]
-struct TopicResponse {
-    name: KafkaString,
-    partitions: Vec<PartitionResponse>,
+pub struct TopicResponse {
+    pub name: KafkaString,
+    pub partitions: Vec<PartitionResponse>,
 }
 
 #[derive(Debug)]
-struct PartitionResponse {
-    partition_index: i32,
-    error_code: i16,
+pub struct PartitionResponse {
+    pub partition_index: i32,
+    pub error_code: i16,
 }
 
 impl FromBytes for CommitOffsetResponse {
-    fn get_from_bytes(buffer: &mut Cursor<Vec<u8>>) -> Self {
+    fn get_from_bytes(buffer: &mut Cursor<Vec<u8>>, version: i16) -> Result<Self> {
+        let flexible = CommitOffsetRequest::is_flexible(version);
         let mut response = Self {
-            throttle_time_ms: i32::read_from_buffer(buffer),
+            throttle_time_ms: 0,
             topics: vec![]
         };
-        let topic_len = i32::read_from_buffer(buffer);
+        if version >= 3 {
+            response.throttle_time_ms = i32::read_from_buffer(buffer)?;
+        }
+        let topic_len = read_array_length(buffer, flexible)?;
         for _ in 0..topic_len {
             let mut topic = TopicResponse {
-                name: KafkaString::read_from_buffer(buffer),
+                name: KafkaString::read_flexible(buffer, flexible)?,
                 partitions: vec![]
             };
-            let partitions_length = i32::read_from_buffer(buffer);
+            let partitions_length = read_array_length(buffer, flexible)?;
             for _ in 0..partitions_length {
                 let partition = PartitionResponse {
-                    partition_index: i32::read_from_buffer(buffer),
-                    error_code: i16::read_from_buffer(buffer),
+                    partition_index: i32::read_from_buffer(buffer)?,
+                    error_code: i16::read_from_buffer(buffer)?,
                 };
                 if partition.error_code != 0 {
-                    check_errors(partition.error_code);
+                    check_errors(partition.error_code)?;
                 }
+                skip_tagged_fields(buffer, flexible)?;
                 topic.partitions.push(partition);
             }
+            skip_tagged_fields(buffer, flexible)?;
             response.topics.push(topic);
         }
-        response
+        skip_tagged_fields(buffer, flexible)?;
+        Ok(response)
     }
 }
\ No newline at end of file
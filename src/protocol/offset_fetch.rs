@@ -0,0 +1,161 @@
+use std::io::Cursor;
+
+use crate::error::Result;
+use crate::protocol::api_keys::ApiKeys;
+use crate::protocol::kafka_error_codes::check_errors;
+use crate::protocol::primitives::{read_array_length, skip_tagged_fields, write_array_length, write_tagged_fields,
+                                  FlexiblePrimitive, KafkaPrimitive, KafkaString, KafkaNullableString};
+use crate::protocol::request::{KafkaRequest, ToBytes};
+use crate::protocol::response::FromBytes;
+
+
+/// OffsetFetch Request (Version: 1-7) => group_id [topics] require_stable TAG_BUFFER
+///   group_id => STRING
+///   topics => name [partition_indexes] TAG_BUFFER
+///     name => STRING
+///     partition_indexes => INT32
+///   require_stable => BOOLEAN (version 7+)
+#[derive(Debug)]
+pub struct OffsetFetchRequest {
+    group_id: KafkaString,
+    topics: Vec<OffsetFetchTopic>,
+    require_stable: bool,
+}
+
+#[derive(Debug)]
+struct OffsetFetchTopic {
+    name: KafkaString,
+    partition_indexes: Vec<i32>,
+}
+
+impl OffsetFetchRequest {
+    /// Asks for committed offsets only, waiting for the transactions still writing offsets
+    /// for the group to end.
+    pub fn new(group_id: String) -> Self {
+        Self {
+            group_id: KafkaString(group_id),
+            topics: vec![],
+            require_stable: true,
+        }
+    }
+
+    pub fn add_partition(&mut self, topic: &str, partition: i32) {
+        match self.topics.iter_mut().find(|t| t.name.0 == topic) {
+            Some(t) => t.partition_indexes.push(partition),
+            None => self.topics.push(OffsetFetchTopic {
+                name: KafkaString(topic.to_string()),
+                partition_indexes: vec![partition],
+            }),
+        }
+    }
+}
+
+impl ToBytes for OffsetFetchRequest {
+    fn get_in_bytes(&self, version: i16) -> Vec<u8> {
+        let flexible = Self::is_flexible(version);
+        let mut buffer = Vec::new();
+        self.group_id.write_flexible(&mut buffer, flexible);
+        write_array_length(&mut buffer, self.topics.len(), flexible);
+        for topic in self.topics.iter() {
+            topic.name.write_flexible(&mut buffer, flexible);
+            write_array_length(&mut buffer, topic.partition_indexes.len(), flexible);
+            for partition in topic.partition_indexes.iter() {
+                partition.write_to_buffer(&mut buffer);
+            }
+            write_tagged_fields(&mut buffer, flexible);
+        }
+        if version >= 7 {
+            self.require_stable.write_to_buffer(&mut buffer);
+        }
+        write_tagged_fields(&mut buffer, flexible);
+        buffer
+    }
+}
+
+impl KafkaRequest for OffsetFetchRequest {
+    type Response = OffsetFetchResponse;
+    const API_KEY: ApiKeys = ApiKeys::OffsetFetch;
+    const MIN_VERSION: i16 = 1;
+    const MAX_VERSION: i16 = 7;
+    const FIRST_FLEXIBLE_VERSION: i16 = 6;
+}
+
+/// OffsetFetch Response (Version: 1-7) => throttle_time_ms [topics] error_code TAG_BUFFER
+///   throttle_time_ms => INT32 (version 3+)
+///   topics => name [partitions] TAG_BUFFER
+///     name => STRING
+///     partitions => partition_index committed_offset committed_leader_epoch metadata error_code TAG_BUFFER
+///       partition_index => INT32
+///       committed_offset => INT64
+///       committed_leader_epoch => INT32 (version 5+)
+///       metadata => NULLABLE_STRING
+///       error_code => INT16
+///   error_code => INT16 (version 2+)
+#[derive(Debug)]
+pub struct OffsetFetchResponse {
+    pub throttle_time_ms: i32,
+    pub topics: Vec<OffsetFetchTopicResponse>,
+    pub error_code: i16,
+}
+
+#[derive(Debug)]
+pub struct OffsetFetchTopicResponse {
+    pub name: KafkaString,
+    pub partitions: Vec<OffsetFetchPartitionResponse>,
+}
+
+#[derive(Debug)]
+pub struct OffsetFetchPartitionResponse {
+    pub partition_index: i32,
+    /// -1 when the group committed no offset for the partition.
+    pub committed_offset: i64,
+    pub committed_leader_epoch: i32,
+    pub metadata: KafkaNullableString,
+    pub error_code: i16,
+}
+
+impl FromBytes for OffsetFetchResponse {
+    fn get_from_bytes(buffer: &mut Cursor<Vec<u8>>, version: i16) -> Result<Self> {
+        let flexible = OffsetFetchRequest::is_flexible(version);
+        let mut response = Self {
+            throttle_time_ms: 0,
+            topics: vec![],
+            error_code: 0,
+        };
+        if version >= 3 {
+            response.throttle_time_ms = i32::read_from_buffer(buffer)?;
+        }
+        let topics_length = read_array_length(buffer, flexible)?;
+        for _ in 0..topics_length {
+            let mut topic = OffsetFetchTopicResponse {
+                name: KafkaString::read_flexible(buffer, flexible)?,
+                partitions: vec![],
+            };
+            let partitions_length = read_array_length(buffer, flexible)?;
+            for _ in 0..partitions_length {
+                let mut partition = OffsetFetchPartitionResponse {
+                    partition_index: i32::read_from_buffer(buffer)?,
+                    committed_offset: i64::read_from_buffer(buffer)?,
+                    committed_leader_epoch: -1,
+                    metadata: KafkaNullableString(None),
+                    error_code: 0,
+                };
+                if version >= 5 {
+                    partition.committed_leader_epoch = i32::read_from_buffer(buffer)?;
+                }
+                partition.metadata = KafkaNullableString::read_flexible(buffer, flexible)?;
+                partition.error_code = i16::read_from_buffer(buffer)?;
+                skip_tagged_fields(buffer, flexible)?;
+                topic.partitions.push(partition);
+            }
+            skip_tagged_fields(buffer, flexible)?;
+            response.topics.push(topic);
+        }
+        if version >= 2 {
+            response.error_code = i16::read_from_buffer(buffer)?;
+        }
+        skip_tagged_fields(buffer, flexible)?;
+        check_errors(response.error_code)?;
+        Ok(response)
+    }
+}
@@ -1,99 +1,476 @@
-use std::io::{Cursor, Read, Write};
-use std::process::exit;
+use std::io::{self, Cursor, Read, Write};
 use std::str::from_utf8;
-use serde::{Serialize, Deserialize};
+use serde::Serialize;
 
 use byteorder::{BE, ReadBytesExt, WriteBytesExt};
 
-pub trait KafkaPrimitive {
+use crate::error::{KafkaError, Result};
+
+pub trait KafkaPrimitive: Sized {
     fn write_to_buffer(&self, buffer: &mut Vec<u8>);
-    fn read_from_buffer(buffer: &mut Cursor<Vec<u8>>) -> Self;
+    fn read_from_buffer(buffer: &mut Cursor<Vec<u8>>) -> Result<Self>;
     fn length(&self) -> usize;
 }
 
+/// Running out of an in-memory buffer means the message was cut short, unlike the end of a socket.
+fn truncated(e: io::Error) -> KafkaError {
+    match e.kind() {
+        io::ErrorKind::UnexpectedEof => KafkaError::Truncated,
+        _ => KafkaError::Io(e),
+    }
+}
+
+fn read_bytes(buffer: &mut Cursor<Vec<u8>>, length: usize) -> Result<Vec<u8>> {
+    let remaining = buffer.get_ref().len().saturating_sub(buffer.position() as usize);
+    if length > remaining {
+        return Err(KafkaError::Truncated);
+    }
+    let mut bytes = vec![0_u8; length];
+    buffer.read_exact(&mut bytes).map_err(truncated)?;
+    Ok(bytes)
+}
+
+fn read_string(buffer: &mut Cursor<Vec<u8>>, length: usize) -> Result<String> {
+    let string_buffer = read_bytes(buffer, length)?;
+    Ok(from_utf8(string_buffer.as_slice())?.to_string())
+}
+
+fn unsized_read<T>() -> Result<T> {
+    Err(KafkaError::Codec(format!("{} has no length prefix and cannot be decoded",
+                                  std::any::type_name::<T>())))
+}
+
+
+fn write_unsigned_varint(buffer: &mut Vec<u8>, mut value: u64) {
+    while (value & !0x7f) != 0 {
+        buffer.write_u8(((value & 0x7f) | 0x80) as u8).unwrap();
+        value >>= 7;
+    }
+    buffer.write_u8(value as u8).unwrap();
+}
+
+/// Reads an unsigned varint that must fit in `bits` bits, rejecting longer encodings
+/// and a last byte carrying bits beyond that width.
+fn read_unsigned_varint(buffer: &mut Cursor<Vec<u8>>, bits: u32) -> Result<u64> {
+    let max_bytes = bits.div_ceil(7);
+    let mut value = 0_u64;
+    for i in 0..max_bytes {
+        let b = buffer.read_u8().map_err(truncated)? as u64;
+        let shift = i * 7;
+        if i == max_bytes - 1 && (b >> (bits - shift)) != 0 {
+            return Err(KafkaError::Codec(format!("varint overflows {} bits", bits)));
+        }
+        value |= (b & 0x7f) << shift;
+        if (b & 0x80) == 0 {
+            return Ok(value);
+        }
+    }
+    Err(KafkaError::Codec(format!("varint is longer than {} bytes", max_bytes)))
+}
 
+fn unsigned_varint_length(value: u64) -> usize {
+    let bits = 64 - value.leading_zeros() as usize;
+    1.max(bits.div_ceil(7))
+}
 
-#[derive(Debug, Clone, Serialize)]
+/// A signed INT32 written as a zigzag encoded varint, so that small negative values stay short.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize)]
 pub struct VarInt(pub i32);
 
+impl VarInt {
+    fn zigzag(&self) -> u32 {
+        ((self.0 << 1) ^ (self.0 >> 31)) as u32
+    }
+}
+
 impl KafkaPrimitive for VarInt {
     fn write_to_buffer(&self, buffer: &mut Vec<u8>) {
-        let mut value = self.0.clone();
-        while (value as u32 & 0xffffff80) != 0 {
-            let b: u8 = ((value & 0x7f) | 0x80) as u8;
-            buffer.write_u8(b).unwrap();
-            value >>= 7;
+        write_unsigned_varint(buffer, self.zigzag() as u64);
+    }
+
+    fn read_from_buffer(buffer: &mut Cursor<Vec<u8>>) -> Result<Self> {
+        let value = read_unsigned_varint(buffer, 32)? as u32;
+        Ok(Self((value >> 1) as i32 ^ -((value & 1) as i32)))
+    }
+
+    fn length(&self) -> usize {
+        unsigned_varint_length(self.zigzag() as u64)
+    }
+}
+
+/// A signed INT64 written as a zigzag encoded varint.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize)]
+pub struct VarLong(pub i64);
+
+impl VarLong {
+    fn zigzag(&self) -> u64 {
+        ((self.0 << 1) ^ (self.0 >> 63)) as u64
+    }
+}
+
+impl KafkaPrimitive for VarLong {
+    fn write_to_buffer(&self, buffer: &mut Vec<u8>) {
+        write_unsigned_varint(buffer, self.zigzag());
+    }
+
+    fn read_from_buffer(buffer: &mut Cursor<Vec<u8>>) -> Result<Self> {
+        let value = read_unsigned_varint(buffer, 64)?;
+        Ok(Self((value >> 1) as i64 ^ -((value & 1) as i64)))
+    }
+
+    fn length(&self) -> usize {
+        unsigned_varint_length(self.zigzag())
+    }
+}
+
+/// An unsigned INT32 written as a varint, used for the lengths of compact types.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct UVarInt(pub u32);
+
+impl KafkaPrimitive for UVarInt {
+    fn write_to_buffer(&self, buffer: &mut Vec<u8>) {
+        write_unsigned_varint(buffer, self.0 as u64);
+    }
+
+    fn read_from_buffer(buffer: &mut Cursor<Vec<u8>>) -> Result<Self> {
+        Ok(Self(read_unsigned_varint(buffer, 32)? as u32))
+    }
+
+    fn length(&self) -> usize {
+        unsigned_varint_length(self.0 as u64)
+    }
+}
+
+/// Bytes of a record prefixed by their length as a varint, -1 standing for null.
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct KafkaValue(pub Option<Vec<u8>>);
+
+impl KafkaPrimitive for KafkaValue {
+    fn write_to_buffer(&self, buffer: &mut Vec<u8>) {
+        match self.0.as_ref() {
+            Some(value) => {
+                VarInt(value.len() as i32).write_to_buffer(buffer);
+                buffer.write_all(value.as_slice()).unwrap();
+            }
+            None => VarInt(-1).write_to_buffer(buffer),
         }
-        buffer.write_u8(value as u8).unwrap();
     }
 
-    fn read_from_buffer(buffer: &mut Cursor<Vec<u8>>) -> Self {
-        let mut i = 0;
-        let mut value = 0;
-        let mut b = buffer.read_u8().unwrap() as i32;
-        while (b & 0x80) != 0 {
-            value |= (b & 0x7f) << i;
-            i += 7;
-            b = buffer.read_u8().unwrap() as i32;
+    fn read_from_buffer(buffer: &mut Cursor<Vec<u8>>) -> Result<Self> {
+        let value_len = VarInt::read_from_buffer(buffer)?;
+        if value_len.0 < 0 {
+            return Ok(Self(None));
         }
-        value |= b << i;
-        value >>= 1;
-        Self(value)
+        Ok(Self(Some(read_bytes(buffer, value_len.0 as usize)?)))
     }
 
     fn length(&self) -> usize {
-        unimplemented!()
+        match self.0.as_ref() {
+            Some(value) => VarInt(value.len() as i32).length() + value.len(),
+            None => VarInt(-1).length(),
+        }
     }
 }
 
-#[derive(Debug, Clone, Serialize)]
-pub struct KafkaValue(pub Vec<u8>);
 
-impl KafkaPrimitive for KafkaValue {
+#[derive(Debug, Clone, Default)]
+pub struct KafkaBytes(pub Vec<u8>);
+
+impl KafkaPrimitive for KafkaBytes {
     fn write_to_buffer(&self, buffer: &mut Vec<u8>) {
-        let value_len = VarInt(self.0.len() as i32);
-        value_len.write_to_buffer(buffer);
+        buffer.write_i32::<BE>(self.0.len() as i32).unwrap();
         buffer.write_all(self.0.as_slice()).unwrap();
     }
 
-    fn read_from_buffer(buffer: &mut Cursor<Vec<u8>>) -> Self {
-        let value_len = VarInt::read_from_buffer(buffer);
-        let mut value_buffer = vec![0 as u8; value_len.0 as usize];
-        buffer.read_exact(&mut value_buffer).unwrap();
-        Self(value_buffer)
+    fn read_from_buffer(buffer: &mut Cursor<Vec<u8>>) -> Result<Self> {
+        let bytes_len = buffer.read_i32::<BE>().map_err(truncated)?;
+        if bytes_len < 0 {
+            return Ok(Self(vec![]));
+        }
+        Ok(Self(read_bytes(buffer, bytes_len as usize)?))
     }
 
     fn length(&self) -> usize {
-        unimplemented!()
+        4 + self.0.len()
+    }
+}
+
+/// NULLABLE_BYTES, prefixed by their length as an INT32, -1 standing for null.
+#[derive(Debug, Clone, Default)]
+pub struct KafkaNullableBytes(pub Option<Vec<u8>>);
+
+impl KafkaPrimitive for KafkaNullableBytes {
+    fn write_to_buffer(&self, buffer: &mut Vec<u8>) {
+        match self.0.as_ref() {
+            Some(bytes) => {
+                buffer.write_i32::<BE>(bytes.len() as i32).unwrap();
+                buffer.write_all(bytes.as_slice()).unwrap();
+            }
+            None => buffer.write_i32::<BE>(-1).unwrap(),
+        }
     }
+
+    fn read_from_buffer(buffer: &mut Cursor<Vec<u8>>) -> Result<Self> {
+        let bytes_len = buffer.read_i32::<BE>().map_err(truncated)?;
+        if bytes_len < 0 {
+            return Ok(Self(None));
+        }
+        Ok(Self(Some(read_bytes(buffer, bytes_len as usize)?)))
+    }
+
+    fn length(&self) -> usize {
+        4 + self.0.as_ref().map_or(0, |bytes| bytes.len())
+    }
+}
+
+impl FlexiblePrimitive for KafkaBytes {
+    fn write_compact(&self, buffer: &mut Vec<u8>) {
+        write_compact_bytes(buffer, Some(self.0.as_slice()));
+    }
+
+    fn read_compact(buffer: &mut Cursor<Vec<u8>>) -> Result<Self> {
+        Ok(Self(KafkaCompactBytes::read_from_buffer(buffer)?.0))
+    }
+}
+
+
+/// Writes the length of a compact string, bytes or array, stored plus one so that 0 means null.
+fn write_compact_length(buffer: &mut Vec<u8>, length: Option<usize>) {
+    let length = length.map_or(0, |length| length as u32 + 1);
+    UVarInt(length).write_to_buffer(buffer);
+}
+
+/// Reads the length written by `write_compact_length`, None standing for null.
+fn read_compact_length(buffer: &mut Cursor<Vec<u8>>) -> Result<Option<usize>> {
+    let length = UVarInt::read_from_buffer(buffer)?.0;
+    Ok(length.checked_sub(1).map(|length| length as usize))
+}
+
+fn write_compact_bytes(buffer: &mut Vec<u8>, bytes: Option<&[u8]>) {
+    write_compact_length(buffer, bytes.map(|bytes| bytes.len()));
+    if let Some(bytes) = bytes {
+        buffer.write_all(bytes).unwrap();
+    }
+}
+
+fn read_compact_string(buffer: &mut Cursor<Vec<u8>>) -> Result<Option<String>> {
+    match read_compact_length(buffer)? {
+        Some(length) => Ok(Some(read_string(buffer, length)?)),
+        None => Ok(None),
+    }
+}
+
+/// Encoding of a primitive in the flexible versions of a message, introduced by KIP-482,
+/// where strings, bytes and arrays carry their length as an unsigned varint.
+pub trait FlexiblePrimitive: KafkaPrimitive {
+    fn write_compact(&self, buffer: &mut Vec<u8>);
+    fn read_compact(buffer: &mut Cursor<Vec<u8>>) -> Result<Self>;
+
+    fn write_flexible(&self, buffer: &mut Vec<u8>, flexible: bool) {
+        if flexible {
+            self.write_compact(buffer);
+        } else {
+            self.write_to_buffer(buffer);
+        }
+    }
+
+    fn read_flexible(buffer: &mut Cursor<Vec<u8>>, flexible: bool) -> Result<Self> {
+        if flexible {
+            Self::read_compact(buffer)
+        } else {
+            Self::read_from_buffer(buffer)
+        }
+    }
+}
+
+/// Writes the length of an array of structures, whose elements are then written one by one.
+pub fn write_array_length(buffer: &mut Vec<u8>, length: usize, flexible: bool) {
+    if flexible {
+        write_compact_length(buffer, Some(length));
+    } else {
+        buffer.write_i32::<BE>(length as i32).unwrap();
+    }
+}
+
+pub fn write_null_array(buffer: &mut Vec<u8>, flexible: bool) {
+    if flexible {
+        write_compact_length(buffer, None);
+    } else {
+        buffer.write_i32::<BE>(-1).unwrap();
+    }
+}
+
+/// Reads the length of an array of structures, a null array being read as empty.
+pub fn read_array_length(buffer: &mut Cursor<Vec<u8>>, flexible: bool) -> Result<usize> {
+    if flexible {
+        return Ok(read_compact_length(buffer)?.unwrap_or(0));
+    }
+    Ok(buffer.read_i32::<BE>().map_err(truncated)?.max(0) as usize)
+}
+
+/// Writes the length of a BYTES or RECORDS field whose content is written separately.
+pub fn write_bytes_length(buffer: &mut Vec<u8>, length: usize, flexible: bool) {
+    write_array_length(buffer, length, flexible);
+}
+
+/// Reads the length of a BYTES or RECORDS field, a null value being read as empty.
+pub fn read_bytes_length(buffer: &mut Cursor<Vec<u8>>, flexible: bool) -> Result<usize> {
+    read_array_length(buffer, flexible)
 }
 
+/// Closes a structure with an empty set of tagged fields in flexible versions.
+pub fn write_tagged_fields(buffer: &mut Vec<u8>, flexible: bool) {
+    if flexible {
+        TaggedFields::default().write_to_buffer(buffer);
+    }
+}
 
+/// Reads the tagged fields closing a structure in flexible versions, none of which
+/// are used by this client.
+pub fn skip_tagged_fields(buffer: &mut Cursor<Vec<u8>>, flexible: bool) -> Result<()> {
+    if flexible {
+        TaggedFields::read_from_buffer(buffer)?;
+    }
+    Ok(())
+}
 
 #[derive(Debug, Clone)]
 pub struct KafkaCompactString(pub String);
 
 impl KafkaPrimitive for KafkaCompactString {
     fn write_to_buffer(&self, buffer: &mut Vec<u8>) {
-        let kafka_string = &self.0;
-        buffer.write_i16::<BE>((kafka_string.len() + 1) as i16).unwrap();
-        buffer.write_all(kafka_string.as_bytes()).unwrap();
+        write_compact_bytes(buffer, Some(self.0.as_bytes()));
     }
 
-    fn read_from_buffer(buffer: &mut Cursor<Vec<u8>>) -> Self {
-        let string_len = VarInt::read_from_buffer(buffer).0;
-        // if string_len == -1 {
-        //     return KafkaCompactString("".to_string());
-        // }
-        let mut string_buffer = vec![0 as u8; string_len as usize];
-        buffer.read_exact(&mut string_buffer).unwrap();
-        let kafka_string = from_utf8(string_buffer.as_slice()).unwrap().to_string();
-        KafkaCompactString(kafka_string)
+    fn read_from_buffer(buffer: &mut Cursor<Vec<u8>>) -> Result<Self> {
+        match read_compact_string(buffer)? {
+            Some(string) => Ok(KafkaCompactString(string)),
+            None => Err(KafkaError::Codec("compact string is null".to_string())),
+        }
     }
 
 
     fn length(&self) -> usize {
-        unimplemented!()
+        UVarInt(self.0.len() as u32 + 1).length() + self.0.len()
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct KafkaCompactNullableString(pub Option<String>);
+
+impl KafkaPrimitive for KafkaCompactNullableString {
+    fn write_to_buffer(&self, buffer: &mut Vec<u8>) {
+        write_compact_bytes(buffer, self.0.as_ref().map(|string| string.as_bytes()));
+    }
+
+    fn read_from_buffer(buffer: &mut Cursor<Vec<u8>>) -> Result<Self> {
+        Ok(KafkaCompactNullableString(read_compact_string(buffer)?))
+    }
+
+    fn length(&self) -> usize {
+        match self.0.as_ref() {
+            Some(string) => UVarInt(string.len() as u32 + 1).length() + string.len(),
+            None => 1,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct KafkaCompactBytes(pub Vec<u8>);
+
+impl KafkaPrimitive for KafkaCompactBytes {
+    fn write_to_buffer(&self, buffer: &mut Vec<u8>) {
+        write_compact_bytes(buffer, Some(self.0.as_slice()));
+    }
+
+    fn read_from_buffer(buffer: &mut Cursor<Vec<u8>>) -> Result<Self> {
+        match read_compact_length(buffer)? {
+            Some(length) => Ok(Self(read_bytes(buffer, length)?)),
+            None => Ok(Self(vec![])),
+        }
+    }
+
+    fn length(&self) -> usize {
+        UVarInt(self.0.len() as u32 + 1).length() + self.0.len()
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct KafkaCompactArray<T: KafkaPrimitive>(pub Vec<T>);
+
+impl<T: KafkaPrimitive> KafkaPrimitive for KafkaCompactArray<T> {
+    fn write_to_buffer(&self, buffer: &mut Vec<u8>) {
+        write_compact_length(buffer, Some(self.0.len()));
+        for i in self.0.iter() {
+            i.write_to_buffer(buffer)
+        }
+    }
+
+    fn read_from_buffer(buffer: &mut Cursor<Vec<u8>>) -> Result<Self> {
+        let array_len = read_compact_length(buffer)?.unwrap_or(0);
+        let mut kafka_array = vec![];
+        for _ in 0..array_len {
+            kafka_array.push(T::read_from_buffer(buffer)?);
+        }
+        Ok(KafkaCompactArray(kafka_array))
+    }
+
+    fn length(&self) -> usize {
+        UVarInt(self.0.len() as u32 + 1).length() + self.0.iter().map(|i| i.length()).sum::<usize>()
+    }
+}
+
+/// A tagged field, whose data is kept undecoded.
+#[derive(Debug, Clone)]
+pub struct TaggedField {
+    pub tag: u32,
+    pub data: Vec<u8>,
+}
+
+/// The tagged fields closing every structure in the flexible versions of a message.
+/// Fields unknown to the reader are kept as raw bytes, and written back sorted by tag.
+#[derive(Debug, Clone, Default)]
+pub struct TaggedFields(pub Vec<TaggedField>);
+
+impl TaggedFields {
+    pub fn get(&self, tag: u32) -> Option<&[u8]> {
+        self.0.iter().find(|field| field.tag == tag).map(|field| field.data.as_slice())
+    }
+}
+
+impl KafkaPrimitive for TaggedFields {
+    fn write_to_buffer(&self, buffer: &mut Vec<u8>) {
+        let mut fields: Vec<&TaggedField> = self.0.iter().collect();
+        fields.sort_by_key(|field| field.tag);
+        UVarInt(fields.len() as u32).write_to_buffer(buffer);
+        for field in fields {
+            UVarInt(field.tag).write_to_buffer(buffer);
+            UVarInt(field.data.len() as u32).write_to_buffer(buffer);
+            buffer.write_all(field.data.as_slice()).unwrap();
+        }
+    }
+
+    fn read_from_buffer(buffer: &mut Cursor<Vec<u8>>) -> Result<Self> {
+        let fields_len = UVarInt::read_from_buffer(buffer)?.0;
+        let mut fields = vec![];
+        let mut last_tag = None;
+        for _ in 0..fields_len {
+            let tag = UVarInt::read_from_buffer(buffer)?.0;
+            if last_tag.is_some_and(|last_tag| tag <= last_tag) {
+                return Err(KafkaError::Codec(format!("tagged field {} is out of order", tag)));
+            }
+            last_tag = Some(tag);
+            let size = UVarInt::read_from_buffer(buffer)?.0;
+            fields.push(TaggedField { tag, data: read_bytes(buffer, size as usize)? });
+        }
+        Ok(TaggedFields(fields))
+    }
+
+    fn length(&self) -> usize {
+        let fields_length: usize = self.0.iter()
+            .map(|field| UVarInt(field.tag).length() + UVarInt(field.data.len() as u32).length() + field.data.len())
+            .sum();
+        UVarInt(self.0.len() as u32).length() + fields_length
     }
 }
 
@@ -108,15 +485,15 @@ impl KafkaPrimitive for KafkaString {
     }
 
 
-    fn read_from_buffer(buffer: &mut Cursor<Vec<u8>>) -> Self {
-        let string_len = buffer.read_i16::<BE>().unwrap();
+    fn read_from_buffer(buffer: &mut Cursor<Vec<u8>>) -> Result<Self> {
+        let string_len = buffer.read_i16::<BE>().map_err(truncated)?;
         if string_len == -1 {
-            return KafkaString("".to_string());
+            return Ok(KafkaString("".to_string()));
         }
-        let mut string_buffer = vec![0 as u8; string_len as usize];
-        buffer.read_exact(&mut string_buffer).unwrap();
-        let kafka_string = from_utf8(string_buffer.as_slice()).unwrap().to_string();
-        KafkaString(kafka_string)
+        if string_len < 0 {
+            return Err(KafkaError::Codec(format!("invalid string length {}", string_len)));
+        }
+        Ok(KafkaString(read_string(buffer, string_len as usize)?))
     }
 
 
@@ -125,29 +502,41 @@ impl KafkaPrimitive for KafkaString {
     }
 }
 
+impl FlexiblePrimitive for KafkaString {
+    fn write_compact(&self, buffer: &mut Vec<u8>) {
+        write_compact_bytes(buffer, Some(self.0.as_bytes()));
+    }
+
+    fn read_compact(buffer: &mut Cursor<Vec<u8>>) -> Result<Self> {
+        Ok(KafkaString(read_compact_string(buffer)?.unwrap_or_default()))
+    }
+}
+
 #[derive(Debug, Clone)]
 pub struct KafkaNullableString(pub Option<String>);
 
 impl KafkaPrimitive for KafkaNullableString {
     fn write_to_buffer(&self, buffer: &mut Vec<u8>) {
-        if self.0.is_none() {
-            buffer.write_i16::<BE>(-1).unwrap();
-            return;
-        }
-        let kafka_string = self.0.as_ref().unwrap();
+        let kafka_string = match self.0.as_ref() {
+            Some(kafka_string) => kafka_string,
+            None => {
+                buffer.write_i16::<BE>(-1).unwrap();
+                return;
+            }
+        };
         buffer.write_i16::<BE>(kafka_string.len() as i16).unwrap();
         buffer.write_all(kafka_string.as_bytes()).unwrap();
     }
 
-    fn read_from_buffer(buffer: &mut Cursor<Vec<u8>>) -> Self {
-        let string_len = buffer.read_i16::<BE>().unwrap();
+    fn read_from_buffer(buffer: &mut Cursor<Vec<u8>>) -> Result<Self> {
+        let string_len = buffer.read_i16::<BE>().map_err(truncated)?;
         if string_len == -1 {
-            return KafkaNullableString(None);
+            return Ok(KafkaNullableString(None));
+        }
+        if string_len < 0 {
+            return Err(KafkaError::Codec(format!("invalid string length {}", string_len)));
         }
-        let mut string_buffer = vec![0 as u8; string_len as usize];
-        buffer.read_exact(&mut string_buffer).unwrap();
-        let kafka_string = from_utf8(string_buffer.as_slice()).unwrap().to_string();
-        KafkaNullableString(Some(kafka_string))
+        Ok(KafkaNullableString(Some(read_string(buffer, string_len as usize)?)))
     }
 
 
@@ -157,12 +546,22 @@ impl KafkaPrimitive for KafkaNullableString {
 }
 
 
+impl FlexiblePrimitive for KafkaNullableString {
+    fn write_compact(&self, buffer: &mut Vec<u8>) {
+        write_compact_bytes(buffer, self.0.as_ref().map(|string| string.as_bytes()));
+    }
+
+    fn read_compact(buffer: &mut Cursor<Vec<u8>>) -> Result<Self> {
+        Ok(KafkaNullableString(read_compact_string(buffer)?))
+    }
+}
+
 #[derive(Debug, Clone)]
 pub struct KafkaArray<T: KafkaPrimitive>(pub Vec<T>);
 
 impl<T: KafkaPrimitive> KafkaPrimitive for KafkaArray<T> {
     fn write_to_buffer(&self, buffer: &mut Vec<u8>) {
-        if self.0.len() == 0 {
+        if self.0.is_empty() {
             buffer.write_i32::<BE>(-1).unwrap();
             return;
         }
@@ -173,16 +572,13 @@ impl<T: KafkaPrimitive> KafkaPrimitive for KafkaArray<T> {
         }
     }
 
-    fn read_from_buffer(buffer: &mut Cursor<Vec<u8>>) -> Self {
-        let array_len = buffer.read_i32::<BE>().unwrap();
-        let mut array_buffer = Vec::with_capacity(*&array_len as usize);
-        buffer.read_exact(&mut array_buffer).unwrap();
-
+    fn read_from_buffer(buffer: &mut Cursor<Vec<u8>>) -> Result<Self> {
+        let array_len = buffer.read_i32::<BE>().map_err(truncated)?;
         let mut kafka_array = vec![];
         for _ in 0..array_len {
-            kafka_array.push(KafkaPrimitive::read_from_buffer(buffer));
+            kafka_array.push(KafkaPrimitive::read_from_buffer(buffer)?);
         }
-        KafkaArray(kafka_array)
+        Ok(KafkaArray(kafka_array))
     }
 
 
@@ -192,13 +588,31 @@ impl<T: KafkaPrimitive> KafkaPrimitive for KafkaArray<T> {
 }
 
 
+impl<T: KafkaPrimitive> FlexiblePrimitive for KafkaArray<T> {
+    fn write_compact(&self, buffer: &mut Vec<u8>) {
+        if self.0.is_empty() {
+            write_compact_length(buffer, None);
+            return;
+        }
+        write_compact_length(buffer, Some(self.0.len()));
+        for i in self.0.iter() {
+            i.write_to_buffer(buffer)
+        }
+    }
+
+    fn read_compact(buffer: &mut Cursor<Vec<u8>>) -> Result<Self> {
+        Ok(KafkaArray(KafkaCompactArray::read_from_buffer(buffer)?.0))
+    }
+}
+
+
 impl KafkaPrimitive for bool {
     fn write_to_buffer(&self, buffer: &mut Vec<u8>) {
         buffer.write_i8(*self as i8).unwrap();
     }
 
-    fn read_from_buffer(buffer: &mut Cursor<Vec<u8>>) -> Self {
-        buffer.read_i8().unwrap() != 0
+    fn read_from_buffer(buffer: &mut Cursor<Vec<u8>>) -> Result<Self> {
+        Ok(buffer.read_i8().map_err(truncated)? != 0)
     }
 
 
@@ -212,8 +626,8 @@ impl KafkaPrimitive for i8 {
         buffer.write_i8(*self).unwrap();
     }
 
-    fn read_from_buffer(buffer: &mut Cursor<Vec<u8>>) -> Self {
-        buffer.read_i8().unwrap()
+    fn read_from_buffer(buffer: &mut Cursor<Vec<u8>>) -> Result<Self> {
+        buffer.read_i8().map_err(truncated)
     }
 
 
@@ -227,8 +641,8 @@ impl KafkaPrimitive for i16 {
         buffer.write_i16::<BE>(*self).unwrap();
     }
 
-    fn read_from_buffer(buffer: &mut Cursor<Vec<u8>>) -> Self {
-        buffer.read_i16::<BE>().unwrap()
+    fn read_from_buffer(buffer: &mut Cursor<Vec<u8>>) -> Result<Self> {
+        buffer.read_i16::<BE>().map_err(truncated)
     }
 
 
@@ -242,8 +656,8 @@ impl KafkaPrimitive for i32 {
         buffer.write_i32::<BE>(*self).unwrap();
     }
 
-    fn read_from_buffer(buffer: &mut Cursor<Vec<u8>>) -> Self {
-        buffer.read_i32::<BE>().unwrap()
+    fn read_from_buffer(buffer: &mut Cursor<Vec<u8>>) -> Result<Self> {
+        buffer.read_i32::<BE>().map_err(truncated)
     }
 
 
@@ -257,8 +671,8 @@ impl KafkaPrimitive for i64 {
         buffer.write_i64::<BE>(*self).unwrap();
     }
 
-    fn read_from_buffer(buffer: &mut Cursor<Vec<u8>>) -> Self {
-        buffer.read_i64::<BE>().unwrap()
+    fn read_from_buffer(buffer: &mut Cursor<Vec<u8>>) -> Result<Self> {
+        buffer.read_i64::<BE>().map_err(truncated)
     }
 
 
@@ -272,8 +686,8 @@ impl KafkaPrimitive for &str {
         buffer.write_all(self.as_bytes()).unwrap();
     }
 
-    fn read_from_buffer(buffer: &mut Cursor<Vec<u8>>) -> Self {
-        unimplemented!()
+    fn read_from_buffer(_buffer: &mut Cursor<Vec<u8>>) -> Result<Self> {
+        unsized_read()
     }
 
 
@@ -287,8 +701,8 @@ impl KafkaPrimitive for String {
         buffer.write_all(self.as_bytes()).unwrap();
     }
 
-    fn read_from_buffer(buffer: &mut Cursor<Vec<u8>>) -> Self {
-        unimplemented!()
+    fn read_from_buffer(_buffer: &mut Cursor<Vec<u8>>) -> Result<Self> {
+        unsized_read()
     }
 
     fn length(&self) -> usize {
@@ -301,15 +715,13 @@ impl KafkaPrimitive for Vec<u8> {
         buffer.write_all(self.as_slice()).unwrap();
     }
 
-    fn read_from_buffer(buffer: &mut Cursor<Vec<u8>>) -> Self {
-        let mut bytes_buffer = Vec::new();
-        buffer.read_exact(&mut bytes_buffer).unwrap();
-        bytes_buffer
+    fn read_from_buffer(_buffer: &mut Cursor<Vec<u8>>) -> Result<Self> {
+        unsized_read()
     }
 
 
     fn length(&self) -> usize {
-        unimplemented!()
+        self.len()
     }
 }
 
@@ -320,8 +732,8 @@ impl<T: KafkaPrimitive + Copy + Default> KafkaPrimitive for Vec<T> {
         }
     }
 
-    fn read_from_buffer(buffer: &mut Cursor<Vec<u8>>) -> Self {
-        unimplemented!()
+    fn read_from_buffer(_buffer: &mut Cursor<Vec<u8>>) -> Result<Self> {
+        unsized_read()
     }
 
 
@@ -337,8 +749,8 @@ impl<T: KafkaPrimitive + Copy + Default> KafkaPrimitive for &[T] {
         }
     }
 
-    fn read_from_buffer(buffer: &mut Cursor<Vec<u8>>) -> Self {
-        unimplemented!()
+    fn read_from_buffer(_buffer: &mut Cursor<Vec<u8>>) -> Result<Self> {
+        unsized_read()
     }
 
 
@@ -346,3 +758,81 @@ impl<T: KafkaPrimitive + Copy + Default> KafkaPrimitive for &[T] {
         unimplemented!()
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn written<T: KafkaPrimitive>(value: &T) -> Vec<u8> {
+        let mut buffer = Vec::new();
+        value.write_to_buffer(&mut buffer);
+        buffer
+    }
+
+    fn read<T: KafkaPrimitive>(bytes: &[u8]) -> Result<T> {
+        T::read_from_buffer(&mut Cursor::new(bytes.to_vec()))
+    }
+
+    #[test]
+    fn varint_zigzag_encoding() {
+        let cases: &[(i32, &[u8])] = &[
+            (0, &[0x00]),
+            (-1, &[0x01]),
+            (1, &[0x02]),
+            (-64, &[0x7f]),
+            (64, &[0x80, 0x01]),
+            (300, &[0xd8, 0x04]),
+            (i32::MAX, &[0xfe, 0xff, 0xff, 0xff, 0x0f]),
+            (i32::MIN, &[0xff, 0xff, 0xff, 0xff, 0x0f]),
+        ];
+        for &(value, bytes) in cases {
+            assert_eq!(written(&VarInt(value)), bytes, "{}", value);
+            assert_eq!(VarInt(value).length(), bytes.len());
+            assert_eq!(read::<VarInt>(bytes).unwrap(), VarInt(value));
+        }
+    }
+
+    #[test]
+    fn varlong_zigzag_encoding() {
+        let cases: &[(i64, &[u8])] = &[
+            (0, &[0x00]),
+            (-1, &[0x01]),
+            (1, &[0x02]),
+            (1 << 35, &[0x80, 0x80, 0x80, 0x80, 0x80, 0x02]),
+            (i64::MAX, &[0xfe, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0x01]),
+            (i64::MIN, &[0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0x01]),
+        ];
+        for &(value, bytes) in cases {
+            assert_eq!(written(&VarLong(value)), bytes, "{}", value);
+            assert_eq!(VarLong(value).length(), bytes.len());
+            assert_eq!(read::<VarLong>(bytes).unwrap(), VarLong(value));
+        }
+    }
+
+    #[test]
+    fn varint_rejects_overflow_and_overlong_encodings() {
+        assert!(read::<VarInt>(&[0xff, 0xff, 0xff, 0xff, 0x1f]).is_err());
+        assert!(read::<VarInt>(&[0x80, 0x80, 0x80, 0x80, 0x80, 0x00]).is_err());
+        assert!(read::<VarLong>(&[0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0x03]).is_err());
+        assert!(matches!(read::<VarInt>(&[0x80]), Err(KafkaError::Truncated)));
+    }
+
+    #[test]
+    fn compact_array_length_matches_bytes_written() {
+        let empty: KafkaCompactArray<i32> = KafkaCompactArray(vec![]);
+        assert_eq!(empty.length(), written(&empty).len());
+        let array = KafkaCompactArray((0..200).collect::<Vec<i32>>());
+        assert_eq!(array.length(), written(&array).len());
+        assert_eq!(array.length(), 2 + 200 * 4);
+    }
+
+    #[test]
+    fn tagged_fields_length_matches_bytes_written() {
+        assert_eq!(TaggedFields::default().length(), 1);
+        let fields = TaggedFields(vec![
+            TaggedField { tag: 0, data: vec![1, 2, 3] },
+            TaggedField { tag: 300, data: vec![0; 200] },
+        ]);
+        assert_eq!(fields.length(), written(&fields).len());
+    }
+}
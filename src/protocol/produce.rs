@@ -1,8 +1,12 @@
 use std::io::Cursor;
 
-use crate::protocol::primitives::{KafkaPrimitive, KafkaString, KafkaNullableString, VarInt};
-use crate::protocol::record::{Record, RecordBatch};
-use crate::protocol::request::ToBytes;
+use crate::error::Result;
+use crate::protocol::api_keys::ApiKeys;
+use crate::protocol::primitives::{read_array_length, skip_tagged_fields, write_array_length, write_bytes_length,
+                                  write_tagged_fields, FlexiblePrimitive, KafkaPrimitive, KafkaString,
+                                  KafkaNullableString};
+use crate::protocol::record::RecordBatch;
+use crate::protocol::request::{KafkaRequest, ToBytes};
 use crate::protocol::response::FromBytes;
 
 
@@ -20,118 +24,177 @@ struct ProduceTopicDataRequest {
 
 struct ProduceDataRequest {
     partition: i32,
-    record_batches: Vec<RecordBatch>,
+    /// The batches added, already encoded.
+    records: Vec<u8>,
 }
 
 impl ProduceRequest {
-    pub fn new(topic: String, key: Vec<u8>, value: Vec<u8>) -> Self {
-        let record = Record::new(key, value);
-        let produce_request = ProduceDataRequest {
-            partition: 0,
-            record_batches: vec![RecordBatch::new(vec![record])],
-        };
+    pub fn new(transactional_id: Option<String>, acks: i16, timeout: i32) -> Self {
         Self {
-            transactional_id: KafkaNullableString(Some("kjh".to_string())),
-            acks: 0,
-            timeout: 5000,
-            topic_data: vec![ProduceTopicDataRequest {
-                topic: KafkaString(topic),
-                data: vec![produce_request]
-            }]
+            transactional_id: KafkaNullableString(transactional_id),
+            acks,
+            timeout,
+            topic_data: vec![],
         }
     }
+
+    /// Encodes a batch into the records of its partition, failing when its codec cannot compress it.
+    pub fn add_batch(&mut self, topic: &str, partition: i32, record_batch: &RecordBatch) -> Result<()> {
+        let records = record_batch.encode()?;
+        let topic_data = match self.topic_data.iter().position(|topic_data| topic_data.topic.0 == topic) {
+            Some(index) => &mut self.topic_data[index],
+            None => {
+                self.topic_data.push(ProduceTopicDataRequest {
+                    topic: KafkaString(topic.to_string()),
+                    data: vec![],
+                });
+                self.topic_data.last_mut().unwrap()
+            }
+        };
+        match topic_data.data.iter_mut().find(|data| data.partition == partition) {
+            Some(data) => data.records.extend(records),
+            None => topic_data.data.push(ProduceDataRequest { partition, records }),
+        }
+        Ok(())
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.topic_data.is_empty()
+    }
 }
 
-/// Produce Request (Version: 6) => transactional_id acks timeout [topic_data]
+/// Produce Request (Version: 3-9) => transactional_id acks timeout [topic_data] TAG_BUFFER
 ///   transactional_id => NULLABLE_STRING
 ///   acks => INT16
 ///   timeout => INT32
-///   topic_data => topic [data]
+///   topic_data => topic [data] TAG_BUFFER
 ///     topic => STRING
-///     data => partition record_set
+///     data => partition record_set TAG_BUFFER
 ///       partition => INT32
 ///       record_set => RECORDS
 impl ToBytes for ProduceRequest {
-    fn get_in_bytes(&self) -> Vec<u8> {
+    fn get_in_bytes(&self, version: i16) -> Vec<u8> {
+        let flexible = Self::is_flexible(version);
         let mut buffer = Vec::new();
-        self.transactional_id.write_to_buffer(&mut buffer);
+        self.transactional_id.write_flexible(&mut buffer, flexible);
         self.acks.write_to_buffer(&mut buffer);
         self.timeout.write_to_buffer(&mut buffer);
-        (self.topic_data.len() as i32).write_to_buffer(&mut buffer);
+        write_array_length(&mut buffer, self.topic_data.len(), flexible);
         for topic_data in self.topic_data.iter() {
-            topic_data.topic.write_to_buffer(&mut buffer);
-            (topic_data.data.len() as i32).write_to_buffer(&mut buffer);
+            topic_data.topic.write_flexible(&mut buffer, flexible);
+            write_array_length(&mut buffer, topic_data.data.len(), flexible);
             for data in &topic_data.data {
                 data.partition.write_to_buffer(&mut buffer);
-                for record_batch in data.record_batches.iter() {
-                    let record = record_batch.get_in_bytes();
-                    (record.len() as i32).write_to_buffer(&mut buffer);
-                    record.write_to_buffer(&mut buffer);
-                }
+                write_bytes_length(&mut buffer, data.records.len(), flexible);
+                data.records.write_to_buffer(&mut buffer);
+                write_tagged_fields(&mut buffer, flexible);
             }
+            write_tagged_fields(&mut buffer, flexible);
         }
+        write_tagged_fields(&mut buffer, flexible);
         buffer
     }
 }
 
-/// Produce Response (Version: 6) => [responses] throttle_time_ms
-///   responses => topic [partition_responses]
+impl KafkaRequest for ProduceRequest {
+    type Response = ProduceResponse;
+    const API_KEY: ApiKeys = ApiKeys::Produce;
+    const MIN_VERSION: i16 = 3;
+    const MAX_VERSION: i16 = 9;
+    const FIRST_FLEXIBLE_VERSION: i16 = 9;
+}
+
+/// Produce Response (Version: 3-9) => [responses] throttle_time_ms TAG_BUFFER
+///   responses => topic [partition_responses] TAG_BUFFER
 ///     topic => STRING
-///     partition_responses => partition error_code base_offset log_append_time log_start_offset
+///     partition_responses => partition error_code base_offset log_append_time log_start_offset [record_errors] error_message TAG_BUFFER
 ///       partition => INT32
 ///       error_code => INT16
 ///       base_offset => INT64
 ///       log_append_time => INT64
-///       log_start_offset => INT64
+///       log_start_offset => INT64 (version 5+)
+///       record_errors => batch_index batch_index_error_message TAG_BUFFER (version 8+)
+///         batch_index => INT32
+///         batch_index_error_message => NULLABLE_STRING
+///       error_message => NULLABLE_STRING (version 8+)
 ///   throttle_time_ms => INT32
 #[derive(Debug)]
 pub struct ProduceResponse {
-    responses: Vec<TopicResponse>,
-    throttle_time_ms: i32,
+    pub responses: Vec<TopicResponse>,
+    pub throttle_time_ms: i32,
 }
 #[derive(Debug)]
 pub struct TopicResponse {
-    topic: KafkaString,
-    partition_responses: Vec<PartitionResponse>,
+    pub topic: KafkaString,
+    pub partition_responses: Vec<PartitionResponse>,
 }
 #[derive(Debug)]
-struct PartitionResponse {
-    partition: i32,
-    error_code: i16,
-    base_offset: i64,
-    log_append_time: i64,
-    log_start_offset: i64,
+pub struct PartitionResponse {
+    pub partition: i32,
+    pub error_code: i16,
+    pub base_offset: i64,
+    pub log_append_time: i64,
+    pub log_start_offset: i64,
+    pub record_errors: Vec<RecordError>,
+    pub error_message: KafkaNullableString,
+}
+
+#[derive(Debug)]
+pub struct RecordError {
+    pub batch_index: i32,
+    pub batch_index_error_message: KafkaNullableString,
 }
 
 impl FromBytes for ProduceResponse {
-    fn get_from_bytes(buffer: &mut Cursor<Vec<u8>>) -> Self {
+    fn get_from_bytes(buffer: &mut Cursor<Vec<u8>>, version: i16) -> Result<Self> {
+        let flexible = ProduceRequest::is_flexible(version);
         let mut response = Self {
             responses: vec![],
             throttle_time_ms: 0
         };
 
-        let responses_len = i32::read_from_buffer(buffer);
+        let responses_len = read_array_length(buffer, flexible)?;
         for _ in 0..responses_len {
             let mut topic_response = TopicResponse {
-                topic: KafkaString::read_from_buffer(buffer),
+                topic: KafkaString::read_flexible(buffer, flexible)?,
                 partition_responses: vec![]
             };
 
-            let partition_responses_len = i32::read_from_buffer(buffer);
+            let partition_responses_len = read_array_length(buffer, flexible)?;
             for _ in 0..partition_responses_len {
-                let partition_response = PartitionResponse {
-                    partition: i32::read_from_buffer(buffer),
-                    error_code: i16::read_from_buffer(buffer),
-                    base_offset: i64::read_from_buffer(buffer),
-                    log_append_time: i64::read_from_buffer(buffer),
-                    log_start_offset: i64::read_from_buffer(buffer),
+                let mut partition_response = PartitionResponse {
+                    partition: i32::read_from_buffer(buffer)?,
+                    error_code: i16::read_from_buffer(buffer)?,
+                    base_offset: i64::read_from_buffer(buffer)?,
+                    log_append_time: i64::read_from_buffer(buffer)?,
+                    log_start_offset: -1,
+                    record_errors: vec![],
+                    error_message: KafkaNullableString(None),
                 };
+                if version >= 5 {
+                    partition_response.log_start_offset = i64::read_from_buffer(buffer)?;
+                }
+                if version >= 8 {
+                    let record_errors_len = read_array_length(buffer, flexible)?;
+                    for _ in 0..record_errors_len {
+                        let record_error = RecordError {
+                            batch_index: i32::read_from_buffer(buffer)?,
+                            batch_index_error_message: KafkaNullableString::read_flexible(buffer, flexible)?,
+                        };
+                        skip_tagged_fields(buffer, flexible)?;
+                        partition_response.record_errors.push(record_error);
+                    }
+                    partition_response.error_message = KafkaNullableString::read_flexible(buffer, flexible)?;
+                }
+                skip_tagged_fields(buffer, flexible)?;
                 topic_response.partition_responses.push(partition_response);
             }
+            skip_tagged_fields(buffer, flexible)?;
             response.responses.push(topic_response);
         }
 
-        response.throttle_time_ms = i32::read_from_buffer(buffer);
-        response
+        response.throttle_time_ms = i32::read_from_buffer(buffer)?;
+        skip_tagged_fields(buffer, flexible)?;
+        Ok(response)
     }
 }
\ No newline at end of file
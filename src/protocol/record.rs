@@ -1,8 +1,41 @@
-use crate::protocol::primitives::{KafkaPrimitive, VarInt, KafkaValue};
-use crate::protocol::request::ToBytes;
+use crate::protocol::compression::Compression;
+use crate::protocol::primitives::{KafkaNullableBytes, KafkaPrimitive, VarInt, VarLong, KafkaValue};
 use crate::protocol::response::FromBytes;
+use crate::utils::time_utils::current_timestamp;
 use std::io::Cursor;
-use serde::{Serialize, Deserialize};
+use serde::Serialize;
+use crate::error::{KafkaError, Result};
+
+const RECORD_BATCH_MAGIC: i8 = 2;
+/// Bytes of the baseOffset and batchLength fields, which batchLength does not count.
+const LOG_OVERHEAD: usize = 8 + 4;
+/// Bytes of a record batch before its first record.
+pub const RECORD_BATCH_OVERHEAD: usize = LOG_OVERHEAD + BATCH_LENGTH_OVERHEAD + RECORDS_OFFSET;
+const MAX_VARINT_SIZE: usize = 5;
+const MAX_VARLONG_SIZE: usize = 10;
+/// Bytes of the length, attributes, timestamp and offset deltas and headers count of a record at most.
+const MAX_RECORD_OVERHEAD: usize = MAX_VARINT_SIZE + 1 + MAX_VARLONG_SIZE + MAX_VARINT_SIZE + MAX_VARINT_SIZE;
+/// Position of the magic byte, shared by record batches and legacy messages.
+const MAGIC_OFFSET: usize = LOG_OVERHEAD + 4;
+/// Bytes from the attributes to the records count, the part of the batch never compressed.
+const RECORDS_OFFSET: usize = 2 + 4 + 8 + 8 + 8 + 2 + 4 + 4;
+/// Bytes of the partitionLeaderEpoch, magic and crc fields, counted in batchLength.
+const BATCH_LENGTH_OVERHEAD: usize = 4 + 1 + 4;
+const TIMESTAMP_TYPE_MASK: i16 = 0x08;
+const TRANSACTIONAL_MASK: i16 = 0x10;
+const CONTROL_MASK: i16 = 0x20;
+
+/// What the timestamp of a record stands for.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TimestampType {
+    /// Messages of the v0 format carry no timestamp.
+    NoTimestampType,
+    /// Set by the producer when the record was created.
+    CreateTime,
+    /// Set by the broker when it appended the batch to the log.
+    LogAppendTime,
+}
+
 ///
 ///  baseOffset: int64
 ///  batchLength: int32
@@ -10,16 +43,16 @@ use serde::{Serialize, Deserialize};
 ///  magic: int8 (current magic value is 2)
 ///  crc: int32
 ///  attributes: int16
-///  	bit 0~2:
-///  		0: no compression
-///  		1: gzip
-///  		2: snappy
-///  		3: lz4
-///  		4: zstd
-///  	bit 3: timestampType
-///  	bit 4: isTransactional (0 means not transactional)
-///  	bit 5: isControlBatch (0 means not a control batch)
-///  	bit 6~15: unused
+///      bit 0~2:
+///          0: no compression
+///          1: gzip
+///          2: snappy
+///          3: lz4
+///          4: zstd
+///      bit 3: timestampType
+///      bit 4: isTransactional (0 means not transactional)
+///      bit 5: isControlBatch (0 means not a control batch)
+///      bit 6~15: unused
 ///  lastOffsetDelta: int32
 ///  firstTimestamp: int64
 ///  maxTimestamp: int64
@@ -27,6 +60,9 @@ use serde::{Serialize, Deserialize};
 ///  producerEpoch: int16
 ///  baseSequence: int32
 ///  records: [Record]
+///
+/// The crc is a CRC-32C of every byte from the attributes to the end of the batch.
+/// With a compression codec set, the records following their count are compressed as a whole.
 #[derive(Debug, Serialize)]
 pub struct RecordBatch {
     pub base_offset: i64,
@@ -45,199 +81,639 @@ pub struct RecordBatch {
 }
 
 impl RecordBatch {
+    /// Builds a batch from records whose offsets are relative to the start of the batch,
+    /// the broker assigning the actual offsets when appending it to the log.
     pub fn new(records: Vec<Record>) -> Self {
         let mut batch = Self {
-            base_offset: 1,
+            base_offset: 0,
             batch_length: 0,
             partition_leader_epoch: -1,
-            magic: 2,
+            magic: RECORD_BATCH_MAGIC,
             crc: 0,
             attributes: 0,
             last_offset_delta: 0,
             first_timestamp: 0,
-            max_timestamp: -1,
+            max_timestamp: 0,
             producer_id: -1,
-            producer_epoch: 1,
+            producer_epoch: -1,
             base_sequence: -1,
             records
         };
-        // batch.batch_length = bincode::serialize(&batch).unwrap().len() as i32;
-        println!("{:#?}", bincode::serialize(&batch).unwrap().len() as i32);
-        batch.batch_length = 100 as i32;
+        for (offset, record) in batch.records.iter_mut().enumerate() {
+            record.offset = offset as i64;
+        }
+        batch.last_offset_delta = batch.records.len().saturating_sub(1) as i32;
+        batch.first_timestamp = batch.records.first().map_or(-1, |record| record.timestamp);
+        batch.max_timestamp = batch.records.iter().map(|record| record.timestamp).max().unwrap_or(-1);
         batch
     }
+
+    /// The offset of the last record the batch held when it was written, even if
+    /// compaction removed that record since.
+    pub fn last_offset(&self) -> i64 {
+        self.base_offset + self.last_offset_delta as i64
+    }
+
+    pub fn compression(&self) -> Result<Compression> {
+        Compression::from_attributes(self.attributes)
+    }
+
+    pub fn set_compression(&mut self, compression: Compression) {
+        self.attributes = compression.apply_to_attributes(self.attributes);
+    }
+
+    /// Whether the records belong to a transaction and are only visible once it commits.
+    pub fn is_transactional(&self) -> bool {
+        self.attributes & TRANSACTIONAL_MASK != 0
+    }
+
+    /// Whether the batch is a marker written by the broker, e.g. for the end of a transaction,
+    /// rather than records produced by a client.
+    pub fn is_control_batch(&self) -> bool {
+        self.attributes & CONTROL_MASK != 0
+    }
+
+    pub fn timestamp_type(&self) -> TimestampType {
+        if self.magic == 0 {
+            TimestampType::NoTimestampType
+        } else if self.attributes & TIMESTAMP_TYPE_MASK != 0 {
+            TimestampType::LogAppendTime
+        } else {
+            TimestampType::CreateTime
+        }
+    }
+
+    pub fn set_transactional(&mut self, transactional: bool) {
+        if transactional {
+            self.attributes |= TRANSACTIONAL_MASK;
+        } else {
+            self.attributes &= !TRANSACTIONAL_MASK;
+        }
+    }
+
+    fn read_records(buffer: &mut Cursor<Vec<u8>>, records_len: i32, batch: &RecordBatch) -> Result<Vec<Record>> {
+        let mut records = Vec::with_capacity(records_len.clamp(0, 1024) as usize);
+        for _ in 0..records_len.max(0) {
+            records.push(Record::read_from_buffer(buffer, batch.base_offset, batch.first_timestamp)?);
+        }
+        Ok(records)
+    }
+
+    /// Reads every batch of a RECORDS field of the given length.
+    ///
+    /// Brokers cut the records of a fetch at the requested size, so the last batch may be
+    /// incomplete; it is dropped and fetched again from its offset on the next request.
+    pub fn read_batches(buffer: &mut Cursor<Vec<u8>>, length: usize, version: i16) -> Result<Vec<RecordBatch>> {
+        let end = buffer.position() as usize + length;
+        let mut batches = Vec::new();
+        while (buffer.position() as usize) + LOG_OVERHEAD <= end {
+            let start = buffer.position();
+            let _base_offset = i64::read_from_buffer(buffer)?;
+            let batch_length = i32::read_from_buffer(buffer)?;
+            buffer.set_position(start);
+            if start as usize + LOG_OVERHEAD + batch_length.max(0) as usize > end {
+                break;
+            }
+            batches.push(RecordBatch::get_from_bytes(buffer, version)?);
+        }
+        buffer.set_position(end as u64);
+        Ok(batches)
+    }
 }
 
 /// length: varint
-/// 	attributes: int8
-/// 		bit 0~7: unused
-/// 	timestampDelta: varint
-/// 	offsetDelta: varint
-/// 	keyLength: varint
-/// 	key: byte[]
-/// 	valueLen: varint
-/// 	value: byte[]
-/// 	Headers => [Header]
-#[derive(Debug, Serialize)]
+///     attributes: int8
+///         bit 0~7: unused
+///     timestampDelta: varlong
+///     offsetDelta: varint
+///     keyLength: varint
+///     key: byte[]
+///     valueLen: varint
+///     value: byte[]
+///     Headers => [Header]
+///
+/// The offset and timestamp are kept absolute, the deltas being computed against
+/// the batch when encoding.
+#[derive(Debug, Clone, Serialize)]
 pub struct Record {
-    pub length: VarInt,
     pub attributes: i8,
-    pub timestamp_delta: VarInt,
-    pub offset_delta: VarInt,
-    pub key_length: VarInt,
-    pub key: KafkaValue,
-    pub value_length: VarInt,
-    pub value: KafkaValue,
+    pub offset: i64,
+    pub timestamp: i64,
+    pub key: Option<Vec<u8>>,
+    pub value: Option<Vec<u8>>,
     pub headers: Vec<RecordHeader>,
 }
 
 impl Record {
-    pub fn new(key: Vec<u8>, value: Vec<u8>) -> Self {
-        let mut record = Self {
-            length: VarInt(10),
+    pub fn new(key: Option<Vec<u8>>, value: Option<Vec<u8>>) -> Self {
+        Self {
             attributes: 0,
-            timestamp_delta: VarInt(0),
-            offset_delta: VarInt(1),
-            key_length: VarInt(key.len() as i32),
-            key: KafkaValue(key),
-            value_length: VarInt(value.len() as i32),
-            value: KafkaValue(value),
-            headers: RecordHeader::new()
+            offset: 0,
+            timestamp: current_timestamp(),
+            key,
+            value,
+            headers: vec![],
+        }
+    }
+
+    /// Upper bound of the bytes this record takes in an uncompressed batch.
+    pub fn size_in_bytes(&self) -> usize {
+        let value_size = |value: &Option<Vec<u8>>| MAX_VARINT_SIZE + value.as_ref().map_or(0, |value| value.len());
+        let headers_size: usize = self.headers.iter()
+            .map(|header| MAX_VARINT_SIZE + header.key.len() + value_size(&header.value))
+            .sum();
+        MAX_RECORD_OVERHEAD + value_size(&self.key) + value_size(&self.value) + headers_size
+    }
+
+    fn write_to_buffer(&self, buffer: &mut Vec<u8>, base_offset: i64, first_timestamp: i64) {
+        let mut record_buffer = Vec::new();
+        self.attributes.write_to_buffer(&mut record_buffer);
+        VarLong(self.timestamp - first_timestamp).write_to_buffer(&mut record_buffer);
+        VarInt((self.offset - base_offset) as i32).write_to_buffer(&mut record_buffer);
+        KafkaValue(self.key.clone()).write_to_buffer(&mut record_buffer);
+        KafkaValue(self.value.clone()).write_to_buffer(&mut record_buffer);
+
+        VarInt(self.headers.len() as i32).write_to_buffer(&mut record_buffer);
+        for header in self.headers.iter() {
+            KafkaValue(Some(header.key.as_bytes().to_vec())).write_to_buffer(&mut record_buffer);
+            KafkaValue(header.value.clone()).write_to_buffer(&mut record_buffer);
+        }
+
+        VarInt(record_buffer.len() as i32).write_to_buffer(buffer);
+        record_buffer.write_to_buffer(buffer);
+    }
+
+    fn read_from_buffer(buffer: &mut Cursor<Vec<u8>>, base_offset: i64, first_timestamp: i64) -> Result<Self> {
+        let length = VarInt::read_from_buffer(buffer)?;
+        if length.0 < 0 {
+            return Err(KafkaError::Codec(format!("invalid record length {}", length.0)));
+        }
+        let end = buffer.position() as usize + length.0 as usize;
+        let mut record = Record {
+            attributes: i8::read_from_buffer(buffer)?,
+            timestamp: first_timestamp + VarLong::read_from_buffer(buffer)?.0,
+            offset: base_offset + VarInt::read_from_buffer(buffer)?.0 as i64,
+            key: KafkaValue::read_from_buffer(buffer)?.0,
+            value: KafkaValue::read_from_buffer(buffer)?.0,
+            headers: vec![],
         };
-        // record.length = VarInt(bincode::serialize(&record).unwrap().len() as i32);
-        // record.length = VarInt(10 as i32);
-        record
+
+        let headers_len = VarInt::read_from_buffer(buffer)?;
+        for _ in 0..headers_len.0.max(0) {
+            let key = KafkaValue::read_from_buffer(buffer)?.0
+                .ok_or_else(|| KafkaError::Codec("record header key is null".to_string()))?;
+            let header = RecordHeader {
+                key: String::from_utf8(key).map_err(|e| KafkaError::Codec(e.to_string()))?,
+                value: KafkaValue::read_from_buffer(buffer)?.0,
+            };
+            record.headers.push(header);
+        }
+        if buffer.position() as usize != end {
+            return Err(KafkaError::Codec(format!(
+                "record at offset {} does not match its length {}", record.offset, length.0)));
+        }
+        Ok(record)
     }
 }
 
 /// headerKeyLength: varint
-/// 		headerKey: String
-/// 		headerValueLength: varint
-/// 		Value: byte[]
-#[derive(Debug, Serialize)]
+///         headerKey: String
+///         headerValueLength: varint
+///         Value: byte[]
+#[derive(Debug, Clone, Serialize)]
 pub struct RecordHeader {
-    pub header_key_length: VarInt,
-    pub header_key: KafkaValue,
-    pub header_value_length: VarInt,
-    pub header_value: KafkaValue,
+    pub key: String,
+    pub value: Option<Vec<u8>>,
 }
 
 impl RecordHeader {
-    pub fn new() -> Vec<RecordHeader> {
-        let header_key = KafkaValue("app".as_bytes().to_vec());
-        let header_value = KafkaValue("rust".as_bytes().to_vec());
-        let header = Self {
-            header_key_length: VarInt(header_key.0.len() as i32),
-            header_key,
-            header_value_length: VarInt(header_value.0.len() as i32),
-            header_value,
-        };
-        vec![header]
+    pub fn new(key: String, value: Option<Vec<u8>>) -> Self {
+        Self { key, value }
     }
 }
 
-impl ToBytes for RecordBatch {
-    fn get_in_bytes(&self) -> Vec<u8> {
-        let mut buffer = Vec::new();
-        self.base_offset.write_to_buffer(&mut buffer);
-        self.batch_length.write_to_buffer(&mut buffer);
-        self.partition_leader_epoch.write_to_buffer(&mut buffer);
-        self.magic.write_to_buffer(&mut buffer);
-        self.crc.write_to_buffer(&mut buffer);
-        self.attributes.write_to_buffer(&mut buffer);
-        self.last_offset_delta.write_to_buffer(&mut buffer);
-        self.first_timestamp.write_to_buffer(&mut buffer);
-        self.max_timestamp.write_to_buffer(&mut buffer);
-        self.producer_id.write_to_buffer(&mut buffer);
-        self.producer_epoch.write_to_buffer(&mut buffer);
-        self.base_sequence.write_to_buffer(&mut buffer);
-
-        let records_len = VarInt(self.records.len() as i32);
-        // let records_len = self.records.len() as i32;
-        records_len.write_to_buffer(&mut buffer);
+impl RecordBatch {
+    /// Encodes the batch, compressing its records with the codec of its attributes.
+    pub fn encode(&self) -> Result<Vec<u8>> {
+        let first_timestamp = self.records.first().map_or(-1, |record| record.timestamp);
+        let max_timestamp = self.records.iter().map(|record| record.timestamp).max().unwrap_or(-1);
+        let last_offset_delta = self.records.iter()
+            .map(|record| (record.offset - self.base_offset) as i32)
+            .max()
+            .unwrap_or(0);
+
         let mut records_buffer = Vec::new();
         for record in self.records.iter() {
-            record.length.write_to_buffer(&mut records_buffer);
-            record.attributes.write_to_buffer(&mut records_buffer);
-            record.timestamp_delta.write_to_buffer(&mut records_buffer);
-            record.offset_delta.write_to_buffer(&mut records_buffer);
-            record.key_length.write_to_buffer(&mut records_buffer);
-            record.key.write_to_buffer(&mut records_buffer);
-            record.value_length.write_to_buffer(&mut records_buffer);
-            record.value.write_to_buffer(&mut records_buffer);
-
-            let headers_len = record.headers.len() as i32;
-            headers_len.write_to_buffer(&mut records_buffer);
-            for header in record.headers.iter() {
-                header.header_key_length.write_to_buffer(&mut records_buffer);
-                header.header_key.write_to_buffer(&mut records_buffer);
-                header.header_value_length.write_to_buffer(&mut records_buffer);
-                header.header_value.write_to_buffer(&mut records_buffer);
-            }
+            record.write_to_buffer(&mut records_buffer, self.base_offset, first_timestamp);
         }
-        (records_buffer.len() as i32).write_to_buffer(&mut buffer);
-        records_buffer.write_to_buffer(&mut buffer);
-        buffer
+        let records_buffer = self.compression()?.compress(records_buffer.as_slice())?;
+
+        let mut crc_buffer = Vec::with_capacity(RECORDS_OFFSET + records_buffer.len());
+        self.attributes.write_to_buffer(&mut crc_buffer);
+        last_offset_delta.write_to_buffer(&mut crc_buffer);
+        first_timestamp.write_to_buffer(&mut crc_buffer);
+        max_timestamp.write_to_buffer(&mut crc_buffer);
+        self.producer_id.write_to_buffer(&mut crc_buffer);
+        self.producer_epoch.write_to_buffer(&mut crc_buffer);
+        self.base_sequence.write_to_buffer(&mut crc_buffer);
+        (self.records.len() as i32).write_to_buffer(&mut crc_buffer);
+        crc_buffer.extend_from_slice(records_buffer.as_slice());
+
+        let batch_length = (BATCH_LENGTH_OVERHEAD + crc_buffer.len()) as i32;
+        let crc = crc32c::crc32c(crc_buffer.as_slice()) as i32;
+        let mut buffer = Vec::with_capacity(LOG_OVERHEAD + batch_length as usize);
+        self.base_offset.write_to_buffer(&mut buffer);
+        batch_length.write_to_buffer(&mut buffer);
+        self.partition_leader_epoch.write_to_buffer(&mut buffer);
+        self.magic.write_to_buffer(&mut buffer);
+        crc.write_to_buffer(&mut buffer);
+        crc_buffer.write_to_buffer(&mut buffer);
+        Ok(buffer)
     }
 }
 
 impl FromBytes for RecordBatch {
-    fn get_from_bytes(buffer: &mut Cursor<Vec<u8>>) -> Self {
+    fn get_from_bytes(buffer: &mut Cursor<Vec<u8>>, _version: i16) -> Result<Self> {
+        let start = buffer.position() as usize;
+        let magic = *buffer.get_ref().get(start + MAGIC_OFFSET).ok_or(KafkaError::Truncated)? as i8;
+        if magic < RECORD_BATCH_MAGIC {
+            return read_legacy_message(buffer);
+        }
         let mut batch = RecordBatch {
-            base_offset: i64::read_from_buffer(buffer),
-            batch_length: i32::read_from_buffer(buffer),
-            partition_leader_epoch: i32::read_from_buffer(buffer),
-            magic: i8::read_from_buffer(buffer),
-            crc: i32::read_from_buffer(buffer),
-            attributes: i16::read_from_buffer(buffer),
-            last_offset_delta: i32::read_from_buffer(buffer),
-            first_timestamp: i64::read_from_buffer(buffer),
-            max_timestamp: i64::read_from_buffer(buffer),
-            producer_id: i64::read_from_buffer(buffer),
-            producer_epoch: i16::read_from_buffer(buffer),
-            base_sequence: i32::read_from_buffer(buffer),
+            base_offset: i64::read_from_buffer(buffer)?,
+            batch_length: i32::read_from_buffer(buffer)?,
+            partition_leader_epoch: i32::read_from_buffer(buffer)?,
+            magic: i8::read_from_buffer(buffer)?,
+            crc: i32::read_from_buffer(buffer)?,
+            attributes: 0,
+            last_offset_delta: 0,
+            first_timestamp: 0,
+            max_timestamp: 0,
+            producer_id: 0,
+            producer_epoch: 0,
+            base_sequence: 0,
             records: vec![],
         };
-        let _records_length = i32::read_from_buffer(buffer);
-
-        let length = VarInt::read_from_buffer(buffer);
-        let attributes = i8::read_from_buffer(buffer);
-        let timestamp_delta = VarInt::read_from_buffer(buffer);
-        let offset_delta = VarInt::read_from_buffer(buffer);
-        let key = KafkaValue::read_from_buffer(buffer);
-        let key_length = VarInt(key.0.len() as i32);
-        let value = KafkaValue::read_from_buffer(buffer);
-        let value_length = VarInt(value.0.len() as i32);
-        let mut record = Record {
-            length: VarInt(length.0),
-            attributes,
-            timestamp_delta: VarInt(timestamp_delta.0),
-            offset_delta: VarInt(offset_delta.0),
-            key_length,
-            key,
-            value_length,
-            value,
-            headers: vec![],
-        };
+        if batch.magic != RECORD_BATCH_MAGIC {
+            return Err(KafkaError::Codec(format!("unsupported record batch magic {}", batch.magic)));
+        }
+        if batch.batch_length < BATCH_LENGTH_OVERHEAD as i32 {
+            return Err(KafkaError::Codec(format!("invalid record batch length {}", batch.batch_length)));
+        }
+        let crc_start = buffer.position() as usize;
+        let end = start + LOG_OVERHEAD + batch.batch_length as usize;
+        if end > buffer.get_ref().len() {
+            return Err(KafkaError::Truncated);
+        }
+        let crc = crc32c::crc32c(&buffer.get_ref()[crc_start..end]);
+        if crc != batch.crc as u32 {
+            return Err(KafkaError::Codec(format!(
+                "record batch at offset {} is corrupt, expected crc {} but computed {}",
+                batch.base_offset, batch.crc as u32, crc)));
+        }
 
-        let header_len = VarInt::read_from_buffer(buffer);
-        if header_len.0 != 0 {
-            let header_key = KafkaValue::read_from_buffer(buffer);
-            let header_key_length = VarInt(header_key.0.len() as i32);
-            let header_value = KafkaValue::read_from_buffer(buffer);
-            let header_value_length = VarInt(header_value.0.len() as i32);
-            let header = RecordHeader {
-                header_key_length,
-                header_key,
-                header_value_length,
-                header_value,
-            };
-            record.headers.push(header);
+        batch.attributes = i16::read_from_buffer(buffer)?;
+        batch.last_offset_delta = i32::read_from_buffer(buffer)?;
+        batch.first_timestamp = i64::read_from_buffer(buffer)?;
+        batch.max_timestamp = i64::read_from_buffer(buffer)?;
+        batch.producer_id = i64::read_from_buffer(buffer)?;
+        batch.producer_epoch = i16::read_from_buffer(buffer)?;
+        batch.base_sequence = i32::read_from_buffer(buffer)?;
+        let records_len = i32::read_from_buffer(buffer)?;
+        let compression = batch.compression()?;
+        if compression == Compression::None {
+            batch.records = Self::read_records(buffer, records_len, &batch)?;
+            if buffer.position() as usize > end {
+                return Err(KafkaError::Codec(format!(
+                    "records overrun the record batch at offset {}", batch.base_offset)));
+            }
+        } else {
+            let position = buffer.position() as usize;
+            let decompressed = compression.decompress(&buffer.get_ref()[position..end])?;
+            let mut records_buffer = Cursor::new(decompressed);
+            batch.records = Self::read_records(&mut records_buffer, records_len, &batch)?;
         }
-        batch.records.push(record);
-        println!("{:#?}", batch);
-        batch
+        buffer.set_position(end as u64);
+        Ok(batch)
+    }
+}
+
+const LEGACY_TIMESTAMP_TYPE_MASK: i8 = 0x08;
+/// Bytes of the crc, magic, attributes and the lengths of the key and value of a v0 message.
+const LEGACY_MESSAGE_MIN_SIZE: i32 = 4 + 1 + 1 + 4 + 4;
+
+/// MessageSet (Version: 0-1) => [offset message_size message]
+///   offset => INT64
+///   message_size => INT32
+///   message => crc magic attributes timestamp key value
+///     crc => INT32
+///     magic => INT8
+///     attributes => INT8
+///         bit 0~2: compression codec, as in record batches
+///         bit 3: timestampType (version 1+)
+///     timestamp => INT64 (version 1+)
+///     key => NULLABLE_BYTES
+///     value => NULLABLE_BYTES
+///
+/// The crc is a CRC-32 of every byte from the magic to the end of the message. A compressed
+/// message wraps a message set in its value, whose offsets are absolute in version 0 and
+/// relative to the last inner message, which carries the offset of the wrapper, in version 1.
+///
+/// Each message is returned as a batch of the records it holds, so that callers see the
+/// same records whatever format the log was written in.
+fn read_legacy_message(buffer: &mut Cursor<Vec<u8>>) -> Result<RecordBatch> {
+    let start = buffer.position() as usize;
+    let offset = i64::read_from_buffer(buffer)?;
+    let message_size = i32::read_from_buffer(buffer)?;
+    if message_size < LEGACY_MESSAGE_MIN_SIZE {
+        return Err(KafkaError::Codec(format!("invalid message size {}", message_size)));
+    }
+    let end = start + LOG_OVERHEAD + message_size as usize;
+    if end > buffer.get_ref().len() {
+        return Err(KafkaError::Truncated);
+    }
+    let crc = i32::read_from_buffer(buffer)?;
+    let computed_crc = crc32fast::hash(&buffer.get_ref()[buffer.position() as usize..end]);
+    if computed_crc != crc as u32 {
+        return Err(KafkaError::Codec(format!(
+            "message at offset {} is corrupt, expected crc {} but computed {}", offset, crc as u32, computed_crc)));
+    }
+    let magic = i8::read_from_buffer(buffer)?;
+    let attributes = i8::read_from_buffer(buffer)?;
+    let timestamp = if magic >= 1 { i64::read_from_buffer(buffer)? } else { -1 };
+    let key = KafkaNullableBytes::read_from_buffer(buffer)?.0;
+    let value = KafkaNullableBytes::read_from_buffer(buffer)?.0;
+    if buffer.position() as usize > end {
+        return Err(KafkaError::Codec(format!("message at offset {} overruns its size", offset)));
     }
+    buffer.set_position(end as u64);
+
+    let compression = Compression::from_attributes(attributes as i16)?;
+    let records = if compression == Compression::None {
+        vec![Record { attributes: 0, offset, timestamp, key, value, headers: vec![] }]
+    } else {
+        let wrapped = compression.decompress(value.unwrap_or_default().as_slice())?;
+        let wrapped_len = wrapped.len();
+        let mut wrapped_buffer = Cursor::new(wrapped);
+        let mut records: Vec<Record> = RecordBatch::read_batches(&mut wrapped_buffer, wrapped_len, 0)?
+            .into_iter()
+            .flat_map(|batch| batch.records)
+            .collect();
+        if magic >= 1 {
+            let last_relative_offset = records.last().map_or(0, |record| record.offset);
+            let log_append_time = attributes & LEGACY_TIMESTAMP_TYPE_MASK != 0;
+            for record in records.iter_mut() {
+                record.offset += offset - last_relative_offset;
+                if log_append_time {
+                    record.timestamp = timestamp;
+                }
+            }
+        }
+        records
+    };
+
+    let base_offset = records.first().map_or(offset, |record| record.offset);
+    Ok(RecordBatch {
+        base_offset,
+        batch_length: message_size,
+        partition_leader_epoch: -1,
+        magic,
+        crc,
+        attributes: attributes as i16,
+        last_offset_delta: records.last().map_or(0, |record| (record.offset - base_offset) as i32),
+        first_timestamp: records.first().map_or(timestamp, |record| record.timestamp),
+        max_timestamp: records.iter().map(|record| record.timestamp).max().unwrap_or(timestamp),
+        producer_id: -1,
+        producer_epoch: -1,
+        base_sequence: -1,
+        records,
+    })
 }
 
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A batch as a broker returns it in a fetch: offsets 42 and 43 in leader epoch 3, the
+    /// second record with a key and a header, laid out by hand from the protocol guide.
+    const FETCHED_BATCH: [u8; 84] = [
+        0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x2a, 0x00, 0x00, 0x00, 0x48, 0x00, 0x00, 0x00, 0x03,
+        0x02, 0xb2, 0x9e, 0xed, 0x2d, 0x00, 0x00, 0x00, 0x00, 0x00, 0x01, 0x00, 0x00, 0x01, 0x8b, 0xcf,
+        0xe5, 0x68, 0x00, 0x00, 0x00, 0x01, 0x8b, 0xcf, 0xe5, 0x68, 0x01, 0xff, 0xff, 0xff, 0xff, 0xff,
+        0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0x00, 0x00, 0x00, 0x02, 0x10, 0x00, 0x00,
+        0x00, 0x01, 0x04, 0x76, 0x30, 0x00, 0x1a, 0x00, 0x02, 0x02, 0x02, 0x6b, 0x04, 0x76, 0x31, 0x02,
+        0x02, 0x68, 0x02, 0x78,
+    ];
+
+    /// A v0 message at offset 7 with key "k" and value "v0".
+    const V0_MESSAGE: [u8; 29] = [
+        0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x07, 0x00, 0x00, 0x00, 0x11, 0x16, 0x57, 0x64, 0xb1,
+        0x00, 0x00, 0x00, 0x00, 0x00, 0x01, 0x6b, 0x00, 0x00, 0x00, 0x02, 0x76, 0x30,
+    ];
+
+    /// A v1 message at offset 8 with a null key, value "v1" and a create time.
+    const V1_MESSAGE: [u8; 36] = [
+        0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x08, 0x00, 0x00, 0x00, 0x18, 0x6a, 0x55, 0x00, 0xfb,
+        0x01, 0x00, 0x00, 0x00, 0x01, 0x8b, 0xcf, 0xe5, 0x68, 0x00, 0xff, 0xff, 0xff, 0xff, 0x00, 0x00,
+        0x00, 0x02, 0x76, 0x31,
+    ];
+
+    /// Lays out a v0 or v1 message, as older brokers return them.
+    #[cfg(feature = "gzip")]
+    fn legacy_message(offset: i64, magic: i8, attributes: i8, timestamp: i64, value: &[u8]) -> Vec<u8> {
+        let mut message = Vec::new();
+        magic.write_to_buffer(&mut message);
+        attributes.write_to_buffer(&mut message);
+        if magic >= 1 {
+            timestamp.write_to_buffer(&mut message);
+        }
+        KafkaNullableBytes(None).write_to_buffer(&mut message);
+        KafkaNullableBytes(Some(value.to_vec())).write_to_buffer(&mut message);
+        let mut buffer = Vec::new();
+        offset.write_to_buffer(&mut buffer);
+        ((message.len() + 4) as i32).write_to_buffer(&mut buffer);
+        (crc32fast::hash(&message) as i32).write_to_buffer(&mut buffer);
+        buffer.extend(message);
+        buffer
+    }
+
+    /// A v1 gzip message at offset 12 wrapping three messages of relative offsets 0 to 2.
+    #[cfg(feature = "gzip")]
+    fn gzip_wrapper(attributes: i8, timestamp: i64) -> Vec<u8> {
+        let inner: Vec<u8> = (0..3)
+            .flat_map(|i| legacy_message(i, 1, 0, 1_700_000_000_000 + i, format!("v{}", i).as_bytes()))
+            .collect();
+        let compressed = Compression::Gzip.compress(&inner).unwrap();
+        legacy_message(12, 1, attributes | Compression::Gzip as i8, timestamp, &compressed)
+    }
+
+    fn decode(bytes: Vec<u8>) -> Result<RecordBatch> {
+        RecordBatch::get_from_bytes(&mut Cursor::new(bytes), 0)
+    }
+
+    fn assert_same_records(decoded: &[Record], expected: &[Record]) {
+        assert_eq!(decoded.len(), expected.len());
+        for (decoded, expected) in decoded.iter().zip(expected.iter()) {
+            assert_eq!(decoded.offset, expected.offset);
+            assert_eq!(decoded.timestamp, expected.timestamp);
+            assert_eq!(decoded.key, expected.key);
+            assert_eq!(decoded.value, expected.value);
+            assert_eq!(decoded.headers.len(), expected.headers.len());
+            for (decoded, expected) in decoded.headers.iter().zip(expected.headers.iter()) {
+                assert_eq!(decoded.key, expected.key);
+                assert_eq!(decoded.value, expected.value);
+            }
+        }
+    }
+
+    fn records() -> Vec<Record> {
+        let mut first = Record::new(None, Some(b"v0".to_vec()));
+        first.timestamp = 1_700_000_000_000;
+        let mut second = Record::new(Some(b"k".to_vec()), Some(b"v1".to_vec()));
+        second.timestamp = 1_700_000_000_001;
+        second.headers.push(RecordHeader::new("h".to_string(), Some(b"x".to_vec())));
+        vec![first, second]
+    }
+
+    #[test]
+    fn decodes_fetched_batch() {
+        let batch = decode(FETCHED_BATCH.to_vec()).unwrap();
+        assert_eq!(batch.base_offset, 42);
+        assert_eq!(batch.partition_leader_epoch, 3);
+        assert_eq!(batch.last_offset(), 43);
+        assert_eq!(batch.compression().unwrap(), Compression::None);
+        assert_eq!(batch.timestamp_type(), TimestampType::CreateTime);
+        assert!(!batch.is_transactional());
+        let mut expected = records();
+        expected[0].offset = 42;
+        expected[1].offset = 43;
+        assert_same_records(&batch.records, &expected);
+    }
+
+    #[test]
+    fn decodes_v0_message() {
+        let batch = decode(V0_MESSAGE.to_vec()).unwrap();
+        assert_eq!(batch.magic, 0);
+        assert_eq!(batch.base_offset, 7);
+        assert_eq!(batch.timestamp_type(), TimestampType::NoTimestampType);
+        assert_eq!(batch.records.len(), 1);
+        assert_eq!(batch.records[0].offset, 7);
+        assert_eq!(batch.records[0].timestamp, -1);
+        assert_eq!(batch.records[0].key, Some(b"k".to_vec()));
+        assert_eq!(batch.records[0].value, Some(b"v0".to_vec()));
+    }
+
+    #[test]
+    fn decodes_v1_message() {
+        let batch = decode(V1_MESSAGE.to_vec()).unwrap();
+        assert_eq!(batch.magic, 1);
+        assert_eq!(batch.base_offset, 8);
+        assert_eq!(batch.timestamp_type(), TimestampType::CreateTime);
+        assert_eq!(batch.records.len(), 1);
+        assert_eq!(batch.records[0].offset, 8);
+        assert_eq!(batch.records[0].timestamp, 1_700_000_000_000);
+        assert_eq!(batch.records[0].key, None);
+        assert_eq!(batch.records[0].value, Some(b"v1".to_vec()));
+    }
+
+    #[cfg(feature = "gzip")]
+    #[test]
+    fn decodes_compressed_v1_message_against_wrapper_offset() {
+        let batch = decode(gzip_wrapper(0, 1_700_000_000_002)).unwrap();
+        assert_eq!(batch.base_offset, 10);
+        assert_eq!(batch.last_offset(), 12);
+        let offsets: Vec<i64> = batch.records.iter().map(|record| record.offset).collect();
+        assert_eq!(offsets, vec![10, 11, 12]);
+        let timestamps: Vec<i64> = batch.records.iter().map(|record| record.timestamp).collect();
+        assert_eq!(timestamps, vec![1_700_000_000_000, 1_700_000_000_001, 1_700_000_000_002]);
+        assert_eq!(batch.records[2].value, Some(b"v2".to_vec()));
+    }
+
+    #[cfg(feature = "gzip")]
+    #[test]
+    fn log_append_time_wrapper_overrides_inner_timestamps() {
+        let batch = decode(gzip_wrapper(LEGACY_TIMESTAMP_TYPE_MASK, 1_700_000_009_999)).unwrap();
+        assert_eq!(batch.timestamp_type(), TimestampType::LogAppendTime);
+        assert!(batch.records.iter().all(|record| record.timestamp == 1_700_000_009_999));
+        assert_eq!(batch.max_timestamp, 1_700_000_009_999);
+    }
+
+    #[test]
+    fn rejects_corrupted_legacy_crc() {
+        let mut bytes = V1_MESSAGE.to_vec();
+        let last = bytes.len() - 1;
+        bytes[last] ^= 0x01;
+        assert!(matches!(decode(bytes), Err(KafkaError::Codec(_))));
+    }
+
+    #[test]
+    fn read_batches_mixes_message_formats() {
+        let mut bytes = V1_MESSAGE.to_vec();
+        bytes.extend_from_slice(&FETCHED_BATCH);
+        let length = bytes.len();
+        let batches = RecordBatch::read_batches(&mut Cursor::new(bytes), length, 0).unwrap();
+        let batches: Vec<(i8, i64, usize)> = batches.iter()
+            .map(|batch| (batch.magic, batch.base_offset, batch.records.len()))
+            .collect();
+        assert_eq!(batches, vec![(1, 8, 1), (2, 42, 2)]);
+    }
+
+    #[test]
+    fn encodes_like_the_broker() {
+        let mut batch = RecordBatch::new(records());
+        batch.base_offset = 42;
+        batch.partition_leader_epoch = 3;
+        for record in batch.records.iter_mut() {
+            record.offset += 42;
+        }
+        assert_eq!(batch.encode().unwrap(), FETCHED_BATCH.to_vec());
+    }
+
+    #[test]
+    fn round_trips_every_enabled_codec() {
+        let codecs = [Compression::None, Compression::Gzip, Compression::Snappy, Compression::Lz4, Compression::Zstd];
+        for &compression in codecs.iter().filter(|compression| compression.is_enabled()) {
+            let mut batch = RecordBatch::new(records());
+            batch.set_compression(compression);
+            batch.set_transactional(true);
+            batch.producer_id = 7;
+            batch.producer_epoch = 1;
+            batch.base_sequence = 10;
+            let decoded = decode(batch.encode().unwrap()).unwrap();
+            assert_eq!(decoded.compression().unwrap(), compression);
+            assert!(decoded.is_transactional());
+            assert_eq!((decoded.producer_id, decoded.producer_epoch, decoded.base_sequence), (7, 1, 10));
+            assert_eq!(decoded.last_offset_delta, 1);
+            assert_same_records(&decoded.records, &batch.records);
+        }
+    }
+
+    #[test]
+    fn encoding_fails_with_disabled_codec() {
+        for &compression in [Compression::Gzip, Compression::Snappy, Compression::Lz4, Compression::Zstd].iter() {
+            let mut batch = RecordBatch::new(records());
+            batch.set_compression(compression);
+            assert_eq!(batch.encode().is_ok(), compression.is_enabled(), "{}", compression.name());
+        }
+    }
 
+    #[test]
+    fn rejects_corrupted_crc() {
+        let mut bytes = FETCHED_BATCH.to_vec();
+        let last = bytes.len() - 1;
+        bytes[last] ^= 0x01;
+        assert!(matches!(decode(bytes), Err(KafkaError::Codec(_))));
+    }
+
+    #[test]
+    fn read_batches_drops_truncated_last_batch() {
+        let mut bytes = FETCHED_BATCH.to_vec();
+        bytes.extend_from_slice(&FETCHED_BATCH[..FETCHED_BATCH.len() - 10]);
+        let length = bytes.len();
+        let mut buffer = Cursor::new(bytes);
+        let batches = RecordBatch::read_batches(&mut buffer, length, 0).unwrap();
+        assert_eq!(batches.len(), 1);
+        assert_eq!(batches[0].base_offset, 42);
+        assert_eq!(buffer.position() as usize, length);
+    }
+}
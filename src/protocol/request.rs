@@ -1,7 +1,33 @@
+use crate::protocol::api_keys::ApiKeys;
 use crate::protocol::header::RequestHeader;
+use crate::protocol::response::FromBytes;
 
 pub trait ToBytes {
-    fn get_in_bytes(&self) -> Vec<u8>;
+    /// Encodes the message in the layout of the given api version.
+    fn get_in_bytes(&self, version: i16) -> Vec<u8>;
+}
+
+/// A request body that can be sent to a broker, along with the versions of the api
+/// this client is able to encode it in and decode its response from.
+pub trait KafkaRequest: ToBytes {
+    type Response: FromBytes;
+    const API_KEY: ApiKeys;
+    const MIN_VERSION: i16;
+    const MAX_VERSION: i16;
+    /// First version encoded with the compact types and tagged fields of KIP-482.
+    const FIRST_FLEXIBLE_VERSION: i16;
+
+    fn is_flexible(version: i16) -> bool {
+        version >= Self::FIRST_FLEXIBLE_VERSION
+    }
+
+    fn request_header_version(version: i16) -> i16 {
+        if Self::is_flexible(version) { 2 } else { 1 }
+    }
+
+    fn response_header_version(version: i16) -> i16 {
+        if Self::is_flexible(version) { 1 } else { 0 }
+    }
 }
 
 #[derive(Debug)]
@@ -12,10 +38,10 @@ pub struct Request<T: ToBytes> {
     pub buffer: Vec<u8>,
 }
 
-impl<T: ToBytes> Request<T> {
+impl<T: KafkaRequest> Request<T> {
     pub fn new(header: RequestHeader, body: T) -> Self {
-        let mut buffer = header.get_in_bytes();
-        buffer.extend(body.get_in_bytes());
+        let mut buffer = header.get_in_bytes(T::request_header_version(header.api_version));
+        buffer.extend(body.get_in_bytes(header.api_version));
         let size = Self::add_size(&mut buffer);
         Self {
             size,
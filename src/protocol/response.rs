@@ -1,12 +1,12 @@
 use crate::protocol::header::ResponseHeader;
 use std::io::{Cursor, Read};
-use std::net::TcpStream;
 use byteorder::{ReadBytesExt, BE};
-use std::process::exit;
-use std::any::{Any, type_name};
 
-pub trait FromBytes {
-    fn get_from_bytes(buffer: &mut Cursor<Vec<u8>>) -> Self;
+use crate::error::{KafkaError, Result};
+
+pub trait FromBytes: Sized {
+    /// Decodes the message from the layout of the given api version.
+    fn get_from_bytes(buffer: &mut Cursor<Vec<u8>>, version: i16) -> Result<Self>;
 }
 
 #[derive(Debug)]
@@ -17,33 +17,52 @@ pub struct Response<T: FromBytes> {
 }
 
 impl<T: FromBytes> Response<T> {
-    pub fn build(stream: &mut TcpStream) -> Self {
-        let mut buffer = vec![0 as u8; 4]; // Buffer for the size of the message
-        let message_size: i32;
-        buffer = match stream.read_exact(&mut buffer) {
-            Ok(_) => {
-                message_size = buffer.as_slice().read_i32::<BE>().unwrap();
-                buffer = vec![0 as u8; message_size as usize]; // Buffer for the message itself
-                stream.read_exact(&mut buffer).unwrap();
-                buffer
-            }
-            Err(e) => {
-                println!("{:#?}", type_name::<T>());
-                eprintln!("Message could not be retrieved. \nError: {}", e);
-                exit(1)
-            }
-        };
-
-        let mut buffer = Cursor::new(buffer);
-        let header = ResponseHeader::get_from_bytes(&mut buffer);
-        let body = T::get_from_bytes(&mut buffer);
-        Self {
+    pub fn build<R: Read>(stream: &mut R, header_version: i16, version: i16) -> Result<Self> {
+        Self::from_frame(read_frame(stream)?, header_version, version)
+    }
+
+    /// Decodes a frame returned by `read_frame`, without its size prefix, answering a request
+    /// sent in the given api version, with a response header in the given header version.
+    pub fn from_frame(frame: Vec<u8>, header_version: i16, version: i16) -> Result<Self> {
+        let message_size = frame.len() as i32;
+        let mut buffer = Cursor::new(frame);
+        let header = ResponseHeader::get_from_bytes(&mut buffer, header_version)?;
+        let body = T::get_from_bytes(&mut buffer, version)?;
+        Ok(Self {
             message_size,
             header,
             body,
-        }
+        })
     }
 }
 
+/// Reads one size-prefixed frame from the stream and returns it without the size.
+pub fn read_frame<R: Read>(stream: &mut R) -> Result<Vec<u8>> {
+    let mut buffer = vec![0_u8; 4]; // Buffer for the size of the message
+    stream.read_exact(&mut buffer)?;
+    let message_size = buffer.as_slice().read_i32::<BE>()?;
+    if message_size < 4 {
+        return Err(KafkaError::Codec(format!("invalid message size {}", message_size)));
+    }
+    let mut buffer = vec![0_u8; message_size as usize]; // Buffer for the message itself
+    stream.read_exact(&mut buffer)?;
+    Ok(buffer)
+}
 
+/// Returns the correlation id of a frame returned by `read_frame`.
+pub fn frame_correlation_id(frame: &[u8]) -> Result<i32> {
+    let mut header = frame.get(0..4).ok_or(KafkaError::Truncated)?;
+    Ok(header.read_i32::<BE>()?)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
 
+    #[test]
+    fn stream_closed_mid_frame_is_not_a_truncated_value() {
+        let mut stream: &[u8] = &[0, 0, 0, 8, 0, 0, 0, 1];
+        assert!(matches!(read_frame(&mut stream), Err(KafkaError::Io(_))));
+        assert!(matches!(frame_correlation_id(&[0, 0]), Err(KafkaError::Truncated)));
+    }
+}
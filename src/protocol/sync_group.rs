@@ -0,0 +1,134 @@
+use std::io::Cursor;
+
+use crate::error::Result;
+use crate::protocol::api_keys::ApiKeys;
+use crate::protocol::kafka_error_codes::check_errors;
+use crate::protocol::primitives::{skip_tagged_fields, write_array_length, write_tagged_fields,
+                                  FlexiblePrimitive, KafkaBytes, KafkaNullableString, KafkaPrimitive, KafkaString};
+use crate::protocol::request::{KafkaRequest, ToBytes};
+use crate::protocol::response::FromBytes;
+
+
+/// SyncGroup Request (Version: 0-5) => group_id generation_id member_id group_instance_id protocol_type protocol_name [assignments] TAG_BUFFER
+///   group_id => STRING
+///   generation_id => INT32
+///   member_id => STRING
+///   group_instance_id => NULLABLE_STRING (version 3+)
+///   protocol_type => NULLABLE_STRING (version 5+)
+///   protocol_name => NULLABLE_STRING (version 5+)
+///   assignments => member_id assignment TAG_BUFFER
+///     member_id => STRING
+///     assignment => BYTES
+#[derive(Debug)]
+pub struct SyncGroupRequest {
+    group_id: KafkaString,
+    generation_id: i32,
+    member_id: KafkaString,
+    group_instance_id: KafkaNullableString,
+    protocol_type: KafkaNullableString,
+    protocol_name: KafkaNullableString,
+    assignments: Vec<SyncGroupAssignment>,
+}
+
+#[derive(Debug)]
+struct SyncGroupAssignment {
+    member_id: KafkaString,
+    assignment: KafkaBytes,
+}
+
+impl SyncGroupRequest {
+    pub fn new(group_id: String, generation_id: i32, member_id: String, group_instance_id: Option<String>,
+               protocol_name: String) -> Self {
+        Self {
+            group_id: KafkaString(group_id),
+            generation_id,
+            member_id: KafkaString(member_id),
+            group_instance_id: KafkaNullableString(group_instance_id),
+            protocol_type: KafkaNullableString(Some("consumer".to_string())),
+            protocol_name: KafkaNullableString(Some(protocol_name)),
+            assignments: vec![],
+        }
+    }
+
+    /// Sends the assignment of a member of the group, encoded as a `ConsumerProtocolAssignment`,
+    /// which only the leader does.
+    pub fn add_assignment(&mut self, member_id: &str, assignment: Vec<u8>) {
+        self.assignments.push(SyncGroupAssignment {
+            member_id: KafkaString(member_id.to_string()),
+            assignment: KafkaBytes(assignment),
+        });
+    }
+}
+
+impl ToBytes for SyncGroupRequest {
+    fn get_in_bytes(&self, version: i16) -> Vec<u8> {
+        let flexible = Self::is_flexible(version);
+        let mut buffer = Vec::new();
+        self.group_id.write_flexible(&mut buffer, flexible);
+        self.generation_id.write_to_buffer(&mut buffer);
+        self.member_id.write_flexible(&mut buffer, flexible);
+        if version >= 3 {
+            self.group_instance_id.write_flexible(&mut buffer, flexible);
+        }
+        if version >= 5 {
+            self.protocol_type.write_flexible(&mut buffer, flexible);
+            self.protocol_name.write_flexible(&mut buffer, flexible);
+        }
+        write_array_length(&mut buffer, self.assignments.len(), flexible);
+        for assignment in self.assignments.iter() {
+            assignment.member_id.write_flexible(&mut buffer, flexible);
+            assignment.assignment.write_flexible(&mut buffer, flexible);
+            write_tagged_fields(&mut buffer, flexible);
+        }
+        write_tagged_fields(&mut buffer, flexible);
+        buffer
+    }
+}
+
+impl KafkaRequest for SyncGroupRequest {
+    type Response = SyncGroupResponse;
+    const API_KEY: ApiKeys = ApiKeys::SyncGroup;
+    const MIN_VERSION: i16 = 0;
+    const MAX_VERSION: i16 = 5;
+    const FIRST_FLEXIBLE_VERSION: i16 = 4;
+}
+
+/// SyncGroup Response (Version: 0-5) => throttle_time_ms error_code protocol_type protocol_name assignment TAG_BUFFER
+///   throttle_time_ms => INT32 (version 1+)
+///   error_code => INT16
+///   protocol_type => NULLABLE_STRING (version 5+)
+///   protocol_name => NULLABLE_STRING (version 5+)
+///   assignment => BYTES
+#[derive(Debug)]
+pub struct SyncGroupResponse {
+    pub throttle_time_ms: i32,
+    pub error_code: i16,
+    pub protocol_type: KafkaNullableString,
+    pub protocol_name: KafkaNullableString,
+    pub assignment: Vec<u8>,
+}
+
+impl FromBytes for SyncGroupResponse {
+    fn get_from_bytes(buffer: &mut Cursor<Vec<u8>>, version: i16) -> Result<Self> {
+        let flexible = SyncGroupRequest::is_flexible(version);
+        let mut response = Self {
+            throttle_time_ms: 0,
+            error_code: 0,
+            protocol_type: KafkaNullableString(None),
+            protocol_name: KafkaNullableString(None),
+            assignment: vec![],
+        };
+        if version >= 1 {
+            response.throttle_time_ms = i32::read_from_buffer(buffer)?;
+        }
+        response.error_code = i16::read_from_buffer(buffer)?;
+        if version >= 5 {
+            response.protocol_type = KafkaNullableString::read_flexible(buffer, flexible)?;
+            response.protocol_name = KafkaNullableString::read_flexible(buffer, flexible)?;
+        }
+        response.assignment = KafkaBytes::read_flexible(buffer, flexible)?.0;
+        skip_tagged_fields(buffer, flexible)?;
+        check_errors(response.error_code)?;
+        Ok(response)
+    }
+}
@@ -0,0 +1,178 @@
+use std::io::Cursor;
+
+use crate::error::Result;
+use crate::protocol::api_keys::ApiKeys;
+use crate::protocol::primitives::{read_array_length, skip_tagged_fields, write_array_length, write_tagged_fields,
+                                  FlexiblePrimitive, KafkaPrimitive, KafkaString, KafkaNullableString};
+use crate::protocol::request::{KafkaRequest, ToBytes};
+use crate::protocol::response::FromBytes;
+
+
+/// TxnOffsetCommit Request (Version: 0-3) => transactional_id group_id producer_id producer_epoch generation_id member_id group_instance_id [topics] TAG_BUFFER
+///   transactional_id => STRING
+///   group_id => STRING
+///   producer_id => INT64
+///   producer_epoch => INT16
+///   generation_id => INT32 (version 3+)
+///   member_id => STRING (version 3+)
+///   group_instance_id => NULLABLE_STRING (version 3+)
+///   topics => name [partitions] TAG_BUFFER
+///     name => STRING
+///     partitions => partition_index committed_offset committed_leader_epoch committed_metadata TAG_BUFFER
+///       partition_index => INT32
+///       committed_offset => INT64
+///       committed_leader_epoch => INT32 (version 2+)
+///       committed_metadata => NULLABLE_STRING
+#[derive(Debug)]
+pub struct TxnOffsetCommitRequest {
+    transactional_id: KafkaString,
+    group_id: KafkaString,
+    producer_id: i64,
+    producer_epoch: i16,
+    generation_id: i32,
+    member_id: KafkaString,
+    group_instance_id: KafkaNullableString,
+    topics: Vec<TxnOffsetCommitTopic>,
+}
+
+#[derive(Debug)]
+struct TxnOffsetCommitTopic {
+    name: KafkaString,
+    partitions: Vec<TxnOffsetCommitPartition>,
+}
+
+#[derive(Debug)]
+struct TxnOffsetCommitPartition {
+    partition_index: i32,
+    committed_offset: i64,
+    committed_leader_epoch: i32,
+    committed_metadata: KafkaNullableString,
+}
+
+impl TxnOffsetCommitRequest {
+    /// Commits offsets for a group without checking the generation and member id of the
+    /// consumer, which brokers only do from version 3 on.
+    pub fn new(transactional_id: String, group_id: String, producer_id: i64, producer_epoch: i16) -> Self {
+        Self {
+            transactional_id: KafkaString(transactional_id),
+            group_id: KafkaString(group_id),
+            producer_id,
+            producer_epoch,
+            generation_id: -1,
+            member_id: KafkaString("".to_string()),
+            group_instance_id: KafkaNullableString(None),
+            topics: vec![],
+        }
+    }
+
+    pub fn add_offset(&mut self, topic: &str, partition: i32, offset: i64) {
+        let partition = TxnOffsetCommitPartition {
+            partition_index: partition,
+            committed_offset: offset,
+            committed_leader_epoch: -1,
+            committed_metadata: KafkaNullableString(Some("".to_string())),
+        };
+        match self.topics.iter_mut().find(|t| t.name.0 == topic) {
+            Some(t) => t.partitions.push(partition),
+            None => self.topics.push(TxnOffsetCommitTopic {
+                name: KafkaString(topic.to_string()),
+                partitions: vec![partition],
+            }),
+        }
+    }
+}
+
+impl ToBytes for TxnOffsetCommitRequest {
+    fn get_in_bytes(&self, version: i16) -> Vec<u8> {
+        let flexible = Self::is_flexible(version);
+        let mut buffer = Vec::new();
+        self.transactional_id.write_flexible(&mut buffer, flexible);
+        self.group_id.write_flexible(&mut buffer, flexible);
+        self.producer_id.write_to_buffer(&mut buffer);
+        self.producer_epoch.write_to_buffer(&mut buffer);
+        if version >= 3 {
+            self.generation_id.write_to_buffer(&mut buffer);
+            self.member_id.write_flexible(&mut buffer, flexible);
+            self.group_instance_id.write_flexible(&mut buffer, flexible);
+        }
+        write_array_length(&mut buffer, self.topics.len(), flexible);
+        for topic in self.topics.iter() {
+            topic.name.write_flexible(&mut buffer, flexible);
+            write_array_length(&mut buffer, topic.partitions.len(), flexible);
+            for partition in topic.partitions.iter() {
+                partition.partition_index.write_to_buffer(&mut buffer);
+                partition.committed_offset.write_to_buffer(&mut buffer);
+                if version >= 2 {
+                    partition.committed_leader_epoch.write_to_buffer(&mut buffer);
+                }
+                partition.committed_metadata.write_flexible(&mut buffer, flexible);
+                write_tagged_fields(&mut buffer, flexible);
+            }
+            write_tagged_fields(&mut buffer, flexible);
+        }
+        write_tagged_fields(&mut buffer, flexible);
+        buffer
+    }
+}
+
+impl KafkaRequest for TxnOffsetCommitRequest {
+    type Response = TxnOffsetCommitResponse;
+    const API_KEY: ApiKeys = ApiKeys::TxnOffsetCommit;
+    const MIN_VERSION: i16 = 0;
+    const MAX_VERSION: i16 = 3;
+    const FIRST_FLEXIBLE_VERSION: i16 = 3;
+}
+
+/// TxnOffsetCommit Response (Version: 0-3) => throttle_time_ms [topics] TAG_BUFFER
+///   throttle_time_ms => INT32
+///   topics => name [partitions] TAG_BUFFER
+///     name => STRING
+///     partitions => partition_index error_code TAG_BUFFER
+///       partition_index => INT32
+///       error_code => INT16
+#[derive(Debug)]
+pub struct TxnOffsetCommitResponse {
+    pub throttle_time_ms: i32,
+    pub topics: Vec<TxnOffsetCommitTopicResponse>,
+}
+
+#[derive(Debug)]
+pub struct TxnOffsetCommitTopicResponse {
+    pub name: KafkaString,
+    pub partitions: Vec<TxnOffsetCommitPartitionResponse>,
+}
+
+#[derive(Debug)]
+pub struct TxnOffsetCommitPartitionResponse {
+    pub partition_index: i32,
+    pub error_code: i16,
+}
+
+impl FromBytes for TxnOffsetCommitResponse {
+    fn get_from_bytes(buffer: &mut Cursor<Vec<u8>>, version: i16) -> Result<Self> {
+        let flexible = TxnOffsetCommitRequest::is_flexible(version);
+        let mut response = Self {
+            throttle_time_ms: i32::read_from_buffer(buffer)?,
+            topics: vec![],
+        };
+        let topics_length = read_array_length(buffer, flexible)?;
+        for _ in 0..topics_length {
+            let mut topic = TxnOffsetCommitTopicResponse {
+                name: KafkaString::read_flexible(buffer, flexible)?,
+                partitions: vec![],
+            };
+            let partitions_length = read_array_length(buffer, flexible)?;
+            for _ in 0..partitions_length {
+                topic.partitions.push(TxnOffsetCommitPartitionResponse {
+                    partition_index: i32::read_from_buffer(buffer)?,
+                    error_code: i16::read_from_buffer(buffer)?,
+                });
+                skip_tagged_fields(buffer, flexible)?;
+            }
+            skip_tagged_fields(buffer, flexible)?;
+            response.topics.push(topic);
+        }
+        skip_tagged_fields(buffer, flexible)?;
+        Ok(response)
+    }
+}
@@ -1 +1,2 @@
-pub mod buffer_utils;
\ No newline at end of file
+pub mod buffer_utils;
+pub mod time_utils;
\ No newline at end of file